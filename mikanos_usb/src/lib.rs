@@ -6,8 +6,9 @@
 
 pub(crate) mod cxx_support;
 
-type MouseObserverType = extern "C" fn(displacement_x: i8, displacement_y: i8);
-type KeyboardObserverType = extern "C" fn(modifier: u8, keycode: u8);
+type MouseObserverType = extern "C" fn(buttons: u8, displacement_x: i8, displacement_y: i8, wheel: i8);
+type KeyboardObserverType = extern "C" fn(modifier: u8, keycode: u8, press: bool);
+type HidReportObserverType = extern "C" fn(driver: usize, data: *const u8, len: i32);
 
 extern "C" {
     fn cxx_xhci_controller_new(xhc_mmio_base: u64) -> *mut xhci::Controller;
@@ -16,9 +17,16 @@ extern "C" {
     fn cxx_xhci_controller_configure_connected_ports(xhc: *mut xhci::Controller);
     fn cxx_xhci_controller_process_event(xhc: *mut xhci::Controller) -> i32;
     fn cxx_xhci_controller_has_event(xhc: *mut xhci::Controller) -> bool;
+    fn cxx_xhci_controller_max_slots(xhc: *mut xhci::Controller) -> usize;
+    fn cxx_xhci_controller_device_info(xhc: *mut xhci::Controller, slot_id: u8, info: *mut xhci::DeviceInfo) -> bool;
     fn cxx_xhci_hid_mouse_driver_set_default_observer(observer: MouseObserverType);
     fn cxx_xhci_hid_keyboard_driver_set_default_observer(observer: KeyboardObserverType);
+    fn cxx_xhci_hid_generic_driver_set_default_observers(
+        descriptor_observer: HidReportObserverType,
+        report_observer: HidReportObserverType,
+    );
     fn cxx_set_memory_pool(pool_ptr: u64, pool_size: usize);
+    fn SetLogLevel(level: LogLevel);
 }
 
 #[derive(Debug)]
@@ -30,6 +38,22 @@ pub mod xhci {
     // opaque type
     pub enum Controller {}
 
+    // rust_support.cpp の USBDeviceInfo と同じレイアウト
+    #[derive(Debug, Default, Clone, Copy)]
+    #[repr(C)]
+    pub struct DeviceInfo {
+        pub slot_id: u8,
+        // 設定が終わって class driver が動いているか
+        pub initialized: bool,
+        // false なら対応する interface がなく, interface_* は 0
+        pub has_class_driver: bool,
+        pub interface_class: u8,
+        pub interface_sub_class: u8,
+        pub interface_protocol: u8,
+        pub vendor_id: u16,
+        pub product_id: u16,
+    }
+
     impl Controller {
         pub unsafe fn new(xhc_mmio_base: u64) -> &'static mut Controller {
             unsafe { &mut *cxx_xhci_controller_new(xhc_mmio_base) }
@@ -56,13 +80,27 @@ pub mod xhci {
         pub fn has_event(&mut self) -> bool {
             unsafe { cxx_xhci_controller_has_event(self) }
         }
+
+        // slot id は 1 から max_slots まで
+        pub fn max_slots(&mut self) -> usize {
+            unsafe { cxx_xhci_controller_max_slots(self) }
+        }
+
+        pub fn device_info(&mut self, slot_id: u8) -> Option<DeviceInfo> {
+            let mut info = DeviceInfo::default();
+            if unsafe { cxx_xhci_controller_device_info(self, slot_id, &mut info) } {
+                Some(info)
+            } else {
+                None
+            }
+        }
     }
 }
 
 // opaque type
 pub enum HidMouseDriver {}
 
-pub type HidMouseObserver = extern "C" fn(displacement_x: i8, displacement_y: i8);
+pub type HidMouseObserver = extern "C" fn(buttons: u8, displacement_x: i8, displacement_y: i8, wheel: i8);
 
 impl HidMouseDriver {
     pub fn set_default_observer(observer: HidMouseObserver) {
@@ -73,7 +111,7 @@ impl HidMouseDriver {
 // opaque type
 pub enum HidKeyboardDriver {}
 
-pub type HidKeyboardObserver = extern "C" fn(modifier: u8, keycode: u8);
+pub type HidKeyboardObserver = extern "C" fn(modifier: u8, keycode: u8, press: bool);
 
 impl HidKeyboardDriver {
     pub fn set_default_observer(observer: HidKeyboardObserver) {
@@ -81,6 +119,34 @@ impl HidKeyboardDriver {
     }
 }
 
+// opaque type
+// boot protocol に対応しない HID device (tablet など). report の解釈は observer で行う
+pub enum HidGenericDriver {}
+
+// driver: device を区別する値, data, len: Report descriptor か report
+pub type HidReportObserver = extern "C" fn(driver: usize, data: *const u8, len: i32);
+
+impl HidGenericDriver {
+    pub fn set_default_observers(descriptor_observer: HidReportObserver, report_observer: HidReportObserver) {
+        unsafe { cxx_xhci_hid_generic_driver_set_default_observers(descriptor_observer, report_observer) }
+    }
+}
+
+// usb_driver/logger.hpp の LogLevel. C++ の Log() はこれを level として usb_log を呼ぶ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum LogLevel {
+    Error = 3,
+    Warn = 4,
+    Info = 6,
+    Debug = 7,
+}
+
+// level より優先度の低い (値の大きい) Log() は usb_log を呼ばずに捨てられる
+pub fn set_log_level(level: LogLevel) {
+    unsafe { SetLogLevel(level) }
+}
+
 pub unsafe fn set_memory_pool(pool_ptr: u64, pool_size: usize) {
     unsafe {
         cxx_set_memory_pool(pool_ptr, pool_size);
@@ -20,9 +20,10 @@ extern "C" void SetLogLevel(LogLevel level) {
 }
 
 int Log(LogLevel level, const char* format, ...) {
-  // if (level > log_level) {
-  //   return 0;
-  // }
+  // しきい値は Rust の logger の設定に合わせて SetLogLevel で変わる
+  if (level > log_level) {
+    return 0;
+  }
 
   va_list ap;
   int result;
@@ -1,5 +1,6 @@
 #include "logger.hpp"
 #include "usb/classdriver/keyboard.hpp"
+#include "usb/classdriver/generic.hpp"
 #include "usb/classdriver/mouse.hpp"
 #include "usb/memory.hpp"
 #include "usb/xhci/xhci.hpp"
@@ -41,19 +42,68 @@ extern "C" bool cxx_xhci_controller_has_event(usb::xhci::Controller *xhc) {
   return xhc->PrimaryEventRing()->HasFront();
 }
 
-extern "C" typedef void (*MouseObserverType)(int8_t displacement_x,
-                                             int8_t displacement_y);
+// Rust の mikanos_usb::xhci::DeviceInfo と同じレイアウト
+struct USBDeviceInfo {
+  uint8_t slot_id;
+  bool initialized;
+  bool has_class_driver;
+  uint8_t interface_class;
+  uint8_t interface_sub_class;
+  uint8_t interface_protocol;
+  uint16_t vendor_id;
+  uint16_t product_id;
+};
+
+extern "C" size_t cxx_xhci_controller_max_slots(usb::xhci::Controller *xhc) {
+  return xhc->DeviceManager()->MaxSlots();
+}
+
+// slot_id にデバイスがなければ false
+extern "C" bool cxx_xhci_controller_device_info(usb::xhci::Controller *xhc, uint8_t slot_id, USBDeviceInfo *info) {
+  auto dev = xhc->DeviceManager()->FindBySlot(slot_id);
+  if (dev == nullptr) {
+    return false;
+  }
+  *info = USBDeviceInfo{
+    slot_id,
+    dev->IsInitialized(),
+    dev->HasClassDriver(),
+    dev->InterfaceClass(),
+    dev->InterfaceSubClass(),
+    dev->InterfaceProtocol(),
+    dev->VendorID(),
+    dev->ProductID(),
+  };
+  return true;
+}
+
+extern "C" typedef void (*MouseObserverType)(uint8_t buttons,
+                                             int8_t displacement_x,
+                                             int8_t displacement_y,
+                                             int8_t wheel);
 
 extern "C" void cxx_xhci_hid_mouse_driver_set_default_observer(MouseObserverType observer) {
   usb::HIDMouseDriver::default_observer = observer;
 }
 
-extern "C" typedef void (*KeyboardObserverType)(uint8_t keycode);
+extern "C" typedef void (*KeyboardObserverType)(uint8_t modifier,
+                                                uint8_t keycode,
+                                                bool press);
 
 extern "C" void cxx_xhci_hid_keyboard_driver_set_default_observer(KeyboardObserverType observer) {
   usb::HIDKeyboardDriver::default_observer = observer;
 }
 
+extern "C" typedef void (*HIDReportObserverType)(uintptr_t driver,
+                                                  const uint8_t* data,
+                                                  int len);
+
+extern "C" void cxx_xhci_hid_generic_driver_set_default_observers(HIDReportObserverType descriptor_observer,
+                                                                  HIDReportObserverType report_observer) {
+  usb::HIDGenericDriver::default_descriptor_observer = descriptor_observer;
+  usb::HIDGenericDriver::default_report_observer = report_observer;
+}
+
 extern "C" void cxx_set_memory_pool(uintptr_t pool_ptr, size_t pool_size) {
   // usb::SetMemoryPool(pool_ptr, pool_size);
 }
@@ -0,0 +1,38 @@
+#include "usb/classdriver/generic.hpp"
+
+#include "usb/memory.hpp"
+#include "usb/device.hpp"
+#include "logger.hpp"
+
+namespace usb {
+  // report protocol の report は boot protocol より長いことがある
+  HIDGenericDriver::HIDGenericDriver(Device* dev, int interface_index)
+      : HIDBaseDriver{dev, interface_index, 64} {
+  }
+
+  Error HIDGenericDriver::OnDataReceived() {
+    if (default_report_observer) {
+      default_report_observer(reinterpret_cast<uintptr_t>(this), Buffer().data(), ReceivedLength());
+    }
+    return MAKE_ERROR(Error::kSuccess);
+  }
+
+  Error HIDGenericDriver::OnReportDescriptorReceived(const uint8_t* desc, int len) {
+    Log(kDebug, "HIDGenericDriver: report descriptor len = %d\n", len);
+    if (default_descriptor_observer) {
+      default_descriptor_observer(reinterpret_cast<uintptr_t>(this), desc, len);
+    }
+    return MAKE_ERROR(Error::kSuccess);
+  }
+
+  void* HIDGenericDriver::operator new(size_t size) {
+    return AllocMem(sizeof(HIDGenericDriver), 0, 0);
+  }
+
+  void HIDGenericDriver::operator delete(void* ptr) noexcept {
+    FreeMem(ptr);
+  }
+
+  std::function<HIDGenericDriver::ObserverType> HIDGenericDriver::default_descriptor_observer;
+  std::function<HIDGenericDriver::ObserverType> HIDGenericDriver::default_report_observer;
+}
@@ -0,0 +1,33 @@
+/**
+ * @file usb/classdriver/generic.hpp
+ *
+ * Generic HID class driver.
+ * boot protocol に対応しない HID デバイス (USB tablet など) 用．
+ * report の解釈は Report ディスクリプタを受け取った observer に任せる．
+ */
+
+#pragma once
+
+#include <functional>
+#include "usb/classdriver/hid.hpp"
+
+namespace usb {
+  class HIDGenericDriver : public HIDBaseDriver {
+   public:
+    HIDGenericDriver(Device* dev, int interface_index);
+
+    void* operator new(size_t size);
+    void operator delete(void* ptr) noexcept;
+
+    Error OnDataReceived() override;
+
+    // driver: どのデバイスからかを区別する値, data: Report ディスクリプタか report
+    using ObserverType = void (uintptr_t driver, const uint8_t* data, int len);
+    static std::function<ObserverType> default_descriptor_observer;
+    static std::function<ObserverType> default_report_observer;
+
+   protected:
+    bool UsesBootProtocol() const override { return false; }
+    Error OnReportDescriptorReceived(const uint8_t* desc, int len) override;
+  };
+}
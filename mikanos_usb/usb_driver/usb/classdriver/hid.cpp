@@ -25,6 +25,21 @@ namespace usb {
   }
 
   Error HIDBaseDriver::OnEndpointsConfigured() {
+    if (!UsesBootProtocol()) {
+      // report の形を知るために Report ディスクリプタを読む (読み終えたら buf_ は report の受信に使う)
+      SetupData setup_data{};
+      setup_data.request_type.bits.direction = request_type::kIn;
+      setup_data.request_type.bits.type = request_type::kStandard;
+      setup_data.request_type.bits.recipient = request_type::kInterface;
+      setup_data.request = request::kGetDescriptor;
+      setup_data.value = static_cast<uint16_t>(kReportDescriptorType) << 8;
+      setup_data.index = interface_index_;
+      setup_data.length = buf_.size();
+
+      initialize_phase_ = 1;
+      return ParentDevice()->ControlIn(kDefaultControlPipeID, setup_data, buf_.data(), buf_.size(), this);
+    }
+
     SetupData setup_data{};
     setup_data.request_type.bits.direction = request_type::kOut;
     setup_data.request_type.bits.type = request_type::kClass;
@@ -43,6 +58,11 @@ namespace usb {
     Log(kDebug, "HIDBaseDriver::OnControlCompleted: dev %08x, phase = %d, len = %d\n",
         this, initialize_phase_, len);
     if (initialize_phase_ == 1) {
+      if (setup_data.request == request::kGetDescriptor) {
+        if (auto err = OnReportDescriptorReceived(reinterpret_cast<const uint8_t*>(buf), len)) {
+          return err;
+        }
+      }
       initialize_phase_ = 2;
       return ParentDevice()->InterruptIn(ep_interrupt_in_, buf_.data(), in_packet_size_);
     }
@@ -52,6 +72,7 @@ namespace usb {
 
   Error HIDBaseDriver::OnInterruptCompleted(EndpointID ep_id, const void* buf, int len) {
     if (ep_id.IsIn()) {
+      received_length_ = len;
       OnDataReceived();
       std::copy_n(buf_.begin(), len, previous_buf_.begin());
       return ParentDevice()->InterruptIn(ep_interrupt_in_, buf_.data(), in_packet_size_);
@@ -21,8 +21,19 @@ namespace usb {
 
     virtual Error OnDataReceived() = 0;
     const static size_t kBufferSize = 1024;
+    const static uint8_t kReportDescriptorType = 34;
     const std::array<uint8_t, kBufferSize>& Buffer() const { return buf_; }
     const std::array<uint8_t, kBufferSize>& PreviousBuffer() const { return previous_buf_; }
+    int ReceivedLength() const { return received_length_; }
+
+   protected:
+    /** boot protocol に切り替えずに使う driver は false を返す．
+     * その場合 SetProtocol の代わりに Report ディスクリプタを読み，OnReportDescriptorReceived に渡す．
+     */
+    virtual bool UsesBootProtocol() const { return true; }
+    virtual Error OnReportDescriptorReceived(const uint8_t* desc, int len) {
+      return MAKE_ERROR(Error::kSuccess);
+    }
 
    private:
     EndpointID ep_interrupt_in_;
@@ -30,6 +41,7 @@ namespace usb {
     const int interface_index_;
     int in_packet_size_;
     int initialize_phase_{0};
+    int received_length_{0};
 
     std::array<uint8_t, kBufferSize> buf_{}, previous_buf_{};
   };
@@ -10,17 +10,39 @@ namespace usb {
   }
 
   Error HIDKeyboardDriver::OnDataReceived() {
+    const auto& buf = Buffer();
+    // 同時に押されたキーが多すぎると keycode がすべて ErrorRollOver (1) になる. 前の状態のままにする
+    if (buf[2] == 1) {
+      return MAKE_ERROR(Error::kSuccess);
+    }
+
+    const uint8_t modifier = buf[0];
+    // 修飾キーは usage ID 0xe0 (LeftControl) から 0xe7 (RightGUI) のキーとして通知する
+    for (int bit = 0; bit < 8; ++bit) {
+      const bool pressed = (modifier >> bit) & 1u;
+      const bool was_pressed = (prev_report_[0] >> bit) & 1u;
+      if (pressed != was_pressed) {
+        NotifyKeyPush(modifier, 0xe0 + bit, pressed);
+      }
+    }
+
+    auto contains = [](const uint8_t* keys, uint8_t key) {
+      return std::find(keys, keys + 6, key) != keys + 6;
+    };
     for (int i = 2; i < 8; ++i) {
-      const uint8_t key = Buffer()[i];
-      if (key == 0) {
-        continue;
+      const uint8_t key = prev_report_[i];
+      if (key != 0 && !contains(&buf[2], key)) {
+        NotifyKeyPush(modifier, key, false);
       }
-      const auto& prev_buf = PreviousBuffer();
-      if (std::find(prev_buf.begin(), prev_buf.end(), key) != prev_buf.end()) {
-        continue;
+    }
+    for (int i = 2; i < 8; ++i) {
+      const uint8_t key = buf[i];
+      if (key != 0 && !contains(&prev_report_[2], key)) {
+        NotifyKeyPush(modifier, key, true);
       }
-      NotifyKeyPush(key);
     }
+
+    std::copy_n(buf.begin(), prev_report_.size(), prev_report_.begin());
     return MAKE_ERROR(Error::kSuccess);
   }
 
@@ -33,15 +55,15 @@ namespace usb {
   }
 
   void HIDKeyboardDriver::SubscribeKeyPush(
-      std::function<void (uint8_t keycode)> observer) {
+      std::function<ObserverType> observer) {
     observers_[num_observers_++] = observer;
   }
 
   std::function<HIDKeyboardDriver::ObserverType> HIDKeyboardDriver::default_observer;
 
-  void HIDKeyboardDriver::NotifyKeyPush(uint8_t keycode) {
+  void HIDKeyboardDriver::NotifyKeyPush(uint8_t modifier, uint8_t keycode, bool press) {
     for (int i = 0; i < num_observers_; ++i) {
-      observers_[i](keycode);
+      observers_[i](modifier, keycode, press);
     }
   }
 }
@@ -19,14 +19,17 @@ namespace usb {
 
     Error OnDataReceived() override;
 
-    using ObserverType = void (uint8_t keycode);
+    // modifier: report の 1 byte 目, keycode: usage ID, press: 押されたら true, 離されたら false
+    using ObserverType = void (uint8_t modifier, uint8_t keycode, bool press);
     void SubscribeKeyPush(std::function<ObserverType> observer);
     static std::function<ObserverType> default_observer;
 
    private:
     std::array<std::function<ObserverType>, 4> observers_;
     int num_observers_ = 0;
+    // 最後に受け取った正常な report (modifier, reserved, keycode x 6)
+    std::array<uint8_t, 8> prev_report_{};
 
-    void NotifyKeyPush(uint8_t keycode);
+    void NotifyKeyPush(uint8_t modifier, uint8_t keycode, bool press);
   };
 }
@@ -7,13 +7,16 @@
 
 namespace usb {
   HIDMouseDriver::HIDMouseDriver(Device* dev, int interface_index)
-      : HIDBaseDriver{dev, interface_index, 3} {
+      : HIDBaseDriver{dev, interface_index, 4} {
   }
 
   Error HIDMouseDriver::OnDataReceived() {
+    uint8_t buttons = Buffer()[0];
     int8_t displacement_x = Buffer()[1];
     int8_t displacement_y = Buffer()[2];
-    NotifyMouseMove(displacement_x, displacement_y);
+    // boot protocol の report は 3 byte. 4 byte 目があれば wheel
+    int8_t wheel = ReceivedLength() >= 4 ? Buffer()[3] : 0;
+    NotifyMouseMove(buttons, displacement_x, displacement_y, wheel);
     Log(kDebug, "%02x,(%3d,%3d)\n", Buffer()[0], displacement_x, displacement_y);
     return MAKE_ERROR(Error::kSuccess);
   }
@@ -27,15 +30,15 @@ namespace usb {
   }
 
   void HIDMouseDriver::SubscribeMouseMove(
-      std::function<void (int8_t displacement_x, int8_t displacement_y)> observer) {
+      std::function<ObserverType> observer) {
     observers_[num_observers_++] = observer;
   }
 
   std::function<HIDMouseDriver::ObserverType> HIDMouseDriver::default_observer;
 
-  void HIDMouseDriver::NotifyMouseMove(int8_t displacement_x, int8_t displacement_y) {
+  void HIDMouseDriver::NotifyMouseMove(uint8_t buttons, int8_t displacement_x, int8_t displacement_y, int8_t wheel) {
     for (int i = 0; i < num_observers_; ++i) {
-      observers_[i](displacement_x, displacement_y);
+      observers_[i](buttons, displacement_x, displacement_y, wheel);
     }
   }
 }
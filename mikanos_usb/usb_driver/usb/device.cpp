@@ -5,6 +5,7 @@
 #include "usb/classdriver/base.hpp"
 #include "usb/classdriver/keyboard.hpp"
 #include "usb/classdriver/mouse.hpp"
+#include "usb/classdriver/generic.hpp"
 
 #include "logger.hpp"
 
@@ -70,6 +71,9 @@ namespace {
         return mouse_driver;
       }
     }
+    if (if_desc.interface_class == 3) {  // boot protocol に対応しない HID (tablet など)
+      return new usb::HIDGenericDriver{dev, if_desc.interface_number};
+    }
     return nullptr;
   }
 
@@ -191,6 +195,8 @@ namespace usb {
   Error Device::InitializePhase1(const uint8_t* buf, int len) {
     const auto device_desc = DescriptorDynamicCast<DeviceDescriptor>(buf);
     num_configurations_ = device_desc->num_configurations;
+    vendor_id_ = device_desc->vendor_id;
+    product_id_ = device_desc->product_id;
     config_index_ = 0;
     initialize_phase_ = 2;
     Log(kDebug, "issuing GetDesc(Config): index=%d)\n", config_index_);
@@ -215,6 +221,10 @@ namespace usb {
         // 非対応デバイス．次の interface を調べる．
         continue;
       }
+      has_class_driver_ = true;
+      interface_class_ = if_desc->interface_class;
+      interface_sub_class_ = if_desc->interface_sub_class;
+      interface_protocol_ = if_desc->interface_protocol;
 
       num_ep_configs_ = 0;
 
@@ -34,6 +34,16 @@ namespace usb {
 
     uint8_t* Buffer() { return buf_.data(); }
 
+    uint16_t VendorID() const { return vendor_id_; }
+    uint16_t ProductID() const { return product_id_; }
+    /** クラスドライバを割り当てた interface の class, sub class, protocol．
+     * 割り当てていなければ HasClassDriver() が false．
+     */
+    bool HasClassDriver() const { return has_class_driver_; }
+    uint8_t InterfaceClass() const { return interface_class_; }
+    uint8_t InterfaceSubClass() const { return interface_sub_class_; }
+    uint8_t InterfaceProtocol() const { return interface_protocol_; }
+
    protected:
     Error OnControlCompleted(EndpointID ep_id, SetupData setup_data,
                              const void* buf, int len);
@@ -53,6 +63,10 @@ namespace usb {
     uint8_t num_configurations_;
     uint8_t config_index_;
 
+    uint16_t vendor_id_ = 0, product_id_ = 0;
+    bool has_class_driver_ = false;
+    uint8_t interface_class_ = 0, interface_sub_class_ = 0, interface_protocol_ = 0;
+
     Error OnDeviceDescriptorReceived(const uint8_t* buf, int len);
     Error OnConfigurationDescriptorReceived(const uint8_t* buf, int len);
     Error OnSetConfigurationCompleted(uint8_t config_value);
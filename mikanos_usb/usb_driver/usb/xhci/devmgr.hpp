@@ -22,6 +22,7 @@ namespace usb::xhci {
     Device* FindByPort(uint8_t port_num, uint32_t route_string) const;
     Device* FindByState(enum Device::State state) const;
     Device* FindBySlot(uint8_t slot_id) const;
+    size_t MaxSlots() const { return max_slots_; }
     //WithError<Device*> Get(uint8_t device_id) const;
     Error AllocDevice(uint8_t slot_id, DoorbellRegister* dbreg);
     Error LoadDCBAA(uint8_t slot_id);
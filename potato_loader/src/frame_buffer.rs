@@ -1,11 +1,21 @@
-use core::slice;
-use uefi::proto::console::gop::PixelFormat as UEFIPixelFormat;
+use uefi::proto::console::gop::{GraphicsOutput, Mode, ModeInfo, PixelFormat as UEFIPixelFormat};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum PixelFormat {
     PixelRGBResv8BitPerColor,
     PixelBGRResv8BitPerColor,
+    PixelBitmask,
+}
+
+// kernel の PixelMask と同じレイアウト
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PixelMask {
+    pub red: u32,
+    pub green: u32,
+    pub blue: u32,
+    pub reserved: u32,
 }
 
 #[derive(Debug)]
@@ -16,19 +26,76 @@ pub struct FrameBuffer {
     horizontal_resolution: usize,
     vertical_resolution: usize,
     pixel_format: PixelFormat,
+    pixel_bitmask: PixelMask,
 }
 
 use uefi::prelude::{Boot, ResultExt, SystemTable};
 
+// ビルド時に POTATO_RESOLUTION=1280x720 のように指定する. 指定がなければ firmware の現在の mode を使う
+const PREFERRED_RESOLUTION: Option<&str> = option_env!("POTATO_RESOLUTION");
+
+fn parse_resolution(s: &str) -> Option<(usize, usize)> {
+    let (w, h) = s.trim().split_once('x')?;
+    Some((w.parse().ok()?, h.parse().ok()?))
+}
+
+// BltOnly は frame buffer に直接書けないので使えない
+fn is_usable(info: &ModeInfo) -> bool {
+    !matches!(info.pixel_format(), UEFIPixelFormat::BltOnly)
+}
+
+// preferred と同じ解像度の mode, なければ preferred に収まる中で最大の mode
+fn select_mode(gop: &GraphicsOutput, preferred: (usize, usize)) -> Option<Mode> {
+    let (pw, ph) = preferred;
+    let area = |mode: &Mode| mode.info().resolution().0 * mode.info().resolution().1;
+    let mut best: Option<Mode> = None;
+    for mode in gop.modes().map(|mode| mode.log()) {
+        let info = mode.info();
+        let (w, h) = info.resolution();
+        if !is_usable(info) || w > pw || h > ph {
+            continue;
+        }
+        if (w, h) == preferred {
+            return Some(mode);
+        }
+        if best.as_ref().map_or(true, |best| area(&mode) > area(best)) {
+            best = Some(mode);
+        }
+    }
+    best
+}
+
 impl FrameBuffer {
     pub fn from_system_table(system_table: &SystemTable<Boot>) -> Self {
-        use uefi::proto::console::gop::GraphicsOutput;
+        use core::fmt::Write;
         let protocol = system_table
             .boot_services()
             .locate_protocol::<GraphicsOutput>()
             .unwrap_success();
         let gop = unsafe { &mut *protocol.get() };
 
+        for (i, mode) in gop.modes().map(|mode| mode.log()).enumerate() {
+            let info = mode.info();
+            writeln!(
+                system_table.stdout(),
+                "GOP mode {}: {:?} {:?}",
+                i, info.resolution(), info.pixel_format()
+            ).unwrap();
+        }
+
+        // 解像度の指定があるか, 今の mode が BltOnly なら mode を変える
+        let preferred = match PREFERRED_RESOLUTION.and_then(parse_resolution) {
+            Some(resolution) => Some(resolution),
+            None if !is_usable(&gop.current_mode_info()) => Some((usize::MAX, usize::MAX)),
+            None => None,
+        };
+        if let Some(preferred) = preferred {
+            match select_mode(gop, preferred) {
+                Some(mode) => gop.set_mode(&mode).unwrap_success(),
+                None => writeln!(system_table.stdout(), "no GOP mode fits {:?}", preferred).unwrap(),
+            }
+        }
+
         // frame buffer
         let frame_buffer_ptr = gop.frame_buffer().as_mut_ptr();
 
@@ -41,8 +108,12 @@ impl FrameBuffer {
         let pixel_format = match mode_info.pixel_format() {
             UEFIPixelFormat::Rgb => PixelFormat::PixelRGBResv8BitPerColor,
             UEFIPixelFormat::Bgr => PixelFormat::PixelBGRResv8BitPerColor,
-            UEFIPixelFormat::Bitmask => panic!("unexpected PixelFormat::Bitmask"),
-            UEFIPixelFormat::BltOnly => panic!("unexpected PixelFormat::BltOnly"),
+            UEFIPixelFormat::Bitmask => PixelFormat::PixelBitmask,
+            UEFIPixelFormat::BltOnly => panic!("no GOP mode with a frame buffer"),
+        };
+        let pixel_bitmask = match mode_info.pixel_bitmask() {
+            Some(mask) => PixelMask { red: mask.red, green: mask.green, blue: mask.blue, reserved: mask.reserved },
+            None => PixelMask { red: 0, green: 0, blue: 0, reserved: 0 },
         };
 
         Self {
@@ -51,6 +122,7 @@ impl FrameBuffer {
             horizontal_resolution: resolution.0,
             vertical_resolution: resolution.1,
             pixel_format,
+            pixel_bitmask,
         }
     }
 }
@@ -2,6 +2,7 @@
 #![feature(asm)]
 
 pub mod frame_buffer;
+pub mod memory_map;
 
 use core::arch::asm;
 #[inline]
@@ -17,11 +17,13 @@ use uefi::{
 };
 
 use potato_loader::frame_buffer::FrameBuffer;
+use potato_loader::memory_map::{MemoryMap, MemoryRegion};
 use uefi::prelude::SystemTable;
 use uefi::table::Boot;
 
 // type EntryFn = extern "sysv64" fn(&FrameBuffer);
-type EntryFn = extern "sysv64" fn(FrameBuffer);
+// (frame buffer, ACPI RSDP のアドレス, メモリマップ)
+type EntryFn = extern "sysv64" fn(FrameBuffer, u64, MemoryMap);
 
 unsafe fn get_frame_buffer(system_table: &SystemTable<Boot>) -> FrameBuffer {
     let frame_buffer = FrameBuffer::from_system_table(system_table);
@@ -76,6 +78,15 @@ pub extern "efiapi" fn efi_main(
             .unwrap();
         unsafe { slice::from_raw_parts_mut(ptr, max_mmap_size) }
     };
+    // exit_boot_services の後で memory map をコピーする先 (LOADER_DATA なのでカーネルは上書きしない)
+    let memory_regions = {
+        let capacity = mmap_storage.len() / mem::size_of::<MemoryDescriptor>();
+        let ptr = system_table
+            .boot_services()
+            .allocate_pool(MemoryType::LOADER_DATA, capacity * mem::size_of::<MemoryRegion>())
+            .unwrap_success();
+        unsafe { slice::from_raw_parts_mut(ptr as *mut MemoryRegion, capacity) }
+    };
 
     // writeln!(system_table.stdout(), "mmap_storage").unwrap();
     // ------------------------------------------------------
@@ -131,6 +142,17 @@ pub extern "efiapi" fn efi_main(
     // frame buffer
     let frame_buffer = unsafe { get_frame_buffer(&system_table) };
 
+    // ACPI RSDP (ACPI 2.0 のものがなければ 1.0 のもの)
+    let acpi_rsdp = {
+        use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
+        let config_table = system_table.config_table();
+        config_table.iter()
+            .find(|entry| entry.guid == ACPI2_GUID)
+            .or_else(|| config_table.iter().find(|entry| entry.guid == ACPI_GUID))
+            .map(|entry| entry.address as u64)
+            .unwrap_or(0)
+    };
+
     // read kernel file
     let mut root_dir = {
         use uefi::proto::loaded_image::LoadedImage;
@@ -228,11 +250,17 @@ pub extern "efiapi" fn efi_main(
     writeln!(system_table.stdout(), "exiting boot services").unwrap();
     // exit boot services (and retreive memory_map)
     uefi::alloc::exit_boot_services();
-    let (_system_table, _memory_map) = system_table
+    let (_system_table, memory_map) = system_table
         .exit_boot_services(image, mmap_storage)
         .unwrap_success();
+    let mut len = 0;
+    for (region, desc) in memory_regions.iter_mut().zip(memory_map) {
+        *region = MemoryRegion::from(desc);
+        len += 1;
+    }
+    let memory_map = MemoryMap::new(&memory_regions[..len]);
 
-    entry_point(frame_buffer); //
+    entry_point(frame_buffer, acpi_rsdp, memory_map); //
 
     loop {}
 }
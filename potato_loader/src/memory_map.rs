@@ -0,0 +1,38 @@
+use uefi::table::boot::MemoryDescriptor;
+
+// カーネルに渡すメモリマップの 1 エントリ (UEFI の MemoryDescriptor から必要な部分だけを取り出したもの)
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MemoryRegion {
+    pub ty: u32,
+    pub phys_start: u64,
+    pub page_count: u64,
+    pub attribute: u64,
+}
+
+impl From<&MemoryDescriptor> for MemoryRegion {
+    fn from(desc: &MemoryDescriptor) -> Self {
+        Self {
+            ty: desc.ty.0,
+            phys_start: desc.phys_start,
+            page_count: desc.page_count,
+            attribute: desc.att.bits(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct MemoryMap {
+    regions: *const MemoryRegion,
+    len: usize,
+}
+
+impl MemoryMap {
+    pub fn new(regions: &[MemoryRegion]) -> Self {
+        Self {
+            regions: regions.as_ptr(),
+            len: regions.len(),
+        }
+    }
+}
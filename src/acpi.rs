@@ -0,0 +1,307 @@
+//! ACPI table parsing (RSDP -> XSDT -> FADT -> DSDT)
+//! 参考: ACPI Specification 6.4, 5.2 ACPI System Description Tables
+
+use crate::sync::SpinMutex;
+use crate::{info, warn};
+
+#[derive(Debug)]
+pub enum AcpiError {
+    InvalidSignature,
+    InvalidChecksum,
+    TableNotFound(&'static str),
+}
+type Result<T> = core::result::Result<T, AcpiError>;
+
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    _reserved: [u8; 3],
+}
+
+impl Rsdp {
+    fn validate(&self) -> Result<()> {
+        if &self.signature != b"RSD PTR " {
+            return Err(AcpiError::InvalidSignature);
+        }
+        // ACPI 1.0 の部分 (20 byte) と, revision 2 以降は全体 (36 byte) の checksum
+        let len = if self.revision >= 2 { 36 } else { 20 };
+        let bytes = unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, len) };
+        if sum_bytes(&bytes[..20]) != 0 || sum_bytes(bytes) != 0 {
+            return Err(AcpiError::InvalidChecksum);
+        }
+        Ok(())
+    }
+}
+
+#[repr(C, packed)]
+struct DescriptionHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+impl DescriptionHeader {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, self.length as usize) }
+    }
+
+    fn validate(&self, signature: &[u8; 4]) -> Result<()> {
+        if &self.signature != signature {
+            return Err(AcpiError::InvalidSignature);
+        }
+        if sum_bytes(self.as_bytes()) != 0 {
+            return Err(AcpiError::InvalidChecksum);
+        }
+        Ok(())
+    }
+
+    // header の後ろに続くデータ
+    fn body(&self) -> &[u8] {
+        &self.as_bytes()[core::mem::size_of::<Self>()..]
+    }
+}
+
+// Generic Address Structure
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct GenericAddress {
+    pub address_space_id: u8,
+    pub register_bit_width: u8,
+    pub register_bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+}
+
+// Fixed ACPI Description Table (X_PM1b_CNT_BLK まで)
+#[repr(C, packed)]
+pub struct Fadt {
+    header: DescriptionHeader,
+    pub firmware_ctrl: u32,
+    pub dsdt: u32,
+    _reserved0: u8,
+    pub preferred_pm_profile: u8,
+    pub sci_int: u16,
+    pub smi_cmd: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub s4bios_req: u8,
+    pub pstate_cnt: u8,
+    pub pm1a_evt_blk: u32,
+    pub pm1b_evt_blk: u32,
+    pub pm1a_cnt_blk: u32,
+    pub pm1b_cnt_blk: u32,
+    pub pm2_cnt_blk: u32,
+    pub pm_tmr_blk: u32,
+    pub gpe0_blk: u32,
+    pub gpe1_blk: u32,
+    pub pm1_evt_len: u8,
+    pub pm1_cnt_len: u8,
+    pub pm2_cnt_len: u8,
+    pub pm_tmr_len: u8,
+    pub gpe0_blk_len: u8,
+    pub gpe1_blk_len: u8,
+    pub gpe1_base: u8,
+    pub cst_cnt: u8,
+    pub p_lvl2_lat: u16,
+    pub p_lvl3_lat: u16,
+    pub flush_size: u16,
+    pub flush_stride: u16,
+    pub duty_offset: u8,
+    pub duty_width: u8,
+    pub day_alrm: u8,
+    pub mon_alrm: u8,
+    pub century: u8,
+    pub iapc_boot_arch: u16,
+    _reserved1: u8,
+    pub flags: u32,
+    pub reset_reg: GenericAddress,
+    pub reset_value: u8,
+    pub arm_boot_arch: u16,
+    pub fadt_minor_version: u8,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+    pub x_pm1a_evt_blk: GenericAddress,
+    pub x_pm1b_evt_blk: GenericAddress,
+    pub x_pm1a_cnt_blk: GenericAddress,
+    pub x_pm1b_cnt_blk: GenericAddress,
+}
+
+impl Fadt {
+    // flags: TMR_VAL_EXT (PM timer が 32 bit)
+    pub const TIMER_VALUE_EXTENDED: u32 = 1 << 8;
+    // flags: RESET_REG_SUP
+    pub const RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+    // 古い FADT は短いので, length を超えるフィールドは読まない
+    fn has_field(&self, offset: usize, size: usize) -> bool {
+        offset + size <= self.header.length as usize
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.header.revision
+    }
+
+    pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+        let offset = 116; // RESET_REG
+        if self.flags & Self::RESET_REG_SUPPORTED == 0 || !self.has_field(offset, 13) {
+            return None;
+        }
+        Some((self.reset_reg, self.reset_value))
+    }
+
+    pub fn dsdt_address(&self) -> u64 {
+        let offset = 140; // X_DSDT
+        if self.has_field(offset, 8) && self.x_dsdt != 0 {
+            self.x_dsdt
+        } else {
+            self.dsdt as u64
+        }
+    }
+
+    // PM timer の I/O ポートと, 32 bit かどうか
+    pub fn pm_timer(&self) -> Option<(u16, bool)> {
+        if self.pm_tmr_blk == 0 {
+            return None;
+        }
+        Some((self.pm_tmr_blk as u16, self.flags & Self::TIMER_VALUE_EXTENDED != 0))
+    }
+
+    // PM1a, PM1b control block の I/O ポート (X_ の方を優先する)
+    pub fn pm1_control_ports(&self) -> (u16, Option<u16>) {
+        let io_port = |gas: GenericAddress, legacy: u32| {
+            if gas.address_space_id == GenericAddress::SYSTEM_IO && gas.address != 0 {
+                gas.address as u16
+            } else {
+                legacy as u16
+            }
+        };
+        let (pm1a, pm1b) = if self.has_field(172, 24) {
+            (io_port(self.x_pm1a_cnt_blk, self.pm1a_cnt_blk), io_port(self.x_pm1b_cnt_blk, self.pm1b_cnt_blk))
+        } else {
+            (self.pm1a_cnt_blk as u16, self.pm1b_cnt_blk as u16)
+        };
+        (pm1a, if pm1b != 0 { Some(pm1b) } else { None })
+    }
+}
+
+pub struct Acpi {
+    fadt: &'static Fadt,
+    // \_S5 の SLP_TYPa, SLP_TYPb
+    s5_sleep_type: Option<(u8, u8)>,
+}
+
+impl Acpi {
+    pub fn fadt(&self) -> &'static Fadt {
+        self.fadt
+    }
+
+    pub fn s5_sleep_type(&self) -> Option<(u8, u8)> {
+        self.s5_sleep_type
+    }
+}
+
+pub static ACPI: SpinMutex<Option<Acpi>> = SpinMutex::new(None);
+
+// loader から受け取った RSDP のアドレスで初期化する. 0 なら何もしない
+pub fn init_acpi(rsdp_address: u64) {
+    if rsdp_address == 0 {
+        warn!("acpi: RSDP not found");
+        return;
+    }
+    match parse_tables(rsdp_address) {
+        Ok(acpi) => {
+            info!("acpi: FADT revision {}, S5 sleep type {:?}", acpi.fadt.revision(), acpi.s5_sleep_type);
+            *ACPI.lock() = Some(acpi);
+        },
+        Err(e) => {
+            warn!("acpi: failed to parse tables: {:?}", e);
+        },
+    }
+}
+
+fn parse_tables(rsdp_address: u64) -> Result<Acpi> {
+    let rsdp = unsafe { &*(rsdp_address as *const Rsdp) };
+    rsdp.validate()?;
+
+    // ACPI 2.0 以降は XSDT (64bit アドレス), 1.0 は RSDT (32bit アドレス)
+    let (sdt, signature, entry_size) = if rsdp.revision >= 2 {
+        (rsdp.xsdt_address, b"XSDT", 8)
+    } else {
+        (rsdp.rsdt_address as u64, b"RSDT", 4)
+    };
+    let sdt = unsafe { &*(sdt as *const DescriptionHeader) };
+    sdt.validate(signature)?;
+
+    let fadt = sdt.body()
+        .chunks_exact(entry_size)
+        .map(|entry| {
+            let addr = entry.iter().rev().fold(0_u64, |addr, &b| addr << 8 | b as u64);
+            unsafe { &*(addr as *const DescriptionHeader) }
+        })
+        .find(|table| table.validate(b"FACP").is_ok())
+        .ok_or(AcpiError::TableNotFound("FACP"))?;
+    let fadt = unsafe { &*(fadt as *const DescriptionHeader as *const Fadt) };
+
+    let dsdt = unsafe { &*(fadt.dsdt_address() as *const DescriptionHeader) };
+    dsdt.validate(b"DSDT")?;
+
+    Ok(Acpi {
+        fadt,
+        s5_sleep_type: find_s5_sleep_type(dsdt.body()),
+    })
+}
+
+// AML を解釈せずに \_S5 パッケージを探す
+// (NameOp "_S5_" PackageOp PkgLength NumElements SLP_TYPa SLP_TYPb ...)
+// 参考: https://forum.osdev.org/viewtopic.php?t=16990
+fn find_s5_sleep_type(aml: &[u8]) -> Option<(u8, u8)> {
+    const NAME_OP: u8 = 0x08;
+    const PACKAGE_OP: u8 = 0x12;
+    const BYTE_PREFIX: u8 = 0x0a;
+
+    let pos = aml.windows(4).position(|w| w == b"_S5_")?;
+    let is_name = (pos >= 1 && aml[pos - 1] == NAME_OP)
+        || (pos >= 2 && aml[pos - 2] == NAME_OP && aml[pos - 1] == b'\\');
+    if !is_name || aml.get(pos + 4) != Some(&PACKAGE_OP) {
+        return None;
+    }
+
+    // PkgLength の先頭 byte の bit 6-7 が後続 byte 数. その後ろの NumElements も飛ばす
+    let mut i = pos + 5;
+    i += ((*aml.get(i)? >> 6) & 0b11) as usize + 2;
+
+    let mut read_byte_const = || {
+        if *aml.get(i)? == BYTE_PREFIX {
+            i += 1;
+        }
+        // ZeroOp (0x00) と OneOp (0x01) はそのまま値として読める
+        let val = *aml.get(i)?;
+        i += 1;
+        Some(val)
+    };
+    let slp_typ_a = read_byte_const()?;
+    let slp_typ_b = read_byte_const()?;
+    Some((slp_typ_a, slp_typ_b))
+}
+
+fn sum_bytes(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0_u8, |sum, &b| sum.wrapping_add(b))
+}
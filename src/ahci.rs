@@ -0,0 +1,479 @@
+//! AHCI (SATA) driver
+//! 参考:
+//! - https://wiki.osdev.org/AHCI
+//! - Serial ATA AHCI 1.3.1 Specification
+
+use crate::block::{self, BlockDevice, BlockError, SECTOR_SIZE};
+use crate::pci::{self, Device};
+use crate::sync::SpinMutex;
+use crate::utils::bit_field::BitField;
+use crate::utils::fixed_vec::FixedVec;
+use crate::info;
+
+const MAX_DISKS: usize = 4;
+const MAX_PORTS: usize = 32;
+const PRDT_ENTRIES: usize = 8;
+// 1 つの PRD で転送できる最大バイト数 (4MiB)
+const MAX_PRD_BYTES: usize = 4 * 1024 * 1024;
+// 1 コマンドで転送するセクタ数の上限 (count は 16bit, 0 は 65536 を意味するので避ける)
+const MAX_SECTORS_PER_COMMAND: usize = 0xffff;
+// レジスタの変化やコマンドの完了を待つ回数の上限
+const SPIN_LIMIT: usize = 10_000_000;
+
+// generic host control
+const HBA_CAP: usize = 0x00;
+const HBA_GHC: usize = 0x04;
+const HBA_PI: usize = 0x0c;
+const HBA_VS: usize = 0x10;
+
+// CAP: 64 bit のアドレスを扱える
+const CAP_S64A: usize = 31;
+
+// port registers (0x100 + port * 0x80)
+const PORT_CLB: usize = 0x00;
+const PORT_CLBU: usize = 0x04;
+const PORT_FB: usize = 0x08;
+const PORT_FBU: usize = 0x0c;
+const PORT_IS: usize = 0x10;
+const PORT_IE: usize = 0x14;
+const PORT_CMD: usize = 0x18;
+const PORT_TFD: usize = 0x20;
+const PORT_SIG: usize = 0x24;
+const PORT_SSTS: usize = 0x28;
+const PORT_SERR: usize = 0x30;
+const PORT_CI: usize = 0x38;
+
+// PxCMD
+const CMD_ST: usize = 0;
+const CMD_FRE: usize = 4;
+const CMD_FR: usize = 14;
+const CMD_CR: usize = 15;
+
+// PxTFD
+const TFD_ERR: usize = 0;
+const TFD_DRQ: usize = 3;
+const TFD_BSY: usize = 7;
+
+// PxIS: task file error status
+const IS_TFES: usize = 30;
+
+const SATA_SIG_ATA: u32 = 0x0000_0101;
+
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+
+const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_CMD_IDENTIFY_DEVICE: u8 = 0xec;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CommandHeader {
+    // CFL (0..5), A (5), W (6), P (7), R (8), B (9), C (10), PMP (12..16), PRDTL (16..32)
+    flags: u32,
+    prdbc: u32,
+    ctba: u32,
+    ctbau: u32,
+    _reserved: [u32; 4],
+}
+
+impl CommandHeader {
+    const EMPTY: Self = Self { flags: 0, prdbc: 0, ctba: 0, ctbau: 0, _reserved: [0; 4] };
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PhysicalRegionDescriptor {
+    dba: u32,
+    dbau: u32,
+    _reserved: u32,
+    // byte count - 1 (0..22), interrupt on completion (31)
+    dbc: u32,
+}
+
+#[repr(C, align(128))]
+struct CommandTable {
+    cfis: [u8; 64],
+    acmd: [u8; 16],
+    _reserved: [u8; 48],
+    prdt: [PhysicalRegionDescriptor; PRDT_ENTRIES],
+}
+
+// 1 ポートあたりに必要な DMA 領域. コマンドスロットは 0 番だけ使う
+#[repr(C, align(1024))]
+struct PortMemory {
+    command_list: [CommandHeader; 32], // 1KiB align
+    received_fis: [u8; 256], // 256 byte align
+    command_table: CommandTable, // 128 byte align
+}
+
+impl PortMemory {
+    const fn new() -> Self {
+        Self {
+            command_list: [CommandHeader::EMPTY; 32],
+            received_fis: [0; 256],
+            command_table: CommandTable {
+                cfis: [0; 64],
+                acmd: [0; 16],
+                _reserved: [0; 48],
+                prdt: [PhysicalRegionDescriptor { dba: 0, dbau: 0, _reserved: 0, dbc: 0 }; PRDT_ENTRIES],
+            },
+        }
+    }
+}
+
+static mut PORT_MEMORY: [PortMemory; MAX_DISKS] = [
+    PortMemory::new(), PortMemory::new(), PortMemory::new(), PortMemory::new(),
+];
+
+#[derive(Debug)]
+pub enum AhciError {
+    PortHung,
+    TaskFileError(u32),
+    BufferTooLarge,
+    // 64 bit アドレスに対応していない HBA に 4GiB 以上のメモリを渡そうとした
+    AddressNotReachable,
+}
+
+// IDENTIFY DEVICE で得られる情報
+pub struct IdentifyData {
+    model: [u8; 40],
+    serial: [u8; 20],
+    firmware: [u8; 8],
+    sector_count: u64,
+}
+
+impl IdentifyData {
+    fn from_words(words: &[u16; 256]) -> Self {
+        // ATA string は 1 word に 2 文字が上位 byte から入っている
+        fn ata_string<const N: usize>(words: &[u16]) -> [u8; N] {
+            let mut s = [b' '; N];
+            for (i, w) in words.iter().take(N / 2).enumerate() {
+                s[2*i] = (w >> 8) as u8;
+                s[2*i + 1] = *w as u8;
+            }
+            s
+        }
+        // LBA48 に対応していれば (word 83, bit 10) words 100..104, そうでなければ words 60..62
+        let sector_count = if words[83].get_bit(10) {
+            (0..4).fold(0_u64, |acc, i| acc | (words[100 + i] as u64) << (16 * i))
+        } else {
+            words[60] as u64 | (words[61] as u64) << 16
+        };
+        Self {
+            model: ata_string(&words[27..47]),
+            serial: ata_string(&words[10..20]),
+            firmware: ata_string(&words[23..27]),
+            sector_count,
+        }
+    }
+
+    fn trim(s: &[u8]) -> &str {
+        core::str::from_utf8(s).unwrap_or("").trim()
+    }
+
+    pub fn model(&self) -> &str {
+        Self::trim(&self.model)
+    }
+
+    pub fn serial(&self) -> &str {
+        Self::trim(&self.serial)
+    }
+
+    pub fn firmware(&self) -> &str {
+        Self::trim(&self.firmware)
+    }
+
+    pub fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Hba {
+    abar: u64,
+}
+
+impl Hba {
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.abar as usize + offset) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, offset: usize, val: u32) {
+        unsafe { ((self.abar as usize + offset) as *mut u32).write_volatile(val) }
+    }
+
+    fn read_port(&self, port: usize, offset: usize) -> u32 {
+        self.read(0x100 + port * 0x80 + offset)
+    }
+
+    fn write_port(&self, port: usize, offset: usize, val: u32) {
+        self.write(0x100 + port * 0x80 + offset, val)
+    }
+
+    fn supports_64bit(&self) -> bool {
+        self.read(HBA_CAP).get_bit(CAP_S64A)
+    }
+
+    // コマンドエンジンが止まるのを待つ.
+    // ST を落として CR が 0 になってから FRE を落とし, FR が 0 になるのを待つ (10.1.2)
+    fn stop_port(&self, port: usize) -> Result<(), AhciError> {
+        let mut cmd = self.read_port(port, PORT_CMD);
+        self.write_port(port, PORT_CMD, *cmd.set_bit(CMD_ST, false));
+        self.wait_port(port, PORT_CMD, |cmd| !cmd.get_bit(CMD_CR))?;
+
+        let mut cmd = self.read_port(port, PORT_CMD);
+        self.write_port(port, PORT_CMD, *cmd.set_bit(CMD_FRE, false));
+        self.wait_port(port, PORT_CMD, |cmd| !cmd.get_bit(CMD_FR))
+    }
+
+    fn start_port(&self, port: usize) -> Result<(), AhciError> {
+        self.wait_port(port, PORT_CMD, |cmd| !cmd.get_bit(CMD_CR))?;
+        let mut cmd = self.read_port(port, PORT_CMD);
+        self.write_port(port, PORT_CMD, *cmd.set_bit(CMD_FRE, true));
+        self.write_port(port, PORT_CMD, *cmd.set_bit(CMD_ST, true));
+        Ok(())
+    }
+
+    fn wait_port<F: Fn(u32) -> bool>(&self, port: usize, offset: usize, cond: F) -> Result<(), AhciError> {
+        for _ in 0..SPIN_LIMIT {
+            if cond(self.read_port(port, offset)) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(AhciError::PortHung)
+    }
+
+    fn has_sata_disk(&self, port: usize) -> bool {
+        let ssts = self.read_port(port, PORT_SSTS);
+        // DET == 3: device present and phy communication established, IPM == 1: active
+        ssts.get_bits(0..4) == 3
+            && ssts.get_bits(8..12) == 1
+            && self.read_port(port, PORT_SIG) == SATA_SIG_ATA
+    }
+}
+
+pub struct AhciDisk {
+    hba: Hba,
+    port: usize,
+    mem: &'static mut PortMemory,
+    identify: IdentifyData,
+}
+
+impl AhciDisk {
+    fn new(hba: Hba, port: usize, mem: &'static mut PortMemory) -> Result<Self, AhciError> {
+        hba.stop_port(port)?;
+
+        *mem = PortMemory::new();
+        let command_list = mem.command_list.as_ptr() as u64;
+        let received_fis = mem.received_fis.as_ptr() as u64;
+        let command_table = &mem.command_table as *const CommandTable as u64;
+        // S64A でなければ上位 32 bit のレジスタは使えない (予約)
+        let s64a = hba.supports_64bit();
+        if !s64a && [command_list, received_fis, command_table].iter().any(|&addr| addr >> 32 != 0) {
+            return Err(AhciError::AddressNotReachable);
+        }
+
+        hba.write_port(port, PORT_CLB, command_list as u32);
+        hba.write_port(port, PORT_FB, received_fis as u32);
+        mem.command_list[0].ctba = command_table as u32;
+        if s64a {
+            hba.write_port(port, PORT_CLBU, (command_list >> 32) as u32);
+            hba.write_port(port, PORT_FBU, (received_fis >> 32) as u32);
+            mem.command_list[0].ctbau = (command_table >> 32) as u32;
+        }
+
+        // 割り込みは使わず, エラーと状態はポーリングで見る
+        hba.write_port(port, PORT_IE, 0);
+        hba.write_port(port, PORT_SERR, !0);
+        hba.write_port(port, PORT_IS, !0);
+        hba.start_port(port)?;
+
+        let mut disk = Self {
+            hba,
+            port,
+            mem,
+            identify: IdentifyData::from_words(&[0; 256]),
+        };
+        let mut words = [0_u16; 256];
+        if let Err(e) = disk.issue(ATA_CMD_IDENTIFY_DEVICE, 0, 0, words.as_mut_ptr() as u64, SECTOR_SIZE, false) {
+            // mem は次の port に使われるので, この port の DMA を止めてから返す
+            let _ = hba.stop_port(port);
+            return Err(e);
+        }
+        disk.identify = IdentifyData::from_words(&words);
+        Ok(disk)
+    }
+
+    pub fn port(&self) -> usize {
+        self.port
+    }
+
+    pub fn identify(&self) -> &IdentifyData {
+        &self.identify
+    }
+
+    // スロット 0 にコマンドを積んで完了まで待つ
+    fn issue(&mut self, command: u8, lba: u64, count: u16, buf: u64, len: usize, write: bool) -> Result<(), AhciError> {
+        let prdt_len = (len + MAX_PRD_BYTES - 1) / MAX_PRD_BYTES;
+        if prdt_len > PRDT_ENTRIES {
+            return Err(AhciError::BufferTooLarge);
+        }
+
+        let table = &mut self.mem.command_table;
+        table.cfis = [0; 64];
+        table.cfis[0] = FIS_TYPE_REG_H2D;
+        table.cfis[1] = 1 << 7; // command (not control)
+        table.cfis[2] = command;
+        table.cfis[4] = lba as u8;
+        table.cfis[5] = (lba >> 8) as u8;
+        table.cfis[6] = (lba >> 16) as u8;
+        table.cfis[7] = 1 << 6; // LBA mode
+        table.cfis[8] = (lba >> 24) as u8;
+        table.cfis[9] = (lba >> 32) as u8;
+        table.cfis[10] = (lba >> 40) as u8;
+        table.cfis[12] = count as u8;
+        table.cfis[13] = (count >> 8) as u8;
+
+        for i in 0..prdt_len {
+            let offset = i * MAX_PRD_BYTES;
+            let bytes = (len - offset).min(MAX_PRD_BYTES);
+            let addr = buf + offset as u64;
+            table.prdt[i] = PhysicalRegionDescriptor {
+                dba: addr as u32,
+                dbau: (addr >> 32) as u32,
+                _reserved: 0,
+                dbc: (bytes - 1) as u32,
+            };
+        }
+
+        let header = &mut self.mem.command_list[0];
+        header.flags = *0_u32
+            .set_bits(0..5, 5) // Register H2D FIS は 5 dword
+            .set_bit(6, write)
+            .set_bits(16..32, prdt_len as u32);
+        header.prdbc = 0;
+
+        let (hba, port) = (self.hba, self.port);
+        hba.wait_port(port, PORT_TFD, |tfd| !tfd.get_bit(TFD_BSY) && !tfd.get_bit(TFD_DRQ))?;
+        hba.write_port(port, PORT_IS, !0);
+        hba.write_port(port, PORT_CI, 1);
+
+        // 終わらなければ (drive が応答しない, 外された) PortHung にする
+        let mut result = Err(AhciError::PortHung);
+        for _ in 0..SPIN_LIMIT {
+            if hba.read_port(port, PORT_IS).get_bit(IS_TFES) {
+                result = Err(AhciError::TaskFileError(hba.read_port(port, PORT_TFD)));
+                break;
+            }
+            if !hba.read_port(port, PORT_CI).get_bit(0) {
+                result = Ok(());
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        if result.is_ok() && hba.read_port(port, PORT_TFD).get_bit(TFD_ERR) {
+            result = Err(AhciError::TaskFileError(hba.read_port(port, PORT_TFD)));
+        }
+        if result.is_err() {
+            // エラー状態をクリアするにはコマンドエンジンを再起動する必要がある
+            hba.write_port(port, PORT_SERR, !0);
+            hba.write_port(port, PORT_IS, !0);
+            let _ = hba.stop_port(port).and_then(|_| hba.start_port(port));
+        }
+        result
+    }
+
+    fn transfer(&mut self, command: u8, lba: u64, buf: u64, len: usize, write: bool) -> block::Result<()> {
+        // data base address は word align が必要
+        if buf & 0b1 != 0 {
+            return Err(BlockError::UnalignedBuffer);
+        }
+        // S64A でなければ 4GiB 未満の buf しか DMA できない
+        if !self.hba.supports_64bit() && buf + len as u64 > 1 << 32 {
+            return Err(BlockError::UnalignedBuffer);
+        }
+        let max_bytes = (MAX_SECTORS_PER_COMMAND * SECTOR_SIZE).min(PRDT_ENTRIES * MAX_PRD_BYTES);
+        let mut done = 0;
+        while done < len {
+            let bytes = (len - done).min(max_bytes);
+            let sectors = bytes / SECTOR_SIZE;
+            let lba = lba + (done / SECTOR_SIZE) as u64;
+            self.issue(command, lba, sectors as u16, buf + done as u64, bytes, write)
+                .map_err(|_| BlockError::DeviceError)?;
+            done += bytes;
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn sector_count(&self) -> u64 {
+        self.identify.sector_count()
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> block::Result<()> {
+        self.check_request(lba, buf.len())?;
+        self.transfer(ATA_CMD_READ_DMA_EXT, lba, buf.as_mut_ptr() as u64, buf.len(), false)
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> block::Result<()> {
+        self.check_request(lba, buf.len())?;
+        self.transfer(ATA_CMD_WRITE_DMA_EXT, lba, buf.as_ptr() as u64, buf.len(), true)
+    }
+}
+
+pub static AHCI_DISKS: SpinMutex<FixedVec<AhciDisk, MAX_DISKS>> = SpinMutex::new(FixedVec::new());
+
+pub fn init_ahci() {
+    let device = pci::devices().iter().find(|device| {
+        let (base, sub, iface, _) = device.as_config().read_class_code();
+        (base, sub, iface) == (0x01, 0x06, 0x01)
+    });
+    if let Some(device) = device {
+        init_controller(device);
+    }
+}
+
+fn init_controller(device: &Device) {
+    device.enable_bus_master();
+    // ABAR は BAR5
+    let abar = match device.read_bar(5) {
+        Some(bar) => bar & !0x0f,
+        None => return,
+    };
+    let hba = Hba { abar };
+
+    // AHCI enable
+    let mut ghc = hba.read(HBA_GHC);
+    hba.write(HBA_GHC, *ghc.set_bit(31, true));
+
+    let version = hba.read(HBA_VS);
+    let num_ports = hba.read(HBA_CAP).get_bits(0..5) as usize + 1;
+    info!("ahci: version {:x}.{:x}, {} ports", version >> 16, version & 0xffff, num_ports);
+
+    let implemented = hba.read(HBA_PI);
+    let mut disks = AHCI_DISKS.lock();
+    for port in (0..MAX_PORTS).filter(|&port| implemented.get_bit(port)) {
+        if !hba.has_sata_disk(port) {
+            continue;
+        }
+        if disks.len() == MAX_DISKS {
+            break;
+        }
+        let mem = unsafe { &mut PORT_MEMORY[disks.len()] };
+        match AhciDisk::new(hba, port, mem) {
+            Ok(disk) => {
+                let id = disk.identify();
+                info!(
+                    "ahci: port {}: {} (serial {}, firmware {}), {} sectors",
+                    port, id.model(), id.serial(), id.firmware(), id.sector_count()
+                );
+                disks.push(disk);
+            },
+            Err(e) => {
+                info!("ahci: port {}: initialization failed: {:?}", port, e);
+            },
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::interrupts::idt::InterruptVector;
+use crate::io::PortWriteOnly;
+use crate::utils::bit_field::BitField;
+
+const LOCAL_APIC_ID_REGISTER: *const u32 = 0xfee00020 as *const u32;
+
+// I/O APIC のレジスタ (QEMU, 実機ともにデフォルトの MMIO アドレス)
+const IO_APIC_INDEX_REGISTER: *mut u32 = 0xfec00000 as *mut u32;
+const IO_APIC_DATA_REGISTER: *mut u32 = 0xfec00010 as *mut u32;
+const IO_APIC_REDIRECTION_TABLE: u32 = 0x10;
+
+pub fn local_apic_id() -> u8 {
+    unsafe { LOCAL_APIC_ID_REGISTER.read_volatile() }.get_bits(24..32) as u8
+}
+
+fn write_io_apic(index: u32, val: u32) {
+    unsafe {
+        IO_APIC_INDEX_REGISTER.write_volatile(index);
+        IO_APIC_DATA_REGISTER.write_volatile(val);
+    }
+}
+
+// legacy IRQ (ISA) を I/O APIC 経由で local APIC の vector に届ける
+// (edge trigger, active high, fixed delivery)
+pub fn redirect_irq(irq: u8, vector: InterruptVector, apic_id: u8) {
+    let low = *0_u32
+        .set_bits(0..8, vector as u32)
+        .set_bit(16, false); // unmask
+    let high = *0_u32.set_bits(24..32, apic_id as u32);
+    let index = IO_APIC_REDIRECTION_TABLE + 2 * irq as u32;
+    write_io_apic(index + 1, high);
+    write_io_apic(index, low);
+}
+
+// I/O APIC を使うので 8259 PIC の割り込みはすべてマスクする
+pub fn disable_pic() {
+    PortWriteOnly::<u8>::new(0xa1).write(0xff);
+    PortWriteOnly::<u8>::new(0x21).write(0xff);
+}
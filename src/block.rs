@@ -0,0 +1,39 @@
+pub const SECTOR_SIZE: usize = 512;
+
+#[derive(Debug)]
+pub enum BlockError {
+    // lba + buf の範囲がディスクの外
+    OutOfRange,
+    // buf が空か, 長さが sector size の倍数でない
+    InvalidBufferSize,
+    // DMA の制約を満たさないアドレスの buf
+    UnalignedBuffer,
+    // デバイスがエラーを返した
+    DeviceError,
+    ReadOnly,
+}
+pub type Result<T> = core::result::Result<T, BlockError>;
+
+// セクタ単位で読み書きできるデバイス (virtio-blk, AHCI, ...)
+pub trait BlockDevice {
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> u64;
+
+    // buf.len() / sector_size() セクタを lba から読む
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Result<()>;
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Result<()>;
+
+    fn check_request(&self, lba: u64, len: usize) -> Result<u64> {
+        if len == 0 || len % self.sector_size() != 0 {
+            return Err(BlockError::InvalidBufferSize);
+        }
+        let count = (len / self.sector_size()) as u64;
+        match lba.checked_add(count) {
+            Some(end) if end <= self.sector_count() => Ok(count),
+            _ => Err(BlockError::OutOfRange),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! ANSI (VT100) の escape sequence の解析
+//! 1 文字ずつ advance に渡し, 表示する文字, 制御文字, sequence をまとめた Action を受け取る.
+//! 参考: https://vt100.net/emu/dec_ansi_parser, https://en.wikipedia.org/wiki/ANSI_escape_code
+
+use crate::graphics::PixelColor;
+
+const ESC: char = '\x1b';
+const MAX_PARAMS: usize = 16;
+
+#[derive(Clone, Copy)]
+enum State {
+    Ground,
+    // ESC の直後
+    Escape,
+    // ESC [ の後. final byte (0x40..=0x7e) まで
+    Csi,
+    // 解釈できない sequence を final byte まで読み飛ばす
+    Ignore,
+}
+
+// CSI の数値の parameter. 省略されたものは None
+#[derive(Clone, Copy)]
+pub struct Params {
+    values: [Option<u16>; MAX_PARAMS],
+    len: usize,
+}
+
+impl Params {
+    const fn new() -> Self {
+        Self { values: [None; MAX_PARAMS], len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, i: usize) -> Option<u16> {
+        if i < self.len { self.values[i] } else { None }
+    }
+
+    // 省略されているか 0 なら default (カーソル移動の回数など)
+    pub fn get_or(&self, i: usize, default: u16) -> u16 {
+        match self.get(i) {
+            Some(0) | None => default,
+            Some(value) => value,
+        }
+    }
+
+    fn push_digit(&mut self, digit: u16) {
+        if self.len == 0 {
+            self.len = 1;
+        }
+        let value = &mut self.values[self.len - 1];
+        *value = Some(value.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+    }
+
+    fn next(&mut self) {
+        if self.len == 0 {
+            self.len = 1;
+        }
+        // 多すぎる parameter は捨てる
+        if self.len < MAX_PARAMS {
+            self.len += 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Action {
+    // 表示する文字
+    Print(char),
+    // 0x00..=0x1f, 0x7f の制御文字 (ESC を除く)
+    Control(char),
+    // ESC に続く 1 文字 (ESC 7, ESC 8 など)
+    Escape(char),
+    // ESC [ params final. private は ESC [ ? ... のとき
+    Csi { params: Params, private: bool, action: char },
+}
+
+pub struct Parser {
+    state: State,
+    params: Params,
+    private: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self { state: State::Ground, params: Params::new(), private: false }
+    }
+
+    pub fn advance(&mut self, c: char) -> Option<Action> {
+        match self.state {
+            State::Ground => match c {
+                ESC => {
+                    self.state = State::Escape;
+                    None
+                },
+                c if c.is_control() && (c as u32) < 0x80 => Some(Action::Control(c)),
+                c => Some(Action::Print(c)),
+            },
+            State::Escape => match c {
+                '[' => {
+                    self.state = State::Csi;
+                    self.params = Params::new();
+                    self.private = false;
+                    None
+                },
+                c => {
+                    self.state = State::Ground;
+                    Some(Action::Escape(c))
+                },
+            },
+            State::Csi => match c {
+                '0'..='9' => {
+                    self.params.push_digit(c as u16 - '0' as u16);
+                    None
+                },
+                ';' => {
+                    self.params.next();
+                    None
+                },
+                '?' if self.params.len == 0 && !self.private => {
+                    self.private = true;
+                    None
+                },
+                '\x40'..='\x7e' => {
+                    self.state = State::Ground;
+                    Some(Action::Csi { params: self.params, private: self.private, action: c })
+                },
+                // 途中の ESC で sequence をやり直す
+                ESC => {
+                    self.state = State::Escape;
+                    None
+                },
+                // intermediate byte など, 対応していないもの
+                _ => {
+                    self.state = State::Ignore;
+                    None
+                },
+            },
+            State::Ignore => {
+                if ('\x40'..='\x7e').contains(&c) {
+                    self.state = State::Ground;
+                }
+                None
+            },
+        }
+    }
+}
+
+// SGR 30..=37, 90..=97 などの 16 色 (xterm の配色)
+pub const PALETTE_16: [PixelColor; 16] = [
+    PixelColor::new(0, 0, 0),
+    PixelColor::new(205, 0, 0),
+    PixelColor::new(0, 205, 0),
+    PixelColor::new(205, 205, 0),
+    PixelColor::new(0, 0, 238),
+    PixelColor::new(205, 0, 205),
+    PixelColor::new(0, 205, 205),
+    PixelColor::new(229, 229, 229),
+    PixelColor::new(127, 127, 127),
+    PixelColor::new(255, 0, 0),
+    PixelColor::new(0, 255, 0),
+    PixelColor::new(255, 255, 0),
+    PixelColor::new(92, 92, 255),
+    PixelColor::new(255, 0, 255),
+    PixelColor::new(0, 255, 255),
+    PixelColor::new(255, 255, 255),
+];
+
+// 256 色: 16 色, 6x6x6 の色立方体, 24 段階の灰色
+pub fn palette_256(index: u8) -> PixelColor {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => PALETTE_16[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            PixelColor::new(LEVELS[(i / 36) as usize], LEVELS[(i / 6 % 6) as usize], LEVELS[(i % 6) as usize])
+        },
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            PixelColor::new(level, level, level)
+        },
+    }
+}
@@ -0,0 +1,733 @@
+use crate::graphics::{
+    PixelColor, Font, ShinonomeFont, Vector2D, Rectangle
+};
+use crate::graphics::font::{self, FallbackFont};
+use crate::graphics::window::{self, WindowId};
+use crate::graphics::layer::LAYER_MANAGER;
+use crate::memory::Frames;
+use crate::sync::IrqSpinMutex;
+use crate::timer;
+use core::ops::Range;
+
+pub mod ansi;
+use ansi::{Action, Params, Parser, PALETTE_16, palette_256};
+
+#[derive(Clone, Copy, PartialEq)]
+struct Color {
+    pub fg: PixelColor,
+    pub bg: PixelColor,
+}
+
+impl Color {
+    pub const DEFAULT: Self = Self {
+        fg: PixelColor::BLACK,
+        bg: PixelColor::WHITE,
+    };
+}
+
+// 1 セル分の文字と色
+#[derive(Clone, Copy, PartialEq)]
+struct ConsoleChar {
+    ch: char,
+    color: Color,
+    bold: bool,
+}
+
+impl ConsoleChar {
+    pub const DEFAULT: Self = Self {
+        ch: ' ',
+        color: Color::DEFAULT,
+        bold: false,
+    };
+}
+
+// SGR で指定する色
+#[derive(Clone, Copy)]
+enum AnsiColor {
+    Default,
+    // 256 色の palette の番号
+    Indexed(u8),
+    Rgb(PixelColor),
+}
+
+impl AnsiColor {
+    fn resolve(self, default: PixelColor, bold: bool) -> PixelColor {
+        match self {
+            AnsiColor::Default => default,
+            // bold の基本 8 色は明るい色で描く
+            AnsiColor::Indexed(index) if bold && index < 8 => PALETTE_16[index as usize + 8],
+            AnsiColor::Indexed(index) => palette_256(index),
+            AnsiColor::Rgb(color) => color,
+        }
+    }
+}
+
+// 次に書く文字の属性
+#[derive(Clone, Copy)]
+struct Attributes {
+    fg: AnsiColor,
+    bg: AnsiColor,
+    bold: bool,
+    inverse: bool,
+}
+
+impl Attributes {
+    const DEFAULT: Self = Self {
+        fg: AnsiColor::Default,
+        bg: AnsiColor::Default,
+        bold: false,
+        inverse: false,
+    };
+
+    fn color(&self) -> Color {
+        let fg = self.fg.resolve(Color::DEFAULT.fg, self.bold);
+        let bg = self.bg.resolve(Color::DEFAULT.bg, false);
+        if self.inverse { Color { fg: bg, bg: fg } } else { Color { fg, bg } }
+    }
+
+    // 消去したセルは反転せずに背景色で塗る
+    fn erase_color(&self) -> Color {
+        Color {
+            fg: self.fg.resolve(Color::DEFAULT.fg, false),
+            bg: self.bg.resolve(Color::DEFAULT.bg, false),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cursor {
+    x: usize,
+    y: usize,
+}
+
+pub struct Console {
+    rows: usize,
+    columns: usize,
+    // 行単位の ring buffer (最後の rows 行が画面, それより前が scrollback) と, 画面に描画済みの内容 (rows 行).
+    // init_console で画面の大きさに合わせて確保するまでは initial を使う
+    frames: Option<Frames>,
+    initial: [ConsoleChar; 2 * INITIAL_ROWS * INITIAL_COLUMNS],
+    // ring buffer の行数, 一番古い行, 使っている行数 (>= rows)
+    capacity: usize,
+    head: usize,
+    len: usize,
+    // 何行前を表示しているか (0 なら最新の行)
+    view_offset: usize,
+    attr: Attributes,
+    cursor: Cursor,
+    // ESC 7 / CSI s で保存したカーソルと属性
+    saved: (Cursor, Attributes),
+    parser: Parser,
+    // 前回の render 以降にスクロールした行数
+    pending_scroll: usize,
+    // 前回の render 以降に変わったかもしれない表示上の行. この範囲だけ描画済みの内容と比べる
+    dirty: Range<usize>,
+    // 点滅の表示側か, カーソルを描いたセル
+    cursor_visible: bool,
+    rendered_cursor: Option<usize>,
+    // 描画先の window (なければ WRITER に直接描画する)
+    window: Option<WindowId>,
+}
+
+// init_console の前の大きさ
+const INITIAL_ROWS: usize = 25;
+const INITIAL_COLUMNS: usize = 80;
+pub const DEFAULT_SCROLLBACK_LINES: usize = 1000;
+// 全角文字の右半分のセル
+const WIDE_CONTINUATION: char = '\0';
+
+use crate::graphics::PixelWriter;
+impl Console {
+    pub const fn new() -> Self {
+        Self {
+            rows: INITIAL_ROWS,
+            columns: INITIAL_COLUMNS,
+            frames: None,
+            initial: [ConsoleChar::DEFAULT; 2 * INITIAL_ROWS * INITIAL_COLUMNS],
+            capacity: INITIAL_ROWS,
+            head: 0,
+            len: INITIAL_ROWS,
+            view_offset: 0,
+            attr: Attributes::DEFAULT,
+            cursor: Cursor {x: 0, y: 0},
+            saved: (Cursor {x: 0, y: 0}, Attributes::DEFAULT),
+            parser: Parser::new(),
+            pending_scroll: 0,
+            dirty: 0..INITIAL_ROWS,
+            cursor_visible: true,
+            rendered_cursor: None,
+            window: None,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+    pub fn fg(&self) -> PixelColor {
+        self.attr.color().fg
+    }
+    pub fn bg(&self) -> PixelColor {
+        self.attr.color().bg
+    }
+
+    // columns x rows にして scrollback_lines 行の履歴を持つ. 今の画面の内容は左上から引き継ぐ.
+    // メモリが確保できなければ false (大きさは変えない)
+    pub fn resize(&mut self, columns: usize, rows: usize, scrollback_lines: usize) -> bool {
+        if columns == 0 || rows == 0 {
+            return false;
+        }
+        let capacity = rows + scrollback_lines;
+        let count = (capacity + rows) * columns;
+        let frames = match Frames::allocate_bytes(count * core::mem::size_of::<ConsoleChar>()) {
+            Some(frames) => frames,
+            None => return false,
+        };
+        let cells = frames.as_mut_ptr() as *mut ConsoleChar;
+        for i in 0..count {
+            unsafe { cells.add(i).write(ConsoleChar::DEFAULT) };
+        }
+        let cells = unsafe { core::slice::from_raw_parts_mut(cells, count) };
+        for y in 0..rows.min(self.rows) {
+            for x in 0..columns.min(self.columns) {
+                cells[y * columns + x] = self.get(y * self.columns + x);
+            }
+        }
+
+        self.frames = Some(frames);
+        (self.columns, self.rows) = (columns, rows);
+        (self.capacity, self.head, self.len) = (capacity, 0, rows);
+        self.view_offset = 0;
+        self.invalidate();
+        self.move_cursor_to(self.cursor.x, self.cursor.y);
+        let saved = self.saved.0;
+        self.saved.0 = Cursor { x: saved.x.min(columns - 1), y: saved.y.min(rows - 1) };
+        true
+    }
+
+    fn cells(&self) -> &[ConsoleChar] {
+        match &self.frames {
+            Some(frames) => unsafe {
+                let count = (self.capacity + self.rows) * self.columns;
+                core::slice::from_raw_parts(frames.as_mut_ptr() as *const ConsoleChar, count)
+            },
+            None => &self.initial,
+        }
+    }
+
+    fn cells_mut(&mut self) -> &mut [ConsoleChar] {
+        match &self.frames {
+            Some(frames) => unsafe {
+                let count = (self.capacity + self.rows) * self.columns;
+                core::slice::from_raw_parts_mut(frames.as_mut_ptr() as *mut ConsoleChar, count)
+            },
+            None => &mut self.initial,
+        }
+    }
+
+    // 画面の index (y * columns + x) のセルの cells 内の位置
+    fn offset(&self, index: usize) -> usize {
+        let line = (self.head + self.len - self.rows + index / self.columns) % self.capacity;
+        line * self.columns + index % self.columns
+    }
+
+    fn get(&self, index: usize) -> ConsoleChar {
+        self.cells()[self.offset(index)]
+    }
+
+    // 描画済みの内容は ring buffer の後ろに置く
+    fn rendered(&self) -> &[ConsoleChar] {
+        let start = self.capacity * self.columns;
+        &self.cells()[start..]
+    }
+
+    fn rendered_mut(&mut self) -> &mut [ConsoleChar] {
+        let start = self.capacity * self.columns;
+        &mut self.cells_mut()[start..]
+    }
+
+    // 全体を描き直させる (描画先を塗りつぶしたときなど)
+    fn invalidate(&mut self) {
+        self.rendered_mut().fill(ConsoleChar::DEFAULT);
+        self.pending_scroll = 0;
+        self.rendered_cursor = None;
+        self.mark_all_dirty();
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty = 0..self.rows;
+    }
+
+    // 画面の index のセルが変わった. 履歴を見ている間は表示上の行がずれる
+    fn mark_dirty(&mut self, index: usize) {
+        let y = index / self.columns + self.view_offset;
+        if y >= self.rows {
+            return;
+        }
+        self.dirty = if self.dirty.is_empty() {
+            y..y + 1
+        } else {
+            self.dirty.start.min(y)..self.dirty.end.max(y + 1)
+        };
+    }
+
+    // lines 行前 (負なら後) の履歴を表示する. 表示は次の render で更新される
+    pub fn scroll_view(&mut self, lines: isize) {
+        let max = (self.len - self.rows) as isize;
+        let view_offset = (self.view_offset as isize + lines).max(0).min(max) as usize;
+        if view_offset != self.view_offset {
+            self.view_offset = view_offset;
+            self.mark_all_dirty();
+        }
+    }
+
+    // 点滅の表示側なら true
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+    }
+
+    // カーソルを描くセル. 全角文字の右半分にいるときは文字全体に描く
+    fn cursor_cell(&self) -> Option<usize> {
+        if !self.cursor_visible || self.view_offset > 0 {
+            return None;
+        }
+        let x = self.cursor.x.min(self.columns - 1);
+        let index = self.cursor.y * self.columns + x;
+        if x > 0 && self.get(index).ch == WIDE_CONTINUATION {
+            Some(index - 1)
+        } else {
+            Some(index)
+        }
+    }
+
+    fn draw_cell(&self, writer: &dyn PixelWriter, font: &dyn Font, index: usize, cell: ConsoleChar, inverse: bool) {
+        // 右半分は左のセルと一緒に描く
+        if cell.ch == WIDE_CONTINUATION {
+            return;
+        }
+        let (font_x, font_y) = font.char_size();
+        let (x, y) = (index % self.columns * font_x, index / self.columns * font_y);
+        let (fg, bg) = if inverse { (cell.color.bg, cell.color.fg) } else { (cell.color.fg, cell.color.bg) };
+        let cells = font::char_width(cell.ch);
+        writer.fill_rect(Vector2D::new(x, y), Vector2D::new(font_x*cells, font_y), &bg);
+        font.write_char(writer, x, y, cell.ch, &fg, &bg);
+        if cell.bold {
+            // 1 pixel ずらして重ねて太く見せる
+            font.write_char(writer, x + 1, y, cell.ch, &fg, &bg);
+        }
+    }
+
+    pub fn is_viewing_history(&self) -> bool {
+        self.view_offset > 0
+    }
+
+    // 前回の render 以降に変わった行とカーソルだけを描く
+    pub fn render(&mut self, writer: &dyn PixelWriter, font: &dyn Font) {
+        let (font_x, font_y) = font.char_size();
+        let (rows, columns) = (self.rows, self.columns);
+        let cursor = self.cursor_cell();
+        // 動いたカーソルや, 一緒にずれてしまうカーソルは先に消す
+        if let Some(index) = self.rendered_cursor {
+            if Some(index) != cursor || self.pending_scroll > 0 {
+                self.draw_cell(writer, font, index, self.rendered()[index], false);
+                self.rendered_cursor = None;
+            }
+        }
+        if self.pending_scroll > 0 {
+            // 描画済みの文字は描き直さずに画面ごとずらす
+            let lines = self.pending_scroll.min(rows);
+            let end = rows * columns;
+            let area = Rectangle::new(
+                Vector2D::new(0, 0),
+                Vector2D::new(columns*font_x, rows*font_y),
+            );
+            writer.scroll_up(area, lines*font_y, &Color::DEFAULT.bg);
+            let rendered = self.rendered_mut();
+            rendered.copy_within(lines*columns..end, 0);
+            rendered[(end - lines*columns)..end].fill(ConsoleChar::DEFAULT);
+            self.pending_scroll = 0;
+        }
+
+        let dirty = core::mem::replace(&mut self.dirty, 0..0);
+        let first_line = self.head + self.len - rows - self.view_offset;
+        for y in dirty {
+            let line = (first_line + y) % self.capacity;
+            for x in 0..columns {
+                let index = y * columns + x;
+                let cell = self.cells()[line * columns + x];
+                if cell == self.rendered()[index] {
+                    continue;
+                }
+                self.rendered_mut()[index] = cell;
+                self.draw_cell(writer, font, index, cell, false);
+                // 上書きされたカーソルは描き直す
+                if let Some(cursor) = self.rendered_cursor {
+                    if (index..index + font::char_width(cell.ch)).contains(&cursor) {
+                        self.rendered_cursor = None;
+                    }
+                }
+            }
+        }
+
+        if let (None, Some(index)) = (self.rendered_cursor, cursor) {
+            self.draw_cell(writer, font, index, self.rendered()[index], true);
+            self.rendered_cursor = Some(index);
+        }
+    }
+
+    pub fn put_string(&mut self, s: &str) {
+        s.chars().for_each(|c| {
+            if let Some(action) = self.parser.advance(c) {
+                self.perform(action);
+            }
+        });
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.put_char(c),
+            Action::Control('\n') => self.new_line(),
+            Action::Control('\r') => self.cursor.x = 0,
+            Action::Control('\x08') => self.move_cursor_backward(),
+            Action::Control('\t') => self.cursor.x = ((self.cursor.x / 8 + 1) * 8).min(self.columns - 1),
+            Action::Control(_) => {},
+            Action::Escape('7') => self.saved = (self.cursor, self.attr),
+            Action::Escape('8') => (self.cursor, self.attr) = self.saved,
+            // 初期状態に戻す
+            Action::Escape('c') => {
+                self.attr = Attributes::DEFAULT;
+                self.erase(0, self.rows * self.columns);
+                self.cursor = Cursor {x: 0, y: 0};
+            },
+            Action::Escape(_) => {},
+            // ESC [ ? 25 l (カーソルの表示) などには対応しない
+            Action::Csi { private: true, .. } => {},
+            Action::Csi { params, action, .. } => self.perform_csi(&params, action),
+        }
+    }
+
+    fn perform_csi(&mut self, params: &Params, action: char) {
+        let n = params.get_or(0, 1) as usize;
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        match action {
+            'A' => self.move_cursor_to(x, y.saturating_sub(n)),
+            'B' => self.move_cursor_to(x, y + n),
+            'C' => self.move_cursor_to(x + n, y),
+            'D' => self.move_cursor_to(x.saturating_sub(n), y),
+            'E' => self.move_cursor_to(0, y + n),
+            'F' => self.move_cursor_to(0, y.saturating_sub(n)),
+            // 位置は 1 から数える
+            'G' => self.move_cursor_to(n - 1, y),
+            'd' => self.move_cursor_to(x, n - 1),
+            'H' | 'f' => self.move_cursor_to(params.get_or(1, 1) as usize - 1, n - 1),
+            'J' => {
+                let (cursor, end) = (y * self.columns + x.min(self.columns - 1), self.rows * self.columns);
+                match params.get(0).unwrap_or(0) {
+                    0 => self.erase(cursor, end),
+                    1 => self.erase(0, cursor + 1),
+                    2 | 3 => self.erase(0, end),
+                    _ => {},
+                }
+            },
+            'K' => {
+                let line = y * self.columns;
+                match params.get(0).unwrap_or(0) {
+                    0 => self.erase(line + x, line + self.columns),
+                    1 => self.erase(line, line + x + 1),
+                    2 => self.erase(line, line + self.columns),
+                    _ => {},
+                }
+            },
+            'm' => self.select_graphic_rendition(params),
+            's' => self.saved = (self.cursor, self.attr),
+            'u' => (self.cursor, self.attr) = self.saved,
+            _ => {},
+        }
+    }
+
+    // SGR (ESC [ ... m)
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let mut i = 0;
+        // ESC [ m は ESC [ 0 m と同じ
+        let len = params.len().max(1);
+        while i < len {
+            match params.get(i).unwrap_or(0) {
+                0 => self.attr = Attributes::DEFAULT,
+                1 => self.attr.bold = true,
+                22 => self.attr.bold = false,
+                7 => self.attr.inverse = true,
+                27 => self.attr.inverse = false,
+                p @ 30..=37 => self.attr.fg = AnsiColor::Indexed(p as u8 - 30),
+                38 => if let Some(color) = extended_color(params, &mut i) { self.attr.fg = color },
+                39 => self.attr.fg = AnsiColor::Default,
+                p @ 40..=47 => self.attr.bg = AnsiColor::Indexed(p as u8 - 40),
+                48 => if let Some(color) = extended_color(params, &mut i) { self.attr.bg = color },
+                49 => self.attr.bg = AnsiColor::Default,
+                p @ 90..=97 => self.attr.fg = AnsiColor::Indexed(p as u8 - 90 + 8),
+                p @ 100..=107 => self.attr.bg = AnsiColor::Indexed(p as u8 - 100 + 8),
+                _ => {},
+            }
+            i += 1;
+        }
+    }
+
+    // 行に収まらなければ次の行に折り返す. cursor.x は columns (行末の次) になることがある
+    fn put_char(&mut self, c: char) {
+        let width = font::char_width(c).min(self.columns);
+        if self.cursor.x + width > self.columns {
+            self.new_line();
+        }
+        let index = self.cursor.y * self.columns + self.cursor.x;
+        let (color, bold) = (self.attr.color(), self.attr.bold);
+        self.set_cell(index, ConsoleChar { ch: c, color, bold });
+        if width == 2 {
+            self.set_cell(index + 1, ConsoleChar { ch: WIDE_CONTINUATION, color, bold });
+        }
+        self.cursor.x += width;
+    }
+
+    fn set(&mut self, index: usize, cell: ConsoleChar) {
+        let offset = self.offset(index);
+        self.cells_mut()[offset] = cell;
+        self.mark_dirty(index);
+    }
+
+    // 全角文字の片側だけを上書きしたら, もう片側を空白にする
+    fn set_cell(&mut self, index: usize, cell: ConsoleChar) {
+        let end = self.rows * self.columns;
+        if self.get(index).ch == WIDE_CONTINUATION && index > 0 && cell.ch != WIDE_CONTINUATION {
+            let lead = self.get(index - 1);
+            self.set(index - 1, ConsoleChar { ch: ' ', ..lead });
+        }
+        if index + 1 < end && self.get(index + 1).ch == WIDE_CONTINUATION {
+            let continuation = self.get(index + 1);
+            self.set(index + 1, ConsoleChar { ch: ' ', ..continuation });
+        }
+        self.set(index, cell);
+    }
+
+    // 画面の start..end のセルを空白にする
+    fn erase(&mut self, start: usize, end: usize) {
+        let end = end.min(self.rows * self.columns);
+        if start >= end {
+            return;
+        }
+        let blank = ConsoleChar { ch: ' ', color: self.attr.erase_color(), bold: false };
+        self.set_cell(start, blank);
+        self.set_cell(end - 1, blank);
+        (start..end).for_each(|index| self.set(index, blank));
+    }
+
+    fn move_cursor_to(&mut self, x: usize, y: usize) {
+        self.cursor.x = x.min(self.columns - 1);
+        self.cursor.y = y.min(self.rows - 1);
+    }
+
+    fn move_cursor_backward(&mut self) {
+        if self.cursor.x > 0 {
+            self.cursor.x -= 1;
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.cursor.x = 0;
+        if self.cursor.y == self.rows - 1 {
+            // スクロールの必要あり
+            self.scroll_up();
+        } else {
+            // スクロールの必要なし
+            self.cursor.y += 1;
+        }
+    }
+
+    // 一番上の行は scrollback に残し, ring buffer が一杯なら一番古い行を捨てる
+    fn scroll_up(&mut self) {
+        if self.len < self.capacity {
+            self.len += 1;
+        } else {
+            self.head = (self.head + 1) % self.capacity;
+        }
+        if self.view_offset == 0 {
+            self.pending_scroll += 1;
+            // まだ描いていない行も一緒にずれる
+            let dirty = self.dirty.start.saturating_sub(1)..self.dirty.end.saturating_sub(1);
+            self.dirty = if dirty.is_empty() { 0..0 } else { dirty };
+        } else if self.view_offset < self.len - self.rows {
+            // 履歴を見ている間は表示している行を変えない
+            self.view_offset += 1;
+        } else {
+            // 一番古い行を捨てたので表示がずれる
+            self.mark_all_dirty();
+        }
+        let end = self.rows * self.columns;
+        let blank = ConsoleChar { ch: ' ', color: self.attr.erase_color(), bold: false };
+        (end - self.columns..end).for_each(|index| self.set(index, blank));
+    }
+
+}
+
+// SGR 38, 48 に続く "5;n" (256 色) か "2;r;g;b" (truecolor). i は使った最後の parameter に進める
+fn extended_color(params: &Params, i: &mut usize) -> Option<AnsiColor> {
+    match params.get(*i + 1) {
+        Some(5) => {
+            let index = params.get(*i + 2)?;
+            *i += 2;
+            Some(AnsiColor::Indexed(index.min(255) as u8))
+        },
+        Some(2) => {
+            let channel = |j| params.get(*i + j).map(|v: u16| v.min(255) as u8);
+            let color = PixelColor::new(channel(2)?, channel(3)?, channel(4)?);
+            *i += 4;
+            Some(AnsiColor::Rgb(color))
+        },
+        _ => None,
+    }
+}
+
+use core::fmt;
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.put_string(s);
+        Ok(())
+    }
+}
+
+#[macro_export]
+macro_rules! kprint {
+    ($($arg:tt)*) => ($crate::console::_kprint(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! kprintln {
+    () => ($crate::kprint!("\n"));
+    ($($arg:tt)*) => ($crate::kprint!("{}\n", format_args!($($arg)*)));
+}
+
+
+// need static CONSOLE for kprint! macro.
+// TODO: 1. implement spin mutex -> FINISHED
+// TODO: 2. initialize WRITER (frame buffer) in kernel_main -> FINISHED
+// TODO: 3. really need spin mutex?
+// TODO: 4. is the implementation correct?
+pub static CONSOLE: IrqSpinMutex<Console> = IrqSpinMutex::new(
+    Console::new()
+);
+
+// hankaku.bin にない文字は add_console_font で登録した font から探す
+pub static CONSOLE_FONT: FallbackFont<ShinonomeFont> = FallbackFont::new(ShinonomeFont::new());
+
+// 日本語などを表示するための font を登録する. 登録しきれなかったら false
+pub fn add_console_font(font: &'static dyn Font) -> bool {
+    if !CONSOLE_FONT.add_fallback(font) {
+        return false;
+    }
+    // 代わりの枠で描いていた文字を描き直す
+    let mut console = CONSOLE.lock();
+    console.rendered_mut().fill(ConsoleChar { ch: WIDE_CONTINUATION, ..ConsoleChar::DEFAULT });
+    console.mark_all_dirty();
+    refresh(&mut console);
+    true
+}
+
+// console の window と画面の端との間隔
+const WINDOW_MARGIN: usize = 8;
+
+// init_layers の後に呼ぶ. 画面に収まる大きさの console 用の window を作って描画先にする.
+// layer がなければ画面全体に直接描画する
+pub fn init_console(scrollback_lines: usize) {
+    let mut console = CONSOLE.lock();
+    let (font_x, font_y) = CONSOLE_FONT.char_size();
+    let (screen_width, screen_height) = unsafe { WRITER.lock().assume_init() }.resolution();
+    let (width, height) = window::client_size(
+        screen_width.saturating_sub(2 * WINDOW_MARGIN),
+        screen_height.saturating_sub(2 * WINDOW_MARGIN),
+    );
+    if LAYER_MANAGER.lock().is_some() {
+        console.resize(width / font_x, height / font_y, scrollback_lines);
+    } else {
+        console.resize(screen_width / font_x, screen_height / font_y, scrollback_lines);
+    }
+
+    let (width, height) = (console.columns * font_x, console.rows * font_y);
+    let pos = Vector2D::new(WINDOW_MARGIN, WINDOW_MARGIN);
+    console.window = window::new_window("Console", width, height, pos);
+    // 描画先には何も描かれていないので全体を描き直す
+    console.invalidate();
+    match console.window {
+        Some(id) => window::draw_in_window(id, |client| {
+            client.fill_rect(Vector2D::new(0, 0), Vector2D::new(width, height), &Color::DEFAULT.bg);
+        }),
+        None => {
+            let writer = unsafe { WRITER.lock().assume_init() };
+            writer.fill_rect(Vector2D::new(0, 0), Vector2D::new(width, height), &Color::DEFAULT.bg);
+        },
+    }
+    refresh(&mut console);
+}
+
+// 変わったセルを描画先に反映する
+fn refresh(console: &mut Console) {
+    match console.window {
+        Some(id) => window::draw_in_window(id, |client| console.render(client, &CONSOLE_FONT)),
+        None => {
+            let writer = WRITER.lock();
+            let writer = unsafe { writer.assume_init() };
+            console.render(writer, &CONSOLE_FONT);
+            crate::graphics::flush_screen();
+        },
+    }
+}
+
+// カーソルの点滅の間隔 (timer の tick 数)
+const CURSOR_BLINK_TICKS: u64 = timer::TIMER_FREQUENCY / 2;
+
+// main loop から呼ぶ. timer が動いていなければカーソルは点いたままになる
+pub fn blink_cursor(ticks: u64) {
+    let visible = ticks / CURSOR_BLINK_TICKS % 2 == 0;
+    let mut console = CONSOLE.lock();
+    if console.cursor_visible != visible {
+        console.set_cursor_visible(visible);
+        refresh(&mut console);
+    }
+}
+
+// mouse の wheel 1 目盛りで動かす行数
+const WHEEL_LINES: isize = 3;
+
+// lines 行前 (負なら後) の履歴を表示する
+pub fn scroll_console(lines: isize) {
+    let mut console = CONSOLE.lock();
+    console.scroll_view(lines);
+    refresh(&mut console);
+}
+
+// Shift+PageUp / PageDown 用. 1 画面分 (1 行は重ねる) 動かす
+pub fn scroll_console_page(up: bool) {
+    let lines = CONSOLE.lock().rows().saturating_sub(1).max(1) as isize;
+    scroll_console(if up { lines } else { -lines });
+}
+
+// mouse の wheel (正で奥に回したとき) で, cursor の下に console の window があれば履歴をスクロールする
+pub fn handle_wheel(pos: Vector2D<usize>, wheel: i8) {
+    let console_window = CONSOLE.lock().window;
+    if console_window.is_some() && window::window_at(pos) == console_window {
+        scroll_console(wheel as isize * WHEEL_LINES);
+    }
+}
+
+use crate::graphics::WRITER;
+pub fn _kprint(args: fmt::Arguments) {
+    // framebuffer が壊れていても, headless でもログを追えるように serial にも出す
+    crate::serial::_print(args);
+    write_console(args);
+}
+
+// serial には出さず console にだけ書く
+pub fn write_console(args: fmt::Arguments) {
+    use core::fmt::Write;
+    let mut console = CONSOLE.lock();
+    console.write_fmt(args).unwrap();
+    refresh(&mut console);
+}
@@ -0,0 +1,253 @@
+//! Glyph Bitmap Distribution Format
+//! text のままでは引けないので, 読み込み時に glyph の表と bitmap を frame に展開する
+//! 参考: https://adobe-type-tools.github.io/font-tech-notes/pdfs/5005.BDF_Spec.pdf
+
+use super::{Font, Glyph};
+use crate::memory::Frames;
+
+#[derive(Debug)]
+pub enum BdfError {
+    // STARTFONT や FONTBOUNDINGBOX がない
+    InvalidHeader,
+    // BBX や BITMAP の行が読めない
+    InvalidGlyph,
+    OutOfMemory,
+}
+pub type Result<T> = core::result::Result<T, BdfError>;
+
+// frame の先頭に code point 順で並べる
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct GlyphEntry {
+    code: u32,
+    // bitmap 領域の先頭からの位置
+    offset: u32,
+    width: u16,
+    height: u16,
+    x_offset: u16,
+    y_offset: u16,
+    advance: u16,
+    _reserved: u16,
+}
+
+// BBX (幅, 高さ, 原点からの x, y)
+#[derive(Clone, Copy)]
+struct BoundingBox {
+    width: usize,
+    height: usize,
+    x: isize,
+    y: isize,
+}
+
+pub struct BdfFont {
+    frames: Frames,
+    glyph_count: usize,
+    cell_width: usize,
+    cell_height: usize,
+}
+
+impl BdfFont {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header = parse_header(data)?;
+
+        let (mut glyph_count, mut bitmap_size) = (0, 0_usize);
+        for_each_char(data, &header, &mut |_, _, bbx, _| {
+            glyph_count += 1;
+            // BBX の値はそのまま信用できないので, 溢れたら壊れた glyph とする
+            bitmap_size = ((bbx.width + 7) / 8)
+                .checked_mul(bbx.height)
+                .and_then(|size| bitmap_size.checked_add(size))
+                .ok_or(BdfError::InvalidGlyph)?;
+            Ok(())
+        })?;
+
+        let table_size = glyph_count * core::mem::size_of::<GlyphEntry>();
+        let total_size = table_size.checked_add(bitmap_size).ok_or(BdfError::OutOfMemory)?;
+        let frames = Frames::allocate_bytes(total_size.max(1)).ok_or(BdfError::OutOfMemory)?;
+        let mut font = Self { frames, glyph_count, cell_width: header.bbx.width, cell_height: header.ascent + header.descent };
+
+        let (entries, bitmaps) = font.tables_mut();
+        let (mut i, mut offset) = (0, 0);
+        for_each_char(data, &header, &mut |code, advance, bbx, bitmap_text| {
+            let stride = (bbx.width + 7) / 8;
+            let bitmap = &mut bitmaps[offset..offset + stride * bbx.height];
+            // BBX 0 0 0 0 (空白など) には bitmap の行がない
+            if stride != 0 && bbx.height != 0 {
+                decode_bitmap(bitmap_text, stride, bitmap)?;
+            }
+            // 原点 (baseline の左端) からの位置をセルの左上からの位置にする
+            entries[i] = GlyphEntry {
+                code,
+                offset: offset as u32,
+                width: bbx.width as u16,
+                height: bbx.height as u16,
+                x_offset: bbx.x.max(0) as u16,
+                y_offset: (header.ascent as isize - bbx.y - bbx.height as isize).max(0) as u16,
+                advance: advance as u16,
+                _reserved: 0,
+            };
+            i += 1;
+            offset += bitmap.len();
+            Ok(())
+        })?;
+        entries.sort_unstable_by_key(|entry| entry.code);
+        Ok(font)
+    }
+
+    fn tables(&self) -> (&[GlyphEntry], &[u8]) {
+        let ptr = self.frames.as_mut_ptr();
+        let table_size = self.glyph_count * core::mem::size_of::<GlyphEntry>();
+        unsafe {
+            (
+                core::slice::from_raw_parts(ptr as *const GlyphEntry, self.glyph_count),
+                core::slice::from_raw_parts(ptr.add(table_size), self.frames.len() - table_size),
+            )
+        }
+    }
+
+    fn tables_mut(&mut self) -> (&mut [GlyphEntry], &mut [u8]) {
+        let ptr = self.frames.as_mut_ptr();
+        let table_size = self.glyph_count * core::mem::size_of::<GlyphEntry>();
+        unsafe {
+            (
+                core::slice::from_raw_parts_mut(ptr as *mut GlyphEntry, self.glyph_count),
+                core::slice::from_raw_parts_mut(ptr.add(table_size), self.frames.len() - table_size),
+            )
+        }
+    }
+
+    fn entry(&self, c: char) -> Option<&GlyphEntry> {
+        let (entries, _) = self.tables();
+        let i = entries.binary_search_by_key(&(c as u32), |entry| entry.code).ok()?;
+        Some(&entries[i])
+    }
+}
+
+impl Font for BdfFont {
+    fn char_size(&self) -> (usize, usize) {
+        (self.cell_width, self.cell_height)
+    }
+
+    fn advance(&self, c: char) -> usize {
+        self.entry(c).map_or(self.cell_width, |entry| entry.advance as usize)
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        let entry = self.entry(c)?;
+        let (_, bitmaps) = self.tables();
+        let (width, height) = (entry.width as usize, entry.height as usize);
+        let bitmap = &bitmaps[entry.offset as usize..][..(width + 7) / 8 * height];
+        Some(Glyph::new(bitmap, width, height, entry.x_offset as usize, entry.y_offset as usize))
+    }
+}
+
+struct Header {
+    bbx: BoundingBox,
+    ascent: usize,
+    descent: usize,
+}
+
+fn lines(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+// keyword と, 空白で区切られた残り
+fn split_keyword(line: &[u8]) -> (&[u8], impl Iterator<Item = &[u8]>) {
+    let mut words = line.split(|b| b.is_ascii_whitespace()).filter(|word| !word.is_empty());
+    (words.next().unwrap_or(&[]), words)
+}
+
+fn parse_int(word: Option<&[u8]>) -> Option<isize> {
+    let word = core::str::from_utf8(word?).ok()?;
+    word.parse().ok()
+}
+
+fn parse_bbx<'a>(mut words: impl Iterator<Item = &'a [u8]>) -> Option<BoundingBox> {
+    let width = parse_int(words.next())?;
+    let height = parse_int(words.next())?;
+    if width < 0 || height < 0 {
+        return None;
+    }
+    Some(BoundingBox { width: width as usize, height: height as usize, x: parse_int(words.next())?, y: parse_int(words.next())? })
+}
+
+fn parse_header(data: &[u8]) -> Result<Header> {
+    if !data.starts_with(b"STARTFONT") {
+        return Err(BdfError::InvalidHeader);
+    }
+    let (mut bbx, mut ascent, mut descent) = (None, None, None);
+    for line in lines(data) {
+        let (keyword, mut words) = split_keyword(line);
+        match keyword {
+            b"FONTBOUNDINGBOX" => bbx = parse_bbx(words),
+            b"FONT_ASCENT" => ascent = parse_int(words.next()),
+            b"FONT_DESCENT" => descent = parse_int(words.next()),
+            b"STARTCHAR" => break,
+            _ => {},
+        }
+    }
+    let bbx = bbx.ok_or(BdfError::InvalidHeader)?;
+    // 指定がなければ bounding box から求める
+    let ascent = ascent.unwrap_or(bbx.height as isize + bbx.y).max(0) as usize;
+    let descent = descent.unwrap_or(-bbx.y).max(0) as usize;
+    Ok(Header { bbx, ascent, descent })
+}
+
+// STARTCHAR から ENDCHAR までを読み, (code point, advance, BBX, BITMAP の行) を f に渡す. ENCODING が負の glyph は飛ばす
+fn for_each_char(
+    data: &[u8],
+    header: &Header,
+    f: &mut dyn FnMut(u32, usize, BoundingBox, &[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut code: Option<u32> = None;
+    let mut advance = header.bbx.width;
+    let mut bbx = header.bbx;
+    let mut offset = 0;
+    let mut bitmap_start = None;
+    // CRLF でも offset がずれないように, \r を取り除く前の長さで数える
+    for raw in data.split(|&b| b == b'\n') {
+        let line_start = offset;
+        offset += raw.len() + 1;
+        let line = raw.strip_suffix(b"\r").unwrap_or(raw);
+        let (keyword, mut words) = split_keyword(line);
+        match keyword {
+            b"STARTCHAR" => {
+                code = None;
+                advance = header.bbx.width;
+                bbx = header.bbx;
+            },
+            b"ENCODING" => {
+                code = parse_int(words.next()).filter(|&code| code >= 0).map(|code| code as u32);
+            },
+            b"DWIDTH" => {
+                advance = parse_int(words.next()).ok_or(BdfError::InvalidGlyph)?.max(0) as usize;
+            },
+            b"BBX" => bbx = parse_bbx(words).ok_or(BdfError::InvalidGlyph)?,
+            b"BITMAP" => bitmap_start = Some(offset),
+            b"ENDCHAR" => {
+                let start = bitmap_start.take().ok_or(BdfError::InvalidGlyph)?;
+                if let Some(code) = code.filter(|&code| char::from_u32(code).is_some()) {
+                    f(code, advance, bbx, &data[start..line_start])?;
+                }
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+// 16 進数の行を stride byte ずつ bitmap に詰める
+fn decode_bitmap(text: &[u8], stride: usize, bitmap: &mut [u8]) -> Result<()> {
+    let hex = |c: u8| (c as char).to_digit(16).map(|v| v as u8).ok_or(BdfError::InvalidGlyph);
+    let mut rows = lines(text).filter(|line| !line.is_empty());
+    for row in bitmap.chunks_exact_mut(stride) {
+        let line = rows.next().ok_or(BdfError::InvalidGlyph)?;
+        for (i, byte) in row.iter_mut().enumerate() {
+            *byte = match line.get(2 * i..2 * i + 2) {
+                Some(digits) => hex(digits[0])? << 4 | hex(digits[1])?,
+                None => 0,
+            };
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+//! font と glyph の描画
+//! ShinonomeFont (hankaku.bin) の他に PSF2, BDF の font を読み込める.
+//! FallbackFont は glyph のない文字を登録された順に他の font で探し, どれにもなければ代替の枠を描く.
+
+pub mod psf2;
+pub mod bdf;
+
+use super::{PixelColor, PixelWriter, Vector2D};
+use crate::sync::SpinMutex;
+use crate::utils::fixed_vec::FixedVec;
+
+const MAX_FALLBACK_FONTS: usize = 4;
+
+// 1 bit 1 pixel, 各行は byte 境界から始まり上位 bit が左
+#[derive(Clone, Copy)]
+pub struct Glyph<'a> {
+    bitmap: &'a [u8],
+    width: usize,
+    height: usize,
+    // 1 行の byte 数
+    stride: usize,
+    // セルの左上からの位置
+    x_offset: usize,
+    y_offset: usize,
+}
+
+impl<'a> Glyph<'a> {
+    pub fn new(bitmap: &'a [u8], width: usize, height: usize, x_offset: usize, y_offset: usize) -> Self {
+        Self { bitmap, width, height, stride: (width + 7) / 8, x_offset, y_offset }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        self.bitmap.get(y * self.stride + x / 8).map_or(false, |&b| (b << (x % 8)) & 0x80 != 0)
+    }
+
+    // 背景は塗らない
+    pub fn draw(&self, writer: &dyn PixelWriter, x: usize, y: usize, fg: &PixelColor) {
+        for dy in 0..self.height {
+            for dx in 0..self.width {
+                if self.is_set(dx, dy) {
+                    writer.draw_pixel(x + self.x_offset + dx, y + self.y_offset + dy, fg);
+                }
+            }
+        }
+    }
+}
+
+pub trait Font {
+    // 半角 1 文字分のセルの大きさ
+    fn char_size(&self) -> (usize, usize);
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>>;
+
+    // c を描いた後に x を進める幅. 全角の文字はセル 2 つ分
+    fn advance(&self, c: char) -> usize {
+        self.char_size().0 * char_width(c)
+    }
+
+    // c を (x, y) を左上とするセルに描き, advance を返す
+    fn write_char(&self, writer: &dyn PixelWriter, x: usize, y: usize, c: char, fg: &PixelColor, _bg: &PixelColor) -> usize {
+        let advance = self.advance(c);
+        match self.glyph(c) {
+            Some(glyph) => glyph.draw(writer, x, y, fg),
+            None => draw_replacement(writer, x, y, advance, self.char_size().1, fg),
+        }
+        advance
+    }
+
+    fn write_ascii(&self, writer: &dyn PixelWriter, x: usize, y: usize, c: char, fg: &PixelColor, bg: &PixelColor) {
+        self.write_char(writer, x, y, c, fg, bg);
+    }
+
+    // 描いた幅を返す
+    fn write_string(&self, writer: &dyn PixelWriter, x: usize, y: usize, s: &str, fg: &PixelColor, bg: &PixelColor) -> usize {
+        s.chars().fold(0, |dx, c| dx + self.write_char(writer, x + dx, y, c, fg, bg))
+    }
+}
+
+// glyph がない文字の代わりに, セルより一回り小さい枠を描く
+fn draw_replacement(writer: &dyn PixelWriter, x: usize, y: usize, width: usize, height: usize, fg: &PixelColor) {
+    if width < 4 || height < 4 {
+        return;
+    }
+    writer.draw_rect(Vector2D::new(x + 1, y + 1), Vector2D::new(width - 2, height - 2), fg);
+}
+
+// 表示に使うセルの数 (East Asian Width が W, F のものを 2 とする. 主な範囲のみ)
+pub fn char_width(c: char) -> usize {
+    const WIDE: [(u32, u32); 13] = [
+        (0x1100, 0x115f),   // Hangul Jamo
+        (0x2e80, 0x303e),   // CJK 部首, 記号
+        (0x3041, 0x33ff),   // ひらがな, カタカナ, CJK 互換
+        (0x3400, 0x4dbf),   // CJK 統合漢字拡張 A
+        (0x4e00, 0x9fff),   // CJK 統合漢字
+        (0xa000, 0xa4cf),   // Yi
+        (0xac00, 0xd7a3),   // Hangul
+        (0xf900, 0xfaff),   // CJK 互換漢字
+        (0xfe30, 0xfe4f),   // CJK 互換形
+        (0xff00, 0xff60),   // 全角英数, 記号
+        (0xffe0, 0xffe6),   // 全角記号
+        (0x1f300, 0x1f64f), // 絵文字
+        (0x20000, 0x3fffd), // CJK 統合漢字拡張 B 以降
+    ];
+    let c = c as u32;
+    if WIDE.iter().any(|&(start, end)| start <= c && c <= end) { 2 } else { 1 }
+}
+
+// 8x16 の hankaku.bin (JIS X 0201 の 256 文字). 1 文字ごとにセルの右と下に 2 pixel の余白を取る
+pub struct ShinonomeFont {
+    font: &'static [u8]
+}
+
+impl ShinonomeFont {
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 16;
+
+    pub const fn new() -> Self {
+        Self {
+            font: include_bytes!("../../../assets/hankaku.bin"),
+        }
+    }
+
+    // Unicode から JIS X 0201 の位置へ
+    fn index(c: char) -> Option<usize> {
+        match c as u32 {
+            c @ 0x20..=0x7e => Some(c as usize),
+            // 半角カタカナ
+            c @ 0xff61..=0xff9f => Some((c - 0xff61 + 0xa1) as usize),
+            _ => None,
+        }
+    }
+}
+
+impl Font for ShinonomeFont {
+    fn char_size(&self) -> (usize, usize) {
+        (Self::WIDTH + 2, Self::HEIGHT + 2)
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        let size = Self::HEIGHT * Self::WIDTH / 8;
+        let bitmap = self.font.get(Self::index(c)? * size..)?.get(..size)?;
+        Some(Glyph::new(bitmap, Self::WIDTH, Self::HEIGHT, 0, 0))
+    }
+}
+
+// primary にない文字を fallback の font で描く. セルの大きさと advance は primary に合わせる
+pub struct FallbackFont<F: Font> {
+    primary: F,
+    fallbacks: SpinMutex<FixedVec<'static, &'static dyn Font, MAX_FALLBACK_FONTS>>,
+}
+
+impl<F: Font> FallbackFont<F> {
+    pub const fn new(primary: F) -> Self {
+        Self { primary, fallbacks: SpinMutex::new(FixedVec::new()) }
+    }
+
+    // 先に登録した font が優先される. 上限を超えたら false
+    pub fn add_fallback(&self, font: &'static dyn Font) -> bool {
+        unsafe { self.fallbacks.lock().try_push(font).is_ok() }
+    }
+}
+
+impl<F: Font> Font for FallbackFont<F> {
+    fn char_size(&self) -> (usize, usize) {
+        self.primary.char_size()
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        if let Some(glyph) = self.primary.glyph(c) {
+            return Some(glyph);
+        }
+        let fallbacks = self.fallbacks.lock();
+        let found = fallbacks.as_slice().iter().find_map(|font| font.glyph(c));
+        found
+    }
+}
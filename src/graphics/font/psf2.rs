@@ -0,0 +1,132 @@
+//! PC Screen Font version 2
+//! 参考: https://www.win.tue.nl/~aeb/linux/kbd/font-formats-1.html
+
+use super::{Font, Glyph};
+use crate::memory::Frames;
+
+const MAGIC: u32 = 0x864a_b572;
+const HEADER_SIZE: usize = 32;
+const FLAG_HAS_UNICODE_TABLE: u32 = 1;
+// unicode table の区切り
+const SEQUENCE_START: u8 = 0xfe;
+const SEPARATOR: u8 = 0xff;
+
+#[derive(Debug)]
+pub enum Psf2Error {
+    InvalidMagic,
+    Truncated,
+    OutOfMemory,
+}
+pub type Result<T> = core::result::Result<T, Psf2Error>;
+
+// unicode table から作る (code point, glyph index) の表. code point で sort 済み
+struct UnicodeMap {
+    frames: Frames,
+    len: usize,
+}
+
+impl UnicodeMap {
+    fn entries(&self) -> &[(u32, u32)] {
+        unsafe { core::slice::from_raw_parts(self.frames.as_mut_ptr() as *const (u32, u32), self.len) }
+    }
+
+    fn entries_mut(&mut self) -> &mut [(u32, u32)] {
+        unsafe { core::slice::from_raw_parts_mut(self.frames.as_mut_ptr() as *mut (u32, u32), self.len) }
+    }
+
+    fn lookup(&self, c: char) -> Option<usize> {
+        let entries = self.entries();
+        entries.binary_search_by_key(&(c as u32), |&(code, _)| code)
+            .ok()
+            .map(|i| entries[i].1 as usize)
+    }
+}
+
+pub struct Psf2Font<'a> {
+    glyphs: &'a [u8],
+    glyph_count: usize,
+    glyph_size: usize,
+    width: usize,
+    height: usize,
+    // なければ code point をそのまま glyph の index とする
+    unicode: Option<UnicodeMap>,
+}
+
+impl<'a> Psf2Font<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let field = |i: usize| -> Result<u32> {
+            let bytes = data.get(i * 4..i * 4 + 4).ok_or(Psf2Error::Truncated)?;
+            Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+        if field(0)? != MAGIC {
+            return Err(Psf2Error::InvalidMagic);
+        }
+        let header_size = (field(2)? as usize).max(HEADER_SIZE);
+        let flags = field(3)?;
+        let glyph_count = field(4)? as usize;
+        let glyph_size = field(5)? as usize;
+        let (height, width) = (field(6)? as usize, field(7)? as usize);
+
+        // header の値で溢れるなら, その大きさのデータはない
+        let glyphs_end = glyph_count
+            .checked_mul(glyph_size)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or(Psf2Error::Truncated)?;
+        let glyphs = data.get(header_size..glyphs_end).ok_or(Psf2Error::Truncated)?;
+        let unicode = if flags & FLAG_HAS_UNICODE_TABLE != 0 {
+            Some(parse_unicode_table(&data[glyphs_end..], glyph_count)?)
+        } else {
+            None
+        };
+        Ok(Self { glyphs, glyph_count, glyph_size, width, height, unicode })
+    }
+}
+
+impl Font for Psf2Font<'_> {
+    fn char_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn advance(&self, _c: char) -> usize {
+        self.width
+    }
+
+    fn glyph(&self, c: char) -> Option<Glyph<'_>> {
+        let index = match &self.unicode {
+            Some(unicode) => unicode.lookup(c)?,
+            None => c as usize,
+        };
+        if index >= self.glyph_count {
+            return None;
+        }
+        let bitmap = &self.glyphs[index * self.glyph_size..(index + 1) * self.glyph_size];
+        Some(Glyph::new(bitmap, self.width, self.height, 0, 0))
+    }
+}
+
+// glyph ごとに UTF-8 の文字が並び, SEPARATOR で終わる. SEQUENCE_START 以降の合成列は使わない
+fn parse_unicode_table(table: &[u8], glyph_count: usize) -> Result<UnicodeMap> {
+    let for_each_entry = |f: &mut dyn FnMut(u32, u32)| {
+        for (index, entry) in table.split(|&b| b == SEPARATOR).take(glyph_count).enumerate() {
+            let singles = entry.split(|&b| b == SEQUENCE_START).next().unwrap_or(&[]);
+            if let Ok(s) = core::str::from_utf8(singles) {
+                s.chars().for_each(|c| f(c as u32, index as u32));
+            }
+        }
+    };
+
+    let mut len = 0;
+    for_each_entry(&mut |_, _| len += 1);
+    let frames = Frames::allocate_bytes(len.max(1) * core::mem::size_of::<(u32, u32)>())
+        .ok_or(Psf2Error::OutOfMemory)?;
+    let mut map = UnicodeMap { frames, len };
+
+    let entries = map.entries_mut();
+    let mut i = 0;
+    for_each_entry(&mut |code, index| {
+        entries[i] = (code, index);
+        i += 1;
+    });
+    entries.sort_unstable_by_key(|&(code, _)| code);
+    Ok(map)
+}
@@ -0,0 +1,393 @@
+//! BMP (無圧縮, RLE8, RLE4, BITFIELDS) と binary PPM (P6) の decoder
+//! decode した画像は frame に確保した PixelColor の配列として持ち, PixelWriter::blit で描画できる
+
+use super::{PixelColor, PixelWriter, Vector2D};
+use crate::memory::Frames;
+
+// これより大きい画像は扱わない (16384 * 16384 * 4 byte = 1GiB)
+const MAX_DIMENSION: usize = 16384;
+
+#[derive(Debug)]
+pub enum ImageError {
+    // BMP でも PPM でもない
+    UnknownFormat,
+    // header や pixel data がファイルの途中で切れている
+    Truncated,
+    InvalidHeader,
+    // 対応していない bit 数や圧縮形式
+    Unsupported(&'static str),
+    OutOfMemory,
+}
+pub type Result<T> = core::result::Result<T, ImageError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleFilter {
+    Nearest,
+    Bilinear,
+}
+
+pub struct Image {
+    // pixels が指すメモリ
+    frames: Frames,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    // 全体を color で塗った画像
+    pub fn new(width: usize, height: usize, color: PixelColor) -> Result<Self> {
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(ImageError::Unsupported("image size"));
+        }
+        let frames = Frames::allocate_bytes(width * height * core::mem::size_of::<PixelColor>())
+            .ok_or(ImageError::OutOfMemory)?;
+        let ptr = frames.as_mut_ptr() as *mut PixelColor;
+        for i in 0..width * height {
+            unsafe { ptr.add(i).write(color) };
+        }
+        Ok(Self { frames, width, height })
+    }
+
+    // 先頭の magic で形式を判別する
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        match data.get(..2) {
+            Some(b"BM") => Self::decode_bmp(data),
+            Some(b"P6") => Self::decode_ppm(data),
+            _ => Err(ImageError::UnknownFormat),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // 左上から行ごとに並ぶ
+    pub fn pixels(&self) -> &[PixelColor] {
+        unsafe { core::slice::from_raw_parts(self.frames.as_mut_ptr() as *const PixelColor, self.width * self.height) }
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [PixelColor] {
+        unsafe { core::slice::from_raw_parts_mut(self.frames.as_mut_ptr() as *mut PixelColor, self.width * self.height) }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> PixelColor {
+        self.pixels()[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: PixelColor) {
+        let width = self.width;
+        self.pixels_mut()[y * width + x] = color;
+    }
+
+    // alpha は無視して描画する (重ねたい場合は Painter を使う)
+    pub fn draw(&self, writer: &dyn PixelWriter, pos: Vector2D<usize>) {
+        writer.blit(pos, self.width, self.pixels());
+    }
+
+    pub fn scale(&self, width: usize, height: usize, filter: ScaleFilter) -> Result<Image> {
+        let mut scaled = Image::new(width, height, PixelColor::BLACK)?;
+        for y in 0..height {
+            for x in 0..width {
+                let color = match filter {
+                    ScaleFilter::Nearest => self.get(x * self.width / width, y * self.height / height),
+                    ScaleFilter::Bilinear => self.sample_bilinear(x, y, width, height),
+                };
+                scaled.set(x, y, color);
+            }
+        }
+        Ok(scaled)
+    }
+
+    // 拡大後の pixel (x, y) の中心に対応する元画像の位置を 16.16 の固定小数点で求めて, 周囲 4 pixel を補間する
+    fn sample_bilinear(&self, x: usize, y: usize, width: usize, height: usize) -> PixelColor {
+        let source_pos = |dst: usize, dst_len: usize, src_len: usize| {
+            let pos = (((2 * dst + 1) * src_len) << 16) / (2 * dst_len);
+            let pos = pos.saturating_sub(1 << 15);
+            let (i, frac) = (pos >> 16, (pos & 0xffff) as u32);
+            (i.min(src_len - 1), (i + 1).min(src_len - 1), frac)
+        };
+        let (x0, x1, fx) = source_pos(x, width, self.width);
+        let (y0, y1, fy) = source_pos(y, height, self.height);
+        let (c00, c10, c01, c11) = (self.get(x0, y0), self.get(x1, y0), self.get(x0, y1), self.get(x1, y1));
+        let lerp = |a: u8, b: u8, t: u32| ((a as u32 * (0x10000 - t) + b as u32 * t) >> 16) as u8;
+        let mix = |f: fn(&PixelColor) -> u8| {
+            lerp(lerp(f(&c00), f(&c10), fx), lerp(f(&c01), f(&c11), fx), fy)
+        };
+        PixelColor::rgba(mix(PixelColor::red), mix(PixelColor::green), mix(PixelColor::blue), mix(PixelColor::alpha))
+    }
+
+    // ------------------------------------------------------
+    // BMP
+    // ------------------------------------------------------
+    pub fn decode_bmp(data: &[u8]) -> Result<Self> {
+        const BI_RGB: u32 = 0;
+        const BI_RLE8: u32 = 1;
+        const BI_RLE4: u32 = 2;
+        const BI_BITFIELDS: u32 = 3;
+        const FILE_HEADER_SIZE: usize = 14;
+        const CORE_HEADER_SIZE: u32 = 12;
+
+        if data.get(..2) != Some(b"BM") {
+            return Err(ImageError::UnknownFormat);
+        }
+        let pixel_offset = read_u32(data, 10)? as usize;
+        let header_size = read_u32(data, FILE_HEADER_SIZE)?;
+        let info = FILE_HEADER_SIZE;
+
+        // OS/2 の BITMAPCOREHEADER は幅と高さが 16bit
+        let (width, height, bpp, compression) = if header_size == CORE_HEADER_SIZE {
+            (read_u16(data, info + 4)? as i32, read_u16(data, info + 6)? as i16 as i32, read_u16(data, info + 10)?, BI_RGB)
+        } else if header_size >= 40 {
+            (read_i32(data, info + 4)?, read_i32(data, info + 8)?, read_u16(data, info + 14)?, read_u32(data, info + 16)?)
+        } else {
+            return Err(ImageError::InvalidHeader);
+        };
+        // height が負なら上の行から並んでいる
+        let top_down = height < 0;
+        let (width, height) = (width.unsigned_abs() as usize, height.unsigned_abs() as usize);
+        let mut image = Image::new(width, height, PixelColor::BLACK)?;
+        let row_of = |y: usize| if top_down { y } else { height - 1 - y };
+
+        // palette (1, 4, 8 bit)
+        let palette_offset = info + header_size as usize;
+        let palette_entry_size = if header_size == CORE_HEADER_SIZE { 3 } else { 4 };
+        let palette_len = if bpp <= 8 {
+            let used = if header_size >= 40 { read_u32(data, info + 32)? as usize } else { 0 };
+            if used == 0 { 1 << bpp } else { used.min(1 << bpp) }
+        } else {
+            0
+        };
+        let palette = |index: usize| -> Result<PixelColor> {
+            if index >= palette_len {
+                return Err(ImageError::InvalidHeader);
+            }
+            let entry = data.get(palette_offset + index * palette_entry_size..)
+                .and_then(|entry| entry.get(..3))
+                .ok_or(ImageError::Truncated)?;
+            Ok(PixelColor::new(entry[2], entry[1], entry[0]))
+        };
+
+        match (compression, bpp) {
+            (BI_RLE8, 8) | (BI_RLE4, 4) => {
+                let pixels = data.get(pixel_offset..).ok_or(ImageError::Truncated)?;
+                decode_bmp_rle(pixels, bpp, width, height, |x, y, index| {
+                    image.set(x, row_of(y), palette(index)?);
+                    Ok(())
+                })?;
+            },
+            (BI_RGB, 1) | (BI_RGB, 4) | (BI_RGB, 8) => {
+                let stride = (width * bpp as usize + 31) / 32 * 4;
+                for y in 0..height {
+                    let row = bmp_row(data, pixel_offset, stride, y)?;
+                    for x in 0..width {
+                        let bit = x * bpp as usize;
+                        let index = (row[bit / 8] >> (8 - bpp as usize - bit % 8)) & ((1 << bpp) - 1) as u8;
+                        image.set(x, row_of(y), palette(index as usize)?);
+                    }
+                }
+            },
+            (BI_RGB, 24) => {
+                let stride = (width * 3 + 3) / 4 * 4;
+                for y in 0..height {
+                    let row = bmp_row(data, pixel_offset, stride, y)?;
+                    for (x, bgr) in row.chunks_exact(3).take(width).enumerate() {
+                        image.set(x, row_of(y), PixelColor::new(bgr[2], bgr[1], bgr[0]));
+                    }
+                }
+            },
+            (BI_RGB, 16) | (BI_RGB, 32) | (BI_BITFIELDS, 16) | (BI_BITFIELDS, 32) => {
+                let masks = if compression == BI_BITFIELDS {
+                    // BITMAPINFOHEADER では header の直後, V4/V5 では header の中にある
+                    let alpha = if header_size >= 56 { read_u32(data, info + 52)? } else { 0 };
+                    [read_u32(data, info + 40)?, read_u32(data, info + 44)?, read_u32(data, info + 48)?, alpha]
+                } else if bpp == 16 {
+                    [0x7c00, 0x03e0, 0x001f, 0]
+                } else {
+                    // 無圧縮 32bit の 4 byte 目は使われていないことが多いので不透明とする
+                    [0x00ff0000, 0x0000ff00, 0x000000ff, 0]
+                };
+                let bytes = bpp as usize / 8;
+                let stride = (width * bytes + 3) / 4 * 4;
+                for y in 0..height {
+                    let row = bmp_row(data, pixel_offset, stride, y)?;
+                    for (x, px) in row.chunks_exact(bytes).take(width).enumerate() {
+                        let value = px.iter().rev().fold(0_u32, |v, &b| v << 8 | b as u32);
+                        let alpha = if masks[3] == 0 { 255 } else { extract_channel(value, masks[3]) };
+                        let color = PixelColor::rgba(
+                            extract_channel(value, masks[0]),
+                            extract_channel(value, masks[1]),
+                            extract_channel(value, masks[2]),
+                            alpha,
+                        );
+                        image.set(x, row_of(y), color);
+                    }
+                }
+            },
+            _ => return Err(ImageError::Unsupported("BMP bit depth or compression")),
+        }
+        Ok(image)
+    }
+
+    // ------------------------------------------------------
+    // PPM (P6)
+    // ------------------------------------------------------
+    pub fn decode_ppm(data: &[u8]) -> Result<Self> {
+        if data.get(..2) != Some(b"P6") {
+            return Err(ImageError::UnknownFormat);
+        }
+        let mut pos = 2;
+        let width = read_ppm_number(data, &mut pos)?;
+        let height = read_ppm_number(data, &mut pos)?;
+        let max_value = read_ppm_number(data, &mut pos)?;
+        if max_value == 0 || max_value > 65535 {
+            return Err(ImageError::InvalidHeader);
+        }
+        // max_value の後ろは空白 1 文字だけ
+        pos += 1;
+
+        let mut image = Image::new(width, height, PixelColor::BLACK)?;
+        let sample_size = if max_value < 256 { 1 } else { 2 };
+        let pixels = data.get(pos..pos + width * height * 3 * sample_size).ok_or(ImageError::Truncated)?;
+        let scale = |sample: &[u8]| {
+            let value = sample.iter().fold(0_usize, |v, &b| v << 8 | b as usize);
+            (value.min(max_value) * 255 / max_value) as u8
+        };
+        for (i, rgb) in pixels.chunks_exact(3 * sample_size).enumerate() {
+            let (r, g, b) = (&rgb[..sample_size], &rgb[sample_size..2 * sample_size], &rgb[2 * sample_size..]);
+            image.set(i % width, i / width, PixelColor::new(scale(r), scale(g), scale(b)));
+        }
+        Ok(image)
+    }
+}
+
+// 下から y 行目 (top-down なら上から) の pixel data
+fn bmp_row(data: &[u8], pixel_offset: usize, stride: usize, y: usize) -> Result<&[u8]> {
+    let start = pixel_offset + stride * y;
+    data.get(start..start + stride).ok_or(ImageError::Truncated)
+}
+
+// RLE8, RLE4 を展開して palette の index を (x, y) ごとに put に渡す (y は下から数える)
+fn decode_bmp_rle<F>(data: &[u8], bpp: u16, width: usize, height: usize, mut put: F) -> Result<()>
+where
+    F: FnMut(usize, usize, usize) -> Result<()>,
+{
+    let (mut x, mut y, mut i) = (0, 0, 0);
+    let mut put_checked = |x: usize, y: usize, index: usize| {
+        if x < width && y < height { put(x, y, index) } else { Ok(()) }
+    };
+    // RLE4 は 1 byte に 2 pixel 分の index が入っている
+    let nibble = |byte: u8, n: usize| if bpp == 4 {
+        (if n % 2 == 0 { byte >> 4 } else { byte & 0x0f }) as usize
+    } else {
+        byte as usize
+    };
+    loop {
+        let (count, value) = match (data.get(i), data.get(i + 1)) {
+            (Some(&count), Some(&value)) => (count as usize, value),
+            _ => return Err(ImageError::Truncated),
+        };
+        i += 2;
+        if count > 0 {
+            // encoded mode: value を count pixel 繰り返す
+            for n in 0..count {
+                put_checked(x, y, nibble(value, n))?;
+                x += 1;
+            }
+            continue;
+        }
+        match value {
+            // end of line
+            0 => {
+                x = 0;
+                y += 1;
+            },
+            // end of bitmap
+            1 => return Ok(()),
+            // delta
+            2 => {
+                let (dx, dy) = match (data.get(i), data.get(i + 1)) {
+                    (Some(&dx), Some(&dy)) => (dx as usize, dy as usize),
+                    _ => return Err(ImageError::Truncated),
+                };
+                i += 2;
+                x += dx;
+                y += dy;
+            },
+            // absolute mode: 続く count pixel 分の index (2 byte 境界に揃える)
+            count => {
+                let count = count as usize;
+                let bytes = if bpp == 4 { (count + 1) / 2 } else { count };
+                let run = data.get(i..i + bytes).ok_or(ImageError::Truncated)?;
+                for n in 0..count {
+                    let byte = if bpp == 4 { run[n / 2] } else { run[n] };
+                    put_checked(x, y, nibble(byte, n))?;
+                    x += 1;
+                }
+                i += (bytes + 1) & !1;
+            },
+        }
+        if y >= height {
+            return Ok(());
+        }
+    }
+}
+
+// mask の位置の値を 8bit に広げる
+fn extract_channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let bits = mask.count_ones();
+    let v = (value & mask) >> mask.trailing_zeros();
+    if bits >= 8 {
+        (v >> (bits - 8)) as u8
+    } else {
+        (v * 255 / ((1 << bits) - 1)) as u8
+    }
+}
+
+// 空白とコメント (# から行末まで) を飛ばして 10 進数を読む
+fn read_ppm_number(data: &[u8], pos: &mut usize) -> Result<usize> {
+    loop {
+        match data.get(*pos) {
+            Some(b'#') => {
+                while !matches!(data.get(*pos), Some(b'\n') | None) {
+                    *pos += 1;
+                }
+            },
+            Some(c) if c.is_ascii_whitespace() => *pos += 1,
+            Some(_) => break,
+            None => return Err(ImageError::Truncated),
+        }
+    }
+    let start = *pos;
+    let mut value: usize = 0;
+    while let Some(c) = data.get(*pos).filter(|c| c.is_ascii_digit()) {
+        value = value.checked_mul(10)
+            .and_then(|v| v.checked_add((c - b'0') as usize))
+            .ok_or(ImageError::InvalidHeader)?;
+        *pos += 1;
+    }
+    if *pos == start {
+        return Err(ImageError::InvalidHeader);
+    }
+    Ok(value)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data.get(offset..offset + 2).ok_or(ImageError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or(ImageError::Truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Result<i32> {
+    read_u32(data, offset).map(|v| v as i32)
+}
@@ -0,0 +1,284 @@
+//! 重ね合わせ (layer) の管理
+//! layer ごとに pixel buffer を持ち, 変更された領域だけを z order の下から順に shadow buffer へ合成する
+
+use super::shadow_buffer::{DirtyRects, PixelBounds, PixelBuffer, ShadowBuffer};
+use super::{PixelColor, PixelWriter, Rectangle, Vector2D};
+use crate::sync::{IrqSpinMutex, SpinMutex};
+use crate::utils::fixed_vec::FixedVec;
+
+const MAX_LAYERS: usize = 16;
+
+pub type LayerId = usize;
+
+pub struct Layer {
+    buffer: PixelBuffer,
+    pos: Vector2D<usize>,
+    visible: bool,
+    // 常に他の layer より上に置く (mouse cursor など)
+    topmost: bool,
+    // この色の pixel は描画せず, 下の layer を見せる (buffer の mask で encode 済み)
+    transparent: Option<u32>,
+    // layer 内の座標
+    dirty: SpinMutex<DirtyRects>,
+    // draw_pixel で描いた領域 (composite で dirty に足す)
+    pixels: PixelBounds,
+}
+
+impl Layer {
+    fn new(width: usize, height: usize, transparent: Option<PixelColor>, screen: &ShadowBuffer) -> Option<Self> {
+        let mask = screen.buffer().mask();
+        let buffer = PixelBuffer::new(width, height, mask)?;
+        let transparent = transparent.map(|color| mask.encode(&color));
+        Some(Self {
+            buffer,
+            pos: Vector2D::new(0, 0),
+            visible: true,
+            topmost: false,
+            transparent,
+            dirty: SpinMutex::new(DirtyRects::new()),
+            pixels: PixelBounds::new(),
+        })
+    }
+
+    pub fn pos(&self) -> Vector2D<usize> {
+        self.pos
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    // 画面上での領域
+    pub fn rect(&self) -> Rectangle {
+        Rectangle::new(self.pos, Vector2D::new(self.buffer.width(), self.buffer.height()))
+    }
+
+    // 透過色なら None
+    fn pixel_at(&self, x: usize, y: usize) -> Option<u32> {
+        let pixel = self.buffer.read_raw(x, y);
+        if Some(pixel) == self.transparent { None } else { Some(pixel) }
+    }
+}
+
+impl PixelWriter for Layer {
+    fn horizontal_resolution(&self) -> usize {
+        self.buffer.width()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.buffer.height()
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        if self.buffer.raw().draw_pixel(x, y, color) {
+            self.pixels.add(x, y);
+        }
+    }
+
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        self.buffer.raw().read_pixel(x, y)
+    }
+
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        if let Some(rect) = self.buffer.raw().fill_rect(Rectangle::new(pos, size), color) {
+            self.dirty.lock().add(rect);
+        }
+    }
+
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        if let Some(rect) = self.buffer.raw().blit(pos, width, pixels) {
+            self.dirty.lock().add(rect);
+        }
+    }
+
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        if let Some(rect) = self.buffer.raw().scroll_up(area, dy, fill) {
+            self.dirty.lock().add(rect);
+        }
+    }
+}
+
+pub struct LayerManager {
+    screen: &'static ShadowBuffer,
+    // LayerId は layers の index
+    layers: FixedVec<'static, Layer, MAX_LAYERS>,
+    // 下から順
+    z_order: FixedVec<'static, LayerId, MAX_LAYERS>,
+    // 移動, 表示/非表示で変わった画面上の領域
+    dirty: DirtyRects,
+}
+
+impl LayerManager {
+    pub fn new(screen: &'static ShadowBuffer) -> Self {
+        Self {
+            screen,
+            layers: FixedVec::new(),
+            z_order: FixedVec::new(),
+            dirty: DirtyRects::new(),
+        }
+    }
+
+    // topmost でない layer の一番上に layer を作る. メモリが確保できないか, layer の数が上限なら None
+    pub fn new_layer(&mut self, width: usize, height: usize, transparent: Option<PixelColor>) -> Option<LayerId> {
+        if self.layers.len() == MAX_LAYERS {
+            return None;
+        }
+        let layer = Layer::new(width, height, transparent, self.screen)?;
+        self.dirty.add(layer.rect());
+        let id = self.layers.len();
+        self.layers.push(layer);
+        self.z_order.push(id);
+        self.sink_below_topmost();
+        Some(id)
+    }
+
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        &self.layers.as_slice()[id]
+    }
+
+    pub fn move_to(&mut self, id: LayerId, pos: Vector2D<usize>) {
+        let layer = &mut self.layers.as_mut_slice()[id];
+        let old = layer.rect();
+        layer.pos = pos;
+        let new = layer.rect();
+        if layer.visible {
+            self.dirty.add(old);
+            self.dirty.add(new);
+        }
+    }
+
+    pub fn set_visible(&mut self, id: LayerId, visible: bool) {
+        let layer = &mut self.layers.as_mut_slice()[id];
+        if layer.visible != visible {
+            layer.visible = visible;
+            self.dirty.add(layer.rect());
+        }
+    }
+
+    // 下から順の LayerId
+    pub fn z_order(&self) -> &[LayerId] {
+        self.z_order.as_slice()
+    }
+
+    // topmost な layer がなければ一番上, あればそれらのすぐ下に移す
+    pub fn raise_to_top(&mut self, id: LayerId) {
+        let z_order = self.z_order.as_mut_slice();
+        if let Some(z) = z_order.iter().position(|&layer| layer == id) {
+            z_order[z..].rotate_left(1);
+            self.sink_below_topmost();
+            self.dirty.add(self.layers.as_slice()[id].rect());
+        }
+    }
+
+    pub fn set_topmost(&mut self, id: LayerId, topmost: bool) {
+        self.layers.as_mut_slice()[id].topmost = topmost;
+        self.raise_to_top(id);
+    }
+
+    // 一番上の layer が topmost でなければ, topmost な layer の下まで沈める
+    fn sink_below_topmost(&mut self) {
+        let layers = self.layers.as_slice();
+        let z_order = self.z_order.as_mut_slice();
+        let mut z = match z_order.len() {
+            0 => return,
+            len => len - 1,
+        };
+        if layers[z_order[z]].topmost {
+            return;
+        }
+        while z > 0 && layers[z_order[z - 1]].topmost {
+            z_order.swap(z - 1, z);
+            z -= 1;
+        }
+    }
+
+    // 前回から変わった領域を合成し, frame buffer に反映する
+    pub fn composite(&mut self) {
+        for layer in self.layers.as_slice() {
+            let rects = {
+                let mut dirty = layer.dirty.lock();
+                if let Some(rect) = layer.pixels.take() {
+                    dirty.add(rect);
+                }
+                dirty.take()
+            };
+            if !layer.visible {
+                continue;
+            }
+            for rect in rects.iter().flatten() {
+                let pos = Vector2D::new(layer.pos.x() + rect.x(), layer.pos.y() + rect.y());
+                self.dirty.add(Rectangle::new(pos, rect.size()));
+            }
+        }
+
+        let screen_bounds = self.screen.buffer().bounds();
+        for area in self.dirty.take().iter().flatten() {
+            if let Some(area) = area.intersection(&screen_bounds) {
+                self.draw_area(&area);
+            }
+        }
+        self.screen.flush();
+    }
+
+    fn draw_area(&self, area: &Rectangle) {
+        let screen = self.screen.buffer();
+        for &id in self.z_order.as_slice() {
+            let layer = &self.layers.as_slice()[id];
+            if !layer.visible {
+                continue;
+            }
+            let rect = match layer.rect().intersection(area) {
+                Some(rect) => rect,
+                None => continue,
+            };
+            let (lx, ly) = (rect.x() - layer.pos.x(), rect.y() - layer.pos.y());
+            for dy in 0..rect.height() {
+                if layer.transparent.is_none() {
+                    // 不透明なら 1 行まとめてコピーする
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(
+                            layer.buffer.row_ptr(ly + dy).add(lx),
+                            screen.row_ptr(rect.y() + dy).add(rect.x()),
+                            rect.width(),
+                        );
+                    }
+                    continue;
+                }
+                for dx in 0..rect.width() {
+                    if let Some(pixel) = layer.pixel_at(lx + dx, ly + dy) {
+                        screen.write_raw(rect.x() + dx, rect.y() + dy, pixel);
+                    }
+                }
+            }
+        }
+        self.screen.mark_dirty(*area);
+    }
+}
+
+pub static LAYER_MANAGER: IrqSpinMutex<Option<LayerManager>> = IrqSpinMutex::new(None);
+
+// 背景 layer を作る. shadow buffer がなければ何もしない (layer を使わずに直接描画する)
+pub fn init_layers(background: &PixelColor) {
+    let screen = match super::shadow_buffer() {
+        Some(screen) => screen,
+        None => return,
+    };
+    let mut manager = LayerManager::new(screen);
+    let (width, height) = screen.resolution();
+    if let Some(id) = manager.new_layer(width, height, None) {
+        manager.layer(id).fill_rect(Vector2D::new(0, 0), Vector2D::new(width, height), background);
+    }
+    manager.composite();
+    *LAYER_MANAGER.lock() = Some(manager);
+}
+
+// layer manager があれば layer を作る
+pub fn new_layer(width: usize, height: usize, transparent: Option<PixelColor>) -> Option<LayerId> {
+    LAYER_MANAGER.lock().as_mut()?.new_layer(width, height, transparent)
+}
+
+// id の layer に描画して画面に反映する
+pub fn draw_on_layer<F: FnOnce(&Layer)>(id: LayerId, f: F) {
+    if let Some(manager) = LAYER_MANAGER.lock().as_mut() {
+        f(manager.layer(id));
+        manager.composite();
+    }
+}
@@ -0,0 +1,543 @@
+
+pub mod shadow_buffer;
+pub mod layer;
+pub mod raw;
+pub mod painter;
+pub mod image;
+pub mod font;
+pub mod window;
+pub mod screenshot;
+
+pub use font::{Font, ShinonomeFont};
+
+use shadow_buffer::ShadowBuffer;
+use raw::RawPixels;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelColor {
+    red: u8,
+    green: u8,
+    blue: u8,
+    // 255 で不透明, 0 で透明. frame buffer には書き込まれない
+    alpha: u8,
+}
+
+impl PixelColor {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+    pub const RED: Self = Self::new(255, 0, 0);
+    pub const GREEN: Self = Self::new(0, 255, 0);
+    pub const BLUE: Self = Self::new(0, 0, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 255)
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self {
+            red: r,
+            green: g,
+            blue: b,
+            alpha: a,
+        }
+    }
+
+    pub const fn with_alpha(self, alpha: u8) -> Self {
+        Self { alpha, ..self }
+    }
+
+    pub fn red(&self) -> u8 {
+        self.red
+    }
+
+    pub fn green(&self) -> u8 {
+        self.green
+    }
+
+    pub fn blue(&self) -> u8 {
+        self.blue
+    }
+
+    pub fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.alpha == 255
+    }
+
+    // self を dst の上に重ねた色 (source-over). dst は不透明とみなす
+    pub fn blend_over(&self, dst: &PixelColor) -> PixelColor {
+        let a = self.alpha as u32;
+        let mix = |src: u8, dst: u8| ((src as u32 * a + dst as u32 * (255 - a) + 127) / 255) as u8;
+        PixelColor::new(
+            mix(self.red, dst.red),
+            mix(self.green, dst.green),
+            mix(self.blue, dst.blue),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum PixelFormat {
+    PixelRGBResv8BitPerColor,
+    PixelBGRResv8BitPerColor,
+    // 各色の位置は FrameBuffer の pixel_bitmask で与えられる
+    PixelBitmask,
+}
+
+// 1 pixel (4 byte) を little endian の u32 として見たときの各色の bit
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct PixelMask {
+    pub red: u32,
+    pub green: u32,
+    pub blue: u32,
+    pub reserved: u32,
+}
+
+impl PixelMask {
+    pub const RGB: Self = Self { red: 0x0000ff, green: 0x00ff00, blue: 0xff0000, reserved: 0xff000000 };
+    pub const BGR: Self = Self { red: 0xff0000, green: 0x00ff00, blue: 0x0000ff, reserved: 0xff000000 };
+
+    pub fn encode(&self, color: &PixelColor) -> u32 {
+        encode_channel(self.red, color.red)
+            | encode_channel(self.green, color.green)
+            | encode_channel(self.blue, color.blue)
+    }
+
+    pub fn decode(&self, pixel: u32) -> PixelColor {
+        PixelColor::new(
+            decode_channel(self.red, pixel),
+            decode_channel(self.green, pixel),
+            decode_channel(self.blue, pixel),
+        )
+    }
+}
+
+// 8bit の値を mask の bit 数に合わせて mask の位置に置く
+fn encode_channel(mask: u32, value: u8) -> u32 {
+    let bits = mask.count_ones();
+    if bits == 0 {
+        return 0;
+    }
+    let value = if bits >= 8 {
+        (value as u32) << (bits - 8)
+    } else {
+        value as u32 >> (8 - bits)
+    };
+    (value << mask.trailing_zeros()) & mask
+}
+
+fn decode_channel(mask: u32, pixel: u32) -> u8 {
+    let bits = mask.count_ones();
+    if bits == 0 {
+        return 0;
+    }
+    let value = (pixel & mask) >> mask.trailing_zeros();
+    if bits >= 8 {
+        (value >> (bits - 8)) as u8
+    } else {
+        // 下位 bit を上位 bit で埋めて 0..=255 に広げる
+        let value = value << (8 - bits);
+        (value | value >> bits) as u8
+    }
+}
+
+
+// need init CONSOLE_WRITER in kernel_main
+use crate::sync::IrqSpinMutex;
+use core::mem::MaybeUninit;
+pub static WRITER: IrqSpinMutex<MaybeUninit<&dyn PixelWriter>> = IrqSpinMutex::new(
+    MaybeUninit::<&dyn PixelWriter>::uninit()
+);
+// 描画は SHADOW_BUFFER に対して行い, flush_screen で frame buffer に反映する.
+// (SHADOW_BUFFER 用のメモリが確保できなければ frame buffer に直接描画する)
+static mut SHADOW_BUFFER: Option<ShadowBuffer> = None;
+
+// init_memory の後に呼ぶこと
+pub fn init_global_writer(frame_buffer: FrameBuffer) {
+    if let Some(shadow_buffer) = ShadowBuffer::new(frame_buffer) {
+        let shadow_buffer = unsafe {
+            SHADOW_BUFFER = Some(shadow_buffer);
+            SHADOW_BUFFER.as_ref().unwrap()
+        };
+        WRITER.lock().write(shadow_buffer);
+        return;
+    }
+
+    WRITER.lock().write(match frame_buffer.pixel_format() {
+        PixelFormat::PixelRGBResv8BitPerColor => {
+            // placement new
+            static mut RGB_WRITER: MaybeUninit<RGBResv8BitPerColorPixelWriter> = MaybeUninit::uninit();
+            unsafe { RGB_WRITER.write(RGBResv8BitPerColorPixelWriter::new(frame_buffer)); }
+            unsafe { RGB_WRITER.assume_init_ref() }
+        },
+        PixelFormat::PixelBGRResv8BitPerColor => {
+            static mut BGR_WRITER: MaybeUninit<BGRResv8BitPerColorPixelWriter> = MaybeUninit::uninit();
+            unsafe { BGR_WRITER.write(BGRResv8BitPerColorPixelWriter::new(frame_buffer)); }
+            unsafe { BGR_WRITER.assume_init_ref() }
+        },
+        PixelFormat::PixelBitmask => {
+            static mut BITMASK_WRITER: MaybeUninit<BitmaskPixelWriter> = MaybeUninit::uninit();
+            unsafe { BITMASK_WRITER.write(BitmaskPixelWriter::new(frame_buffer)); }
+            unsafe { BITMASK_WRITER.assume_init_ref() }
+        },
+    });
+}
+
+pub fn shadow_buffer() -> Option<&'static ShadowBuffer> {
+    unsafe { SHADOW_BUFFER.as_ref() }
+}
+
+// 前回の flush 以降に描画された領域を frame buffer にコピーする
+pub fn flush_screen() {
+    if let Some(shadow_buffer) = unsafe { SHADOW_BUFFER.as_ref() } {
+        shadow_buffer.flush();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FrameBuffer {
+    frame_buffer: *mut u8,
+    pixel_per_scan_line: usize,
+    horizontal_resolution: usize,
+    vertical_resolution: usize,
+    pixel_format: PixelFormat,
+    // pixel_format が PixelBitmask のときだけ有効
+    pixel_bitmask: PixelMask,
+}
+
+impl FrameBuffer {
+    pub const fn uninitialized_default() -> Self {
+        Self {
+            frame_buffer: 0 as *mut u8,
+            pixel_per_scan_line: 0,
+            horizontal_resolution: 0,
+            vertical_resolution: 0,
+            pixel_format: PixelFormat::PixelRGBResv8BitPerColor,
+            pixel_bitmask: PixelMask::RGB,
+        }
+    }
+
+    pub fn h(&self) -> usize {
+        self.horizontal_resolution
+    }
+
+    pub fn v(&self) -> usize {
+        self.vertical_resolution
+    }
+
+    pub fn pixel_format(&self) -> &PixelFormat {
+        &self.pixel_format
+    }
+
+    pub fn pixel_mask(&self) -> PixelMask {
+        match self.pixel_format {
+            PixelFormat::PixelRGBResv8BitPerColor => PixelMask::RGB,
+            PixelFormat::PixelBGRResv8BitPerColor => PixelMask::BGR,
+            PixelFormat::PixelBitmask => self.pixel_bitmask,
+        }
+    }
+
+    pub fn raw(&self) -> RawPixels {
+        RawPixels::new(
+            self.frame_buffer as *mut u32,
+            self.pixel_per_scan_line,
+            self.horizontal_resolution,
+            self.vertical_resolution,
+            self.pixel_mask(),
+        )
+    }
+
+}
+
+impl PixelWriter for FrameBuffer {
+    fn horizontal_resolution(&self) -> usize {
+        self.h()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.v()
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        self.raw().draw_pixel(x, y, color);
+    }
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        self.raw().read_pixel(x, y)
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.raw().fill_rect(Rectangle::new(pos, size), color);
+    }
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        self.raw().blit(pos, width, pixels);
+    }
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        self.raw().scroll_up(area, dy, fill);
+    }
+}
+
+pub trait PixelWriter {
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor);
+
+    fn horizontal_resolution(&self) -> usize;
+    fn vertical_resolution(&self) -> usize;
+    fn resolution(&self) -> (usize, usize) {
+        (self.horizontal_resolution(), self.vertical_resolution())
+    }
+
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        for dy in 0..size.y() {
+            for dx in 0..size.x() {
+                self.draw_pixel(pos.x() + dx, pos.y() + dy, color)
+            }
+        }
+    }
+
+    // 輪郭は pos から pos + size - 1 まで (fill_rect と同じ領域の縁)
+    fn draw_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        if size.x() == 0 || size.y() == 0 {
+            return;
+        }
+        let (right, bottom) = (pos.x() + size.x() - 1, pos.y() + size.y() - 1);
+        let (horizontal, vertical) = (Vector2D::new(size.x(), 1), Vector2D::new(1, size.y()));
+        self.fill_rect(pos, horizontal, color);
+        self.fill_rect(Vector2D::new(pos.x(), bottom), horizontal, color);
+        self.fill_rect(pos, vertical, color);
+        self.fill_rect(Vector2D::new(right, pos.y()), vertical, color);
+    }
+
+    // 描画済みの色. 読めない writer や範囲外なら None
+    fn read_pixel(&self, _x: usize, _y: usize) -> Option<PixelColor> {
+        None
+    }
+
+    // 幅 width の画像 pixels (行ごとに並んだもの) を pos に描く
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        if width == 0 {
+            return;
+        }
+        for (i, color) in pixels.iter().enumerate() {
+            self.draw_pixel(pos.x() + i % width, pos.y() + i / width, color);
+        }
+    }
+
+    // area の中身を dy pixel 上にずらし, 空いた下端を fill で塗る
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor);
+}
+
+pub struct RGBResv8BitPerColorPixelWriter {
+    frame_buffer: FrameBuffer
+}
+
+impl RGBResv8BitPerColorPixelWriter {
+    pub fn new(frame_buffer: FrameBuffer) -> Self {
+        Self { frame_buffer }
+    }
+}
+
+impl PixelWriter for RGBResv8BitPerColorPixelWriter {
+    fn horizontal_resolution(&self) -> usize {
+        self.frame_buffer.h()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.frame_buffer.v()
+    }
+    fn draw_pixel(&self, x:usize, y:usize, color: &PixelColor) {
+        if x >= self.frame_buffer.h() || y >= self.frame_buffer.v() {
+            return;
+        }
+        let pixel_position = self.frame_buffer.pixel_per_scan_line * y + x;
+        let pixel = unsafe { (self.frame_buffer.frame_buffer as *mut u32).add(pixel_position) };
+        let data = color.red as u32 | (color.green as u32) << 8 | (color.blue as u32) << 16;
+        unsafe { pixel.write_volatile(data) };
+    }
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        self.frame_buffer.raw().read_pixel(x, y)
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.frame_buffer.raw().fill_rect(Rectangle::new(pos, size), color);
+    }
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        self.frame_buffer.raw().blit(pos, width, pixels);
+    }
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        self.frame_buffer.raw().scroll_up(area, dy, fill);
+    }
+}
+
+pub struct BGRResv8BitPerColorPixelWriter {
+    frame_buffer: FrameBuffer
+}
+
+impl BGRResv8BitPerColorPixelWriter {
+    pub fn new(frame_buffer: FrameBuffer) -> Self {
+        Self { frame_buffer }
+    }
+
+}
+
+impl PixelWriter for BGRResv8BitPerColorPixelWriter {
+    fn horizontal_resolution(&self) -> usize {
+        self.frame_buffer.h()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.frame_buffer.v()
+    }
+    fn draw_pixel(&self, x:usize, y:usize, color: &PixelColor) {
+        if x >= self.frame_buffer.h() || y >= self.frame_buffer.v() {
+            return;
+        }
+        let pixel_position = self.frame_buffer.pixel_per_scan_line * y + x;
+        let pixel = unsafe { (self.frame_buffer.frame_buffer as *mut u32).add(pixel_position) };
+        let data = color.blue as u32 | (color.green as u32) << 8 | (color.red as u32) << 16;
+        unsafe { pixel.write_volatile(data) };
+    }
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        self.frame_buffer.raw().read_pixel(x, y)
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.frame_buffer.raw().fill_rect(Rectangle::new(pos, size), color);
+    }
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        self.frame_buffer.raw().blit(pos, width, pixels);
+    }
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        self.frame_buffer.raw().scroll_up(area, dy, fill);
+    }
+}
+
+// 任意の mask の pixel format 用. RGB/BGR 専用の writer より遅い
+pub struct BitmaskPixelWriter {
+    raw: RawPixels,
+}
+
+impl BitmaskPixelWriter {
+    pub fn new(frame_buffer: FrameBuffer) -> Self {
+        Self { raw: frame_buffer.raw() }
+    }
+}
+
+impl PixelWriter for BitmaskPixelWriter {
+    fn horizontal_resolution(&self) -> usize {
+        self.raw.width()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.raw.height()
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        self.raw.draw_pixel(x, y, color);
+    }
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        self.raw.read_pixel(x, y)
+    }
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        self.raw.fill_rect(Rectangle::new(pos, size), color);
+    }
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        self.raw.blit(pos, width, pixels);
+    }
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        self.raw.scroll_up(area, dy, fill);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector2D<T: Ord + Copy> {
+    x: T, 
+    y: T,
+}
+
+impl<T: Ord + Copy> Vector2D<T> {
+    pub fn new(x: T, y: T) -> Self {
+        Self { x, y }
+    }
+
+    pub fn x(&self) -> T {
+        self.x
+    }
+
+    pub fn y(&self) -> T {
+        self.y
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rectangle {
+    pos: Vector2D<usize>,
+    size: Vector2D<usize>,
+}
+
+impl Rectangle {
+    pub fn new(pos: Vector2D<usize>, size: Vector2D<usize>) -> Self {
+        Self { pos, size }
+    }
+
+    pub fn pos(&self) -> Vector2D<usize> {
+        self.pos
+    }
+
+    pub fn size(&self) -> Vector2D<usize> {
+        self.size
+    }
+
+    pub fn x(&self) -> usize {
+        self.pos.x()
+    }
+
+    pub fn y(&self) -> usize {
+        self.pos.y()
+    }
+
+    pub fn width(&self) -> usize {
+        self.size.x()
+    }
+
+    pub fn height(&self) -> usize {
+        self.size.y()
+    }
+
+    // 右端, 下端 (含まない)
+    pub fn right(&self) -> usize {
+        self.x() + self.width()
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y() + self.height()
+    }
+
+    pub fn area(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    pub fn contains(&self, other: &Rectangle) -> bool {
+        self.x() <= other.x() && self.y() <= other.y()
+            && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+
+    pub fn contains_point(&self, x: usize, y: usize) -> bool {
+        self.x() <= x && x < self.right() && self.y() <= y && y < self.bottom()
+    }
+
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let (left, top) = (self.x().max(other.x()), self.y().max(other.y()));
+        let (right, bottom) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+        if left < right && top < bottom {
+            Some(Rectangle::new(Vector2D::new(left, top), Vector2D::new(right - left, bottom - top)))
+        } else {
+            None
+        }
+    }
+
+    // 両方を含む最小の矩形
+    pub fn union(&self, other: &Rectangle) -> Rectangle {
+        let (left, top) = (self.x().min(other.x()), self.y().min(other.y()));
+        let (right, bottom) = (self.right().max(other.right()), self.bottom().max(other.bottom()));
+        Rectangle::new(Vector2D::new(left, top), Vector2D::new(right - left, bottom - top))
+    }
+}
@@ -0,0 +1,326 @@
+//! PixelWriter の上に図形の描画をまとめたもの
+//! 座標は画面外 (負の値) も取れるように isize で受け取り, clip 領域 (clip stack の一番上) の外は描かない.
+//! 色の alpha が 255 未満なら, 描画済みの色 (read_pixel) と source-over で合成する.
+
+use core::ops::RangeInclusive;
+use super::{PixelColor, PixelWriter, Rectangle, Vector2D};
+use crate::utils::fixed_vec::FixedVec;
+
+const MAX_CLIP_DEPTH: usize = 8;
+// fill_polygon で 1 行あたりに扱える辺との交点の数
+const MAX_INTERSECTIONS: usize = 32;
+
+pub type Point = Vector2D<isize>;
+
+pub struct Painter<'a> {
+    writer: &'a dyn PixelWriter,
+    // writer 全体の上に push_clip した領域が積まれる
+    clip_stack: FixedVec<'static, Rectangle, MAX_CLIP_DEPTH>,
+}
+
+impl<'a> Painter<'a> {
+    pub fn new(writer: &'a dyn PixelWriter) -> Self {
+        let (width, height) = writer.resolution();
+        let mut clip_stack = FixedVec::new();
+        clip_stack.push(Rectangle::new(Vector2D::new(0, 0), Vector2D::new(width, height)));
+        Self { writer, clip_stack }
+    }
+
+    pub fn clip(&self) -> Rectangle {
+        *self.clip_stack.as_slice().last().unwrap()
+    }
+
+    // 今の clip 領域と rect の共通部分を新しい clip 領域にする. 積みすぎたら false
+    pub fn push_clip(&mut self, rect: Rectangle) -> bool {
+        let clip = self.clip().intersection(&rect)
+            .unwrap_or(Rectangle::new(Vector2D::new(0, 0), Vector2D::new(0, 0)));
+        unsafe { self.clip_stack.try_push(clip).is_ok() }
+    }
+
+    // writer 全体の clip は取り除かない
+    pub fn pop_clip(&mut self) {
+        if self.clip_stack.len() > 1 {
+            self.clip_stack.pop();
+        }
+    }
+
+    // clip 領域の左端と右端 (どちらも領域に含まれる)
+    fn clip_columns(&self) -> (isize, isize) {
+        let clip = self.clip();
+        (clip.x() as isize, clip.right() as isize - 1)
+    }
+
+    // clip 領域の上端と下端 (どちらも領域に含まれる)
+    fn clip_rows(&self) -> (isize, isize) {
+        let clip = self.clip();
+        (clip.y() as isize, clip.bottom() as isize - 1)
+    }
+
+    fn clipped(&self, x: isize, y: isize) -> Option<(usize, usize)> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if self.clip().contains_point(x, y) { Some((x, y)) } else { None }
+    }
+
+    pub fn draw_pixel(&self, p: Point, color: &PixelColor) {
+        let (x, y) = match self.clipped(p.x(), p.y()) {
+            Some(pos) => pos,
+            None => return,
+        };
+        match color.alpha() {
+            0 => {},
+            255 => self.writer.draw_pixel(x, y, color),
+            _ => {
+                let color = match self.writer.read_pixel(x, y) {
+                    Some(dst) => color.blend_over(&dst),
+                    None => color.with_alpha(255),
+                };
+                self.writer.draw_pixel(x, y, &color);
+            },
+        }
+    }
+
+    // y 行目の x0 ..= x1
+    fn draw_hline(&self, x0: isize, x1: isize, y: isize, color: &PixelColor) {
+        let (x0, x1) = (x0.min(x1), x0.max(x1));
+        let ((left, right), (top, bottom)) = (self.clip_columns(), self.clip_rows());
+        if y < top || y > bottom {
+            return;
+        }
+        let (left, right) = (x0.max(left), x1.min(right));
+        if left > right {
+            return;
+        }
+        if color.is_opaque() {
+            self.writer.fill_rect(
+                Vector2D::new(left as usize, y as usize),
+                Vector2D::new((right - left + 1) as usize, 1),
+                color,
+            );
+        } else {
+            (left..=right).for_each(|x| self.draw_pixel(Vector2D::new(x, y), color));
+        }
+    }
+
+    // 長い方の軸に 1 pixel ずつ進め, 短い方の軸は四捨五入する (Bresenham とほぼ同じ点になる).
+    // 先に clip 領域にかかる区間を求めて, 画面から大きくはみ出す線でもその区間だけを描く
+    pub fn draw_line(&self, p0: Point, p1: Point, color: &PixelColor) {
+        let (dx, dy) = (p1.x() - p0.x(), p1.y() - p0.y());
+        let steps = dx.abs().max(dy.abs());
+        if steps == 0 {
+            self.draw_pixel(p0, color);
+            return;
+        }
+        let (x_first, x_last) = line_steps(p0.x(), dx, steps, self.clip_columns());
+        let (y_first, y_last) = line_steps(p0.y(), dy, steps, self.clip_rows());
+        // i 歩目の点は p0 + round(i * d / steps)
+        let at = |p: isize, d: isize, i: isize| p + (2 * i * d + steps).div_euclid(2 * steps);
+        for i in x_first.max(y_first).max(0)..=x_last.min(y_last).min(steps) {
+            self.draw_pixel(Vector2D::new(at(p0.x(), dx, i), at(p0.y(), dy, i)), color);
+        }
+    }
+
+    // 輪郭は pos から pos + size - 1 まで
+    pub fn draw_rect(&self, pos: Point, size: Vector2D<usize>, color: &PixelColor) {
+        if size.x() == 0 || size.y() == 0 {
+            return;
+        }
+        let (right, bottom) = (pos.x() + size.x() as isize - 1, pos.y() + size.y() as isize - 1);
+        self.draw_hline(pos.x(), right, pos.y(), color);
+        if bottom != pos.y() {
+            self.draw_hline(pos.x(), right, bottom, color);
+        }
+        let (clip_top, clip_bottom) = self.clip_rows();
+        for y in (pos.y() + 1).max(clip_top)..bottom.min(clip_bottom + 1) {
+            self.draw_pixel(Vector2D::new(pos.x(), y), color);
+            if right != pos.x() {
+                self.draw_pixel(Vector2D::new(right, y), color);
+            }
+        }
+    }
+
+    pub fn fill_rect(&self, pos: Point, size: Vector2D<usize>, color: &PixelColor) {
+        if size.x() == 0 {
+            return;
+        }
+        let (top, bottom) = self.clip_rows();
+        for y in pos.y().max(top)..(pos.y() + size.y() as isize).min(bottom + 1) {
+            self.draw_hline(pos.x(), pos.x() + size.x() as isize - 1, y, color);
+        }
+    }
+
+    pub fn draw_circle(&self, center: Point, radius: usize, color: &PixelColor) {
+        self.draw_ellipse(center, radius, radius, color);
+    }
+
+    pub fn fill_circle(&self, center: Point, radius: usize, color: &PixelColor) {
+        self.fill_ellipse(center, radius, radius, color);
+    }
+
+    pub fn draw_ellipse(&self, center: Point, rx: usize, ry: usize, color: &PixelColor) {
+        for &(sx, sy) in [(1, 1), (-1, 1), (-1, -1), (1, -1)].iter() {
+            self.draw_quarter_ellipse(center, rx, ry, sx, sy, color);
+        }
+    }
+
+    pub fn fill_ellipse(&self, center: Point, rx: usize, ry: usize, color: &PixelColor) {
+        for dy in clip_offsets(-(ry as isize), ry as isize, center.y(), 1, self.clip_rows()) {
+            let w = half_width(rx, ry, dy.unsigned_abs());
+            self.draw_hline(center.x() - w, center.x() + w, center.y() + dy, color);
+        }
+    }
+
+    // 中心から (sx, sy) 方向の 1/4 の輪郭. 軸上の点は sx, sy が正のときだけ描く (4 つ合わせたときに重ならないように)
+    fn draw_quarter_ellipse(&self, center: Point, rx: usize, ry: usize, sx: isize, sy: isize, color: &PixelColor) {
+        let (columns, rows) = (self.clip_columns(), self.clip_rows());
+        for dy in clip_offsets(if sy < 0 { 1 } else { 0 }, ry as isize, center.y(), sy, rows) {
+            let dy = dy as usize;
+            // 次の行の幅までつなげて, 輪郭が途切れないようにする
+            let w = half_width(rx, ry, dy);
+            let next = if dy == ry { -1 } else { half_width(rx, ry, dy + 1) };
+            let start = (next + 1).min(w).max(if sx < 0 { 1 } else { 0 });
+            for dx in clip_offsets(start, w, center.x(), sx, columns) {
+                self.draw_pixel(Vector2D::new(center.x() + sx * dx, center.y() + sy * dy as isize), color);
+            }
+        }
+    }
+
+    pub fn draw_rounded_rect(&self, pos: Point, size: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        let r = clamp_radius(size, radius);
+        if r == 0 {
+            self.draw_rect(pos, size, color);
+            return;
+        }
+        let (w, h) = (size.x() as isize, size.y() as isize);
+        let (ri, (left, top)) = (r as isize, (pos.x(), pos.y()));
+        let (right, bottom) = (left + w - 1, top + h - 1);
+        // 角の円の中心
+        let (cx0, cy0, cx1, cy1) = (left + ri, top + ri, right - ri, bottom - ri);
+        if cx0 + 1 < cx1 {
+            self.draw_hline(cx0 + 1, cx1 - 1, top, color);
+            self.draw_hline(cx0 + 1, cx1 - 1, bottom, color);
+        }
+        let (columns, rows) = (self.clip_columns(), self.clip_rows());
+        for y in (cy0 + 1).max(rows.0)..cy1.min(rows.1 + 1) {
+            self.draw_pixel(Vector2D::new(left, y), color);
+            self.draw_pixel(Vector2D::new(right, y), color);
+        }
+        for &(cx, cy, sx, sy) in [(cx1, cy1, 1, 1), (cx0, cy1, -1, 1), (cx0, cy0, -1, -1), (cx1, cy0, 1, -1)].iter() {
+            // 角ごとの 1/4 円は軸上の点も含めて描く
+            for dy in clip_offsets(0, ri, cy, sy, rows) {
+                let dy = dy as usize;
+                let w = half_width(r, r, dy);
+                let next = if dy == r { -1 } else { half_width(r, r, dy + 1) };
+                for dx in clip_offsets((next + 1).min(w), w, cx, sx, columns) {
+                    self.draw_pixel(Vector2D::new(cx + sx * dx, cy + sy * dy as isize), color);
+                }
+            }
+        }
+    }
+
+    pub fn fill_rounded_rect(&self, pos: Point, size: Vector2D<usize>, radius: usize, color: &PixelColor) {
+        let r = clamp_radius(size, radius) as isize;
+        let (w, h) = (size.x() as isize, size.y() as isize);
+        for i in clip_offsets(0, h - 1, pos.y(), 1, self.clip_rows()) {
+            // 角の円の中心の行からの距離
+            let d = if i < r { r - i } else if i > h - 1 - r { i - (h - 1 - r) } else { 0 };
+            let inset = r - half_width(r as usize, r as usize, d as usize);
+            if w - 1 - inset >= inset {
+                self.draw_hline(pos.x() + inset, pos.x() + w - 1 - inset, pos.y() + i, color);
+            }
+        }
+    }
+
+    pub fn draw_polygon(&self, points: &[Point], color: &PixelColor) {
+        for (i, &p0) in points.iter().enumerate() {
+            let p1 = points[(i + 1) % points.len()];
+            self.draw_line(p0, p1, color);
+        }
+    }
+
+    // even-odd rule. 各行の pixel の中心 (y + 0.5) で辺との交点を求める
+    pub fn fill_polygon(&self, points: &[Point], color: &PixelColor) {
+        if points.len() < 3 {
+            return;
+        }
+        let top = points.iter().map(|p| p.y()).min().unwrap();
+        let bottom = points.iter().map(|p| p.y()).max().unwrap();
+        let clip = self.clip();
+        for y in top.max(clip.y() as isize)..=bottom.min(clip.bottom() as isize - 1) {
+            let mut xs = [0_isize; MAX_INTERSECTIONS];
+            let mut count = 0;
+            let sample_y = 2 * y + 1;
+            for (i, &a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                let (ay, by) = (2 * a.y(), 2 * b.y());
+                if (ay <= sample_y) == (by <= sample_y) || count == MAX_INTERSECTIONS {
+                    continue;
+                }
+                xs[count] = a.x() + (sample_y - ay) * (b.x() - a.x()) / (by - ay);
+                count += 1;
+            }
+            let xs = &mut xs[..count];
+            xs.sort_unstable();
+            for span in xs.chunks_exact(2) {
+                if span[0] < span[1] {
+                    self.draw_hline(span[0], span[1] - 1, y, color);
+                }
+            }
+        }
+    }
+}
+
+// lo..=hi のうち, base + sign * d が min..=max に収まる d の範囲
+fn clip_offsets(lo: isize, hi: isize, base: isize, sign: isize, (min, max): (isize, isize)) -> RangeInclusive<isize> {
+    let (min, max) = if sign > 0 { (min - base, max - base) } else { (base - max, base - min) };
+    lo.max(min)..=hi.min(max)
+}
+
+// p + i * d / steps が min - 1 ..= max + 1 に入る i の範囲.
+// 四捨五入でずれる分だけ広めに取り, 範囲外の点は draw_pixel で弾く
+fn line_steps(p: isize, d: isize, steps: isize, (min, max): (isize, isize)) -> (isize, isize) {
+    if d == 0 {
+        return if min <= p && p <= max { (0, steps) } else { (1, 0) };
+    }
+    let (a, b) = ((min - 1 - p) * steps, (max + 1 - p) * steps);
+    // d が負なら不等号の向きが逆になる
+    let (a, b, d) = if d > 0 { (a, b, d) } else { (-b, -a, -d) };
+    // a <= i * d <= b
+    ((a + d - 1).div_euclid(d), b.div_euclid(d))
+}
+
+// 楕円 (x/rx)^2 + (y/ry)^2 <= 1 の, 中心から dy 行離れた所での半分の幅
+fn half_width(rx: usize, ry: usize, dy: usize) -> isize {
+    if dy > ry {
+        return -1;
+    }
+    if ry == 0 {
+        return rx as isize;
+    }
+    // u64 では半径が 2^16 を超えると溢れるので u128 で計算する (それでも溢れる大きさなら飽和させた近似値になる)
+    let (rx, ry, dy) = (rx as u128, ry as u128, dy as u128);
+    let w = isqrt((rx * rx).saturating_mul(ry * ry - dy * dy) / (ry * ry)).min(rx);
+    w.min(isize::MAX as u128) as isize
+}
+
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // Newton 法
+    let mut x = n;
+    let mut y = x / 2 + x % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+// 角の半径は短い辺の半分まで
+fn clamp_radius(size: Vector2D<usize>, radius: usize) -> usize {
+    let shorter = size.x().min(size.y());
+    if shorter == 0 { 0 } else { radius.min((shorter - 1) / 2) }
+}
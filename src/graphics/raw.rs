@@ -0,0 +1,113 @@
+//! 32bit pixel の配列 (frame buffer, shadow buffer, layer) への一括操作
+//! pixel の位置と format の変換を pixel ごとではなく行ごとに行う
+
+use super::{PixelColor, PixelMask, Rectangle, Vector2D};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RawPixels {
+    base: *mut u32,
+    // 1 行の pixel 数 (width 以上)
+    stride: usize,
+    width: usize,
+    height: usize,
+    mask: PixelMask,
+}
+
+impl RawPixels {
+    pub fn new(base: *mut u32, stride: usize, width: usize, height: usize, mask: PixelMask) -> Self {
+        Self { base, stride, width, height, mask }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn mask(&self) -> PixelMask {
+        self.mask
+    }
+
+    pub fn bounds(&self) -> Rectangle {
+        Rectangle::new(Vector2D::new(0, 0), Vector2D::new(self.width, self.height))
+    }
+
+    // y 行目の先頭
+    pub fn row_ptr(&self, y: usize) -> *mut u32 {
+        unsafe { self.base.add(self.stride * y) }
+    }
+
+    // 呼び出し側で x < width, y < height を保証すること
+    pub fn read_raw(&self, x: usize, y: usize) -> u32 {
+        unsafe { self.row_ptr(y).add(x).read_volatile() }
+    }
+
+    pub fn write_raw(&self, x: usize, y: usize, pixel: u32) {
+        unsafe { self.row_ptr(y).add(x).write_volatile(pixel) }
+    }
+
+    // 範囲外なら何もしない
+    pub fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        self.write_raw(x, y, self.mask.encode(color));
+        true
+    }
+
+    pub fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.mask.decode(self.read_raw(x, y)))
+    }
+
+    // 実際に塗った領域を返す
+    pub fn fill_rect(&self, rect: Rectangle, color: &PixelColor) -> Option<Rectangle> {
+        let rect = rect.intersection(&self.bounds())?;
+        let pixel = self.mask.encode(color);
+        for y in rect.y()..rect.bottom() {
+            let row = unsafe { core::slice::from_raw_parts_mut(self.row_ptr(y).add(rect.x()), rect.width()) };
+            row.fill(pixel);
+        }
+        Some(rect)
+    }
+
+    // 幅 width の pixels を pos に描く. 実際に描いた領域を返す
+    pub fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) -> Option<Rectangle> {
+        if width == 0 {
+            return None;
+        }
+        let src = Rectangle::new(pos, Vector2D::new(width, pixels.len() / width));
+        let rect = src.intersection(&self.bounds())?;
+        let (sx, sy) = (rect.x() - pos.x(), rect.y() - pos.y());
+        for dy in 0..rect.height() {
+            let src_row = &pixels[(sy + dy) * width + sx..][..rect.width()];
+            let dst = self.row_ptr(rect.y() + dy);
+            for (dx, color) in src_row.iter().enumerate() {
+                unsafe { dst.add(rect.x() + dx).write_volatile(self.mask.encode(color)) };
+            }
+        }
+        Some(rect)
+    }
+
+    // area の中身を dy 行上にずらし, 空いた下端を fill で塗る. 実際に変わった領域を返す
+    pub fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) -> Option<Rectangle> {
+        let area = area.intersection(&self.bounds())?;
+        let dy = dy.min(area.height());
+        for y in area.y()..area.bottom() - dy {
+            // 同じ buffer 内のコピー (memmove)
+            unsafe {
+                core::ptr::copy(self.row_ptr(y + dy).add(area.x()), self.row_ptr(y).add(area.x()), area.width());
+            }
+        }
+        let cleared = Rectangle::new(
+            Vector2D::new(area.x(), area.bottom() - dy),
+            Vector2D::new(area.width(), dy),
+        );
+        self.fill_rect(cleared, fill);
+        Some(area)
+    }
+}
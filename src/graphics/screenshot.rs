@@ -0,0 +1,237 @@
+//! 画面の screenshot
+//! 合成済みの画面 (WRITER. shadow buffer がなければ frame buffer) を読み, PPM か BMP に encode して ImageSink に書き出す.
+//! serial に送るときは frame に分けて CRC-32 を付ける. host 側では tools/screenshot.py で組み立てる.
+//!
+//! frame (数値は little endian):
+//!   magic "PSHT" | kind: u8 | seq: u16 | len: u16 | payload: [u8; len] | crc32(kind から payload まで): u32
+//! kind:
+//!   BEGIN: format: u8 (b'P' か b'B') | width: u32 | height: u32 | 画像の byte 数: u32
+//!   DATA:  画像の続き
+//!   END:   画像全体の crc32: u32
+
+use super::{PixelColor, PixelWriter, WRITER};
+use crate::serial::SERIAL;
+use crate::utils::crc32::Crc32;
+use core::fmt;
+
+const FRAME_MAGIC: &[u8; 4] = b"PSHT";
+const FRAME_BEGIN: u8 = 0;
+const FRAME_DATA: u8 = 1;
+const FRAME_END: u8 = 2;
+// 1 frame の payload の最大
+const MAX_PAYLOAD: usize = 1024;
+
+const BMP_HEADER_SIZE: usize = 14 + 40;
+
+// host からこの byte を受け取ったら screenshot を送る (Ctrl-P, Ctrl-B)
+pub const REQUEST_PPM: u8 = 0x10;
+pub const REQUEST_BMP: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenshotFormat {
+    // binary (P6), 8 bit RGB
+    Ppm,
+    // 24 bit, 下の行から
+    Bmp,
+}
+
+impl ScreenshotFormat {
+    fn id(&self) -> u8 {
+        match self {
+            ScreenshotFormat::Ppm => b'P',
+            ScreenshotFormat::Bmp => b'B',
+        }
+    }
+
+    // encode した結果の byte 数
+    pub fn encoded_size(&self, width: usize, height: usize) -> usize {
+        match self {
+            ScreenshotFormat::Ppm => ppm_header_len(width, height) + width * height * 3,
+            ScreenshotFormat::Bmp => BMP_HEADER_SIZE + bmp_stride(width) * height,
+        }
+    }
+}
+
+// encode した画像の書き出し先 (serial, 将来は file)
+pub trait ImageSink {
+    fn write(&mut self, data: &[u8]);
+}
+
+// 小さい書き込みをまとめて sink に渡す
+struct BufferedSink<'a> {
+    sink: &'a mut dyn ImageSink,
+    buf: [u8; MAX_PAYLOAD],
+    len: usize,
+}
+
+impl<'a> BufferedSink<'a> {
+    fn new(sink: &'a mut dyn ImageSink) -> Self {
+        Self { sink, buf: [0; MAX_PAYLOAD], len: 0 }
+    }
+
+    fn put(&mut self, data: &[u8]) {
+        for &b in data {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.len > 0 {
+            self.sink.write(&self.buf[..self.len]);
+            self.len = 0;
+        }
+    }
+}
+
+impl fmt::Write for BufferedSink<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.put(s.as_bytes());
+        Ok(())
+    }
+}
+
+// source 全体を format で encode して sink に書き出す
+pub fn encode(source: &dyn PixelWriter, format: ScreenshotFormat, sink: &mut dyn ImageSink) {
+    let (width, height) = source.resolution();
+    let mut out = BufferedSink::new(sink);
+    // 読めない pixel は黒にする
+    let pixel = |x, y| source.read_pixel(x, y).unwrap_or(PixelColor::BLACK);
+    match format {
+        ScreenshotFormat::Ppm => {
+            use core::fmt::Write;
+            write!(out, "P6\n{} {}\n255\n", width, height).unwrap();
+            for y in 0..height {
+                for x in 0..width {
+                    let color = pixel(x, y);
+                    out.put(&[color.red(), color.green(), color.blue()]);
+                }
+            }
+        },
+        ScreenshotFormat::Bmp => {
+            let stride = bmp_stride(width);
+            let image_size = (stride * height) as u32;
+            let file_size = BMP_HEADER_SIZE as u32 + image_size;
+            // BITMAPFILEHEADER
+            out.put(b"BM");
+            out.put(&file_size.to_le_bytes());
+            out.put(&[0; 4]);
+            out.put(&(BMP_HEADER_SIZE as u32).to_le_bytes());
+            // BITMAPINFOHEADER (高さが正なので下の行から)
+            out.put(&40u32.to_le_bytes());
+            out.put(&(width as i32).to_le_bytes());
+            out.put(&(height as i32).to_le_bytes());
+            out.put(&1u16.to_le_bytes());
+            out.put(&24u16.to_le_bytes());
+            out.put(&0u32.to_le_bytes());
+            out.put(&image_size.to_le_bytes());
+            // 2835 pixel/m (72 dpi)
+            out.put(&2835i32.to_le_bytes());
+            out.put(&2835i32.to_le_bytes());
+            out.put(&[0; 8]);
+            let padding = [0; 3];
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    let color = pixel(x, y);
+                    out.put(&[color.blue(), color.green(), color.red()]);
+                }
+                out.put(&padding[..stride - width * 3]);
+            }
+        },
+    }
+    out.flush();
+}
+
+fn ppm_header_len(width: usize, height: usize) -> usize {
+    let digits = |mut n: usize| {
+        let mut len = 1;
+        while n >= 10 {
+            n /= 10;
+            len += 1;
+        }
+        len
+    };
+    // "P6\n" + "{width} {height}\n" + "255\n"
+    3 + digits(width) + 1 + digits(height) + 1 + 4
+}
+
+// 各行は 4 byte 境界に揃える
+fn bmp_stride(width: usize) -> usize {
+    (width * 3 + 3) & !3
+}
+
+// serial に frame に分けて送る. serial がなければ捨てる
+pub struct SerialSink {
+    seq: u16,
+    crc: Crc32,
+}
+
+impl SerialSink {
+    // BEGIN frame を送る
+    pub fn begin(format: ScreenshotFormat, width: usize, height: usize) -> Self {
+        let mut sink = Self { seq: 0, crc: Crc32::new() };
+        let mut payload = [0; 13];
+        payload[0] = format.id();
+        payload[1..5].copy_from_slice(&(width as u32).to_le_bytes());
+        payload[5..9].copy_from_slice(&(height as u32).to_le_bytes());
+        payload[9..13].copy_from_slice(&(format.encoded_size(width, height) as u32).to_le_bytes());
+        sink.send_frame(FRAME_BEGIN, &payload);
+        sink
+    }
+
+    // END frame を送る
+    pub fn end(mut self) {
+        let crc = self.crc.finish();
+        self.send_frame(FRAME_END, &crc.to_le_bytes());
+    }
+
+    // 送信中も kprint できるように, lock は frame ごとに取る
+    fn send_frame(&mut self, kind: u8, payload: &[u8]) {
+        let mut header = [0; 9];
+        header[..4].copy_from_slice(FRAME_MAGIC);
+        header[4] = kind;
+        header[5..7].copy_from_slice(&self.seq.to_le_bytes());
+        header[7..9].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        let mut crc = Crc32::new();
+        crc.update(&header[4..]);
+        crc.update(payload);
+        if let Some(port) = SERIAL.lock().as_mut() {
+            port.write_bytes(&header);
+            port.write_bytes(payload);
+            port.write_bytes(&crc.finish().to_le_bytes());
+        }
+        self.seq = self.seq.wrapping_add(1);
+    }
+}
+
+impl ImageSink for SerialSink {
+    fn write(&mut self, data: &[u8]) {
+        self.crc.update(data);
+        for chunk in data.chunks(MAX_PAYLOAD) {
+            self.send_frame(FRAME_DATA, chunk);
+        }
+    }
+}
+
+// 今の画面を serial に送る. init_global_writer の後に呼ぶこと
+pub fn send_to_serial(format: ScreenshotFormat) {
+    let writer = unsafe { WRITER.lock().assume_init() };
+    let (width, height) = writer.resolution();
+    let mut sink = SerialSink::begin(format, width, height);
+    encode(writer, format, &mut sink);
+    sink.end();
+}
+
+// serial から受け取った byte が screenshot の要求なら送って true を返す
+pub fn handle_request(byte: u8) -> bool {
+    let format = match byte {
+        REQUEST_PPM => ScreenshotFormat::Ppm,
+        REQUEST_BMP => ScreenshotFormat::Bmp,
+        _ => return false,
+    };
+    send_to_serial(format);
+    true
+}
@@ -0,0 +1,250 @@
+//! frame buffer と同じ pixel format の off-screen buffer
+//! 描画した領域を dirty rectangle として記録しておき, flush でその領域だけを frame buffer にコピーする
+
+use super::raw::RawPixels;
+use super::{FrameBuffer, PixelColor, PixelMask, PixelWriter, Rectangle, Vector2D};
+use crate::memory::Frames;
+use crate::sync::SpinMutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const BYTES_PER_PIXEL: usize = 4;
+const MAX_DIRTY_RECTS: usize = 16;
+
+// 幅 x 高さの u32 pixel の配列 (pixel は frame buffer と同じ mask で格納する)
+pub struct PixelBuffer {
+    // raw が指すメモリ
+    _frames: Frames,
+    raw: RawPixels,
+}
+
+impl PixelBuffer {
+    pub fn new(width: usize, height: usize, mask: PixelMask) -> Option<Self> {
+        let frames = Frames::allocate_bytes(width * height * BYTES_PER_PIXEL)?;
+        let raw = RawPixels::new(frames.as_mut_ptr() as *mut u32, width, width, height, mask);
+        Some(Self { _frames: frames, raw })
+    }
+
+    pub fn width(&self) -> usize {
+        self.raw.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.raw.height()
+    }
+
+    pub fn mask(&self) -> PixelMask {
+        self.raw.mask()
+    }
+
+    pub fn bounds(&self) -> Rectangle {
+        self.raw.bounds()
+    }
+
+    pub fn raw(&self) -> &RawPixels {
+        &self.raw
+    }
+
+    // y 行目の先頭
+    pub(super) fn row_ptr(&self, y: usize) -> *mut u32 {
+        self.raw.row_ptr(y)
+    }
+
+    pub fn read_raw(&self, x: usize, y: usize) -> u32 {
+        self.raw.read_raw(x, y)
+    }
+
+    pub fn write_raw(&self, x: usize, y: usize, pixel: u32) {
+        self.raw.write_raw(x, y, pixel)
+    }
+}
+
+// 重なる (接する) 矩形はまとめて, 最大 MAX_DIRTY_RECTS 個で保持する
+pub(super) struct DirtyRects {
+    rects: [Option<Rectangle>; MAX_DIRTY_RECTS],
+}
+
+impl DirtyRects {
+    pub(super) const fn new() -> Self {
+        Self { rects: [None; MAX_DIRTY_RECTS] }
+    }
+
+    pub(super) fn add(&mut self, rect: Rectangle) {
+        if rect.is_empty() {
+            return;
+        }
+        // 同じ領域に繰り返し描画されることが多いので, 既存の矩形に含まれるかを先に調べる
+        if self.rects.iter().flatten().any(|r| r.contains(&rect)) {
+            return;
+        }
+
+        let mut rect = rect;
+        // 結合すると他の矩形とも接するようになることがあるので, なくなるまで繰り返す
+        while let Some(i) = self.rects.iter().position(|r| r.map_or(false, |r| touches(&r, &rect))) {
+            rect = rect.union(&self.rects[i].take().unwrap());
+        }
+
+        if let Some(slot) = self.rects.iter_mut().find(|r| r.is_none()) {
+            *slot = Some(rect);
+            return;
+        }
+        // 空きがなければ, 結合しても面積の増加が最小のものとまとめる
+        let (i, _) = self.rects.iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let r = r.unwrap();
+                (i, r.union(&rect).area() - r.area())
+            })
+            .min_by_key(|&(_, growth)| growth)
+            .unwrap();
+        self.rects[i] = Some(self.rects[i].unwrap().union(&rect));
+    }
+
+    pub(super) fn take(&mut self) -> [Option<Rectangle>; MAX_DIRTY_RECTS] {
+        core::mem::replace(&mut self.rects, [None; MAX_DIRTY_RECTS])
+    }
+}
+
+fn touches(a: &Rectangle, b: &Rectangle) -> bool {
+    a.x() <= b.right() && b.x() <= a.right() && a.y() <= b.bottom() && b.y() <= a.bottom()
+}
+
+// draw_pixel で描いた pixel をすべて囲む矩形.
+// 1 pixel ごとに lock を取って DirtyRects に足すと遅いので, atomic で広げておき flush の前に 1 つの矩形として足す
+pub(super) struct PixelBounds {
+    left: AtomicUsize,
+    top: AtomicUsize,
+    // right, bottom は含まない
+    right: AtomicUsize,
+    bottom: AtomicUsize,
+}
+
+impl PixelBounds {
+    pub(super) const fn new() -> Self {
+        Self {
+            left: AtomicUsize::new(usize::MAX),
+            top: AtomicUsize::new(usize::MAX),
+            right: AtomicUsize::new(0),
+            bottom: AtomicUsize::new(0),
+        }
+    }
+
+    pub(super) fn add(&self, x: usize, y: usize) {
+        self.left.fetch_min(x, Ordering::Relaxed);
+        self.top.fetch_min(y, Ordering::Relaxed);
+        self.right.fetch_max(x + 1, Ordering::Relaxed);
+        self.bottom.fetch_max(y + 1, Ordering::Relaxed);
+    }
+
+    // 空に戻し, 何も描いていなければ None
+    pub(super) fn take(&self) -> Option<Rectangle> {
+        let left = self.left.swap(usize::MAX, Ordering::Relaxed);
+        let top = self.top.swap(usize::MAX, Ordering::Relaxed);
+        let right = self.right.swap(0, Ordering::Relaxed);
+        let bottom = self.bottom.swap(0, Ordering::Relaxed);
+        if left < right && top < bottom {
+            Some(Rectangle::new(Vector2D::new(left, top), Vector2D::new(right - left, bottom - top)))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct ShadowBuffer {
+    buffer: PixelBuffer,
+    target: RawPixels,
+    dirty: SpinMutex<DirtyRects>,
+    // draw_pixel で描いた領域 (flush で dirty に足す)
+    pixels: PixelBounds,
+}
+
+impl ShadowBuffer {
+    // frame buffer の今の内容をコピーして作る. メモリが確保できなければ None
+    pub fn new(target: FrameBuffer) -> Option<Self> {
+        let target = target.raw();
+        let buffer = PixelBuffer::new(target.width(), target.height(), target.mask())?;
+        for y in 0..target.height() {
+            unsafe {
+                core::ptr::copy_nonoverlapping(target.row_ptr(y), buffer.row_ptr(y), target.width());
+            }
+        }
+        Some(Self {
+            buffer,
+            target,
+            dirty: SpinMutex::new(DirtyRects::new()),
+            pixels: PixelBounds::new(),
+        })
+    }
+
+    fn bounds(&self) -> Rectangle {
+        self.buffer.bounds()
+    }
+
+    // 直接書き込んだ場合は mark_dirty で領域を知らせること
+    pub(super) fn buffer(&self) -> &PixelBuffer {
+        &self.buffer
+    }
+
+    pub fn mark_dirty(&self, rect: Rectangle) {
+        if let Some(rect) = rect.intersection(&self.bounds()) {
+            self.dirty.lock().add(rect);
+        }
+    }
+
+    // dirty rectangle を 1 行ずつまとめて frame buffer にコピーする
+    pub fn flush(&self) {
+        let rects = {
+            let mut dirty = self.dirty.lock();
+            if let Some(rect) = self.pixels.take() {
+                dirty.add(rect);
+            }
+            dirty.take()
+        };
+        for rect in rects.iter().flatten() {
+            for y in rect.y()..rect.bottom() {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        self.buffer.row_ptr(y).add(rect.x()),
+                        self.target.row_ptr(y).add(rect.x()),
+                        rect.width(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl PixelWriter for ShadowBuffer {
+    fn horizontal_resolution(&self) -> usize {
+        self.buffer.width()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.buffer.height()
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        if self.buffer.raw().draw_pixel(x, y, color) {
+            self.pixels.add(x, y);
+        }
+    }
+
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        self.buffer.raw().read_pixel(x, y)
+    }
+
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        if let Some(rect) = self.buffer.raw().fill_rect(Rectangle::new(pos, size), color) {
+            self.dirty.lock().add(rect);
+        }
+    }
+
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        if let Some(rect) = self.buffer.raw().blit(pos, width, pixels) {
+            self.dirty.lock().add(rect);
+        }
+    }
+
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        if let Some(rect) = self.buffer.raw().scroll_up(area, dy, fill) {
+            self.dirty.lock().add(rect);
+        }
+    }
+}
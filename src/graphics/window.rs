@@ -0,0 +1,365 @@
+//! title bar 付きの window
+//! window は 1 つの layer に枠, title bar, 閉じるボタンと client 領域を描いたもの.
+//! mouse の位置とボタンの状態 (handle_mouse) から, 当たり判定, 最前面への移動, ドラッグ, 閉じる操作を行う.
+
+use super::layer::{Layer, LayerId, LayerManager, LAYER_MANAGER};
+use super::painter::Painter;
+use super::{Font, PixelColor, PixelWriter, Rectangle, ShinonomeFont, Vector2D};
+use crate::sync::IrqSpinMutex;
+use crate::utils::fixed_vec::FixedVec;
+
+const MAX_WINDOWS: usize = 8;
+
+const BORDER: usize = 2;
+const TITLE_BAR_HEIGHT: usize = 20;
+const CLOSE_BUTTON_SIZE: usize = 14;
+// これより狭い client 領域は広げる (閉じるボタンと両側の余白が title bar に収まる幅)
+const MIN_CLIENT_WIDTH: usize = TITLE_BAR_HEIGHT;
+
+const FRAME_COLOR: PixelColor = PixelColor::new(198, 198, 198);
+const ACTIVE_TITLE_COLOR: PixelColor = PixelColor::new(0, 0, 132);
+const INACTIVE_TITLE_COLOR: PixelColor = PixelColor::new(132, 132, 132);
+const TITLE_TEXT_COLOR: PixelColor = PixelColor::WHITE;
+const CLIENT_COLOR: PixelColor = PixelColor::WHITE;
+
+const TITLE_FONT: ShinonomeFont = ShinonomeFont::new();
+
+// mouse のボタンの bit (mouse::MouseButton と同じ)
+const LEFT_BUTTON: u8 = 0b001;
+
+pub type WindowId = usize;
+
+struct Window {
+    layer: LayerId,
+    title: &'static str,
+    closed: bool,
+}
+
+// window の中で mouse が指している部分
+#[derive(Clone, Copy, PartialEq)]
+enum Hit {
+    TitleBar,
+    CloseButton,
+    Client,
+}
+
+pub struct WindowManager {
+    // WindowId は windows の index
+    windows: FixedVec<'static, Window, MAX_WINDOWS>,
+    focused: Option<WindowId>,
+    // ドラッグ中の window と, window の左上から見た cursor の位置
+    drag: Option<(WindowId, Vector2D<usize>)>,
+}
+
+impl WindowManager {
+    pub const fn new() -> Self {
+        Self { windows: FixedVec::new(), focused: None, drag: None }
+    }
+
+    pub fn focused(&self) -> Option<WindowId> {
+        self.focused
+    }
+
+    // client 領域が width x height の window を pos に作り, focus する
+    pub fn new_window(
+        &mut self,
+        layers: &mut LayerManager,
+        title: &'static str,
+        width: usize,
+        height: usize,
+        pos: Vector2D<usize>,
+    ) -> Option<WindowId> {
+        if self.windows.len() == MAX_WINDOWS {
+            return None;
+        }
+        let (frame_width, frame_height) = frame_size(width, height);
+        let layer = layers.new_layer(frame_width, frame_height, None)?;
+        layers.move_to(layer, pos);
+        draw_frame(layers.layer(layer), title, false);
+        let id = self.windows.len();
+        self.windows.push(Window { layer, title, closed: false });
+        self.focus(layers, id);
+        Some(id)
+    }
+
+    fn window(&self, id: WindowId) -> &Window {
+        &self.windows.as_slice()[id]
+    }
+
+    pub fn client_area<'a>(&self, layers: &'a LayerManager, id: WindowId) -> ClientArea<'a> {
+        ClientArea::new(layers.layer(self.window(id).layer))
+    }
+
+    // 最前面に移し, title bar の色を変える
+    pub fn focus(&mut self, layers: &mut LayerManager, id: WindowId) {
+        if self.window(id).closed {
+            return;
+        }
+        layers.raise_to_top(self.window(id).layer);
+        if self.focused == Some(id) {
+            return;
+        }
+        if let Some(old) = self.focused {
+            let window = self.window(old);
+            draw_title_bar(layers.layer(window.layer), window.title, false);
+        }
+        let window = self.window(id);
+        draw_title_bar(layers.layer(window.layer), window.title, true);
+        self.focused = Some(id);
+    }
+
+    // layer は再利用できないので, 非表示にして残しておく
+    pub fn close(&mut self, layers: &mut LayerManager, id: WindowId) {
+        let window = &mut self.windows.as_mut_slice()[id];
+        if window.closed {
+            return;
+        }
+        window.closed = true;
+        layers.set_visible(window.layer, false);
+        if self.drag.map(|(dragged, _)| dragged) == Some(id) {
+            self.drag = None;
+        }
+        if self.focused == Some(id) {
+            self.focused = None;
+            // 残っている window のうち一番上のものに focus を移す
+            if let Some(next) = self.window_at_top(layers) {
+                self.focus(layers, next);
+            }
+        }
+    }
+
+    // (x, y) にある一番上の window
+    fn window_at(&self, layers: &LayerManager, x: usize, y: usize) -> Option<WindowId> {
+        layers.z_order().iter().rev()
+            .filter(|&&layer| layers.layer(layer).is_visible() && layers.layer(layer).rect().contains_point(x, y))
+            .find_map(|&layer| self.window_of(layer))
+    }
+
+    fn window_at_top(&self, layers: &LayerManager) -> Option<WindowId> {
+        layers.z_order().iter().rev()
+            .filter(|&&layer| layers.layer(layer).is_visible())
+            .find_map(|&layer| self.window_of(layer))
+    }
+
+    fn window_of(&self, layer: LayerId) -> Option<WindowId> {
+        self.windows.as_slice().iter().position(|window| window.layer == layer && !window.closed)
+    }
+
+    // mouse の位置 pos と, 前回と今回のボタンの状態から window を操作する
+    pub fn handle_mouse(&mut self, layers: &mut LayerManager, pos: Vector2D<usize>, prev_buttons: u8, buttons: u8) {
+        let pressed = buttons & !prev_buttons;
+        let released = prev_buttons & !buttons;
+
+        if released & LEFT_BUTTON != 0 {
+            self.drag = None;
+        }
+        if let Some((id, grab)) = self.drag {
+            // 画面の左上より外には出さない
+            let x = pos.x().saturating_sub(grab.x());
+            let y = pos.y().saturating_sub(grab.y());
+            layers.move_to(self.window(id).layer, Vector2D::new(x, y));
+        }
+        if pressed & LEFT_BUTTON == 0 {
+            return;
+        }
+
+        let id = match self.window_at(layers, pos.x(), pos.y()) {
+            Some(id) => id,
+            None => return,
+        };
+        let origin = layers.layer(self.window(id).layer).pos();
+        let local = Vector2D::new(pos.x() - origin.x(), pos.y() - origin.y());
+        let (width, _) = layers.layer(self.window(id).layer).resolution();
+        match hit_test(width, local) {
+            Some(Hit::CloseButton) => self.close(layers, id),
+            Some(Hit::TitleBar) => {
+                self.focus(layers, id);
+                self.drag = Some((id, local));
+            },
+            Some(Hit::Client) | None => self.focus(layers, id),
+        }
+    }
+}
+
+pub static WINDOW_MANAGER: IrqSpinMutex<WindowManager> = IrqSpinMutex::new(WindowManager::new());
+
+// layer manager があれば window を作って画面に反映する
+pub fn new_window(title: &'static str, width: usize, height: usize, pos: Vector2D<usize>) -> Option<WindowId> {
+    let mut windows = WINDOW_MANAGER.lock();
+    let mut layers = LAYER_MANAGER.lock();
+    let layers = layers.as_mut()?;
+    let id = windows.new_window(layers, title, width, height, pos)?;
+    layers.composite();
+    Some(id)
+}
+
+// id の window の client 領域に描画して画面に反映する
+pub fn draw_in_window<F: FnOnce(&ClientArea)>(id: WindowId, f: F) {
+    let windows = WINDOW_MANAGER.lock();
+    if let Some(layers) = LAYER_MANAGER.lock().as_mut() {
+        f(&windows.client_area(layers, id));
+        layers.composite();
+    }
+}
+
+// pos にある一番上の window
+pub fn window_at(pos: Vector2D<usize>) -> Option<WindowId> {
+    let windows = WINDOW_MANAGER.lock();
+    let layers = LAYER_MANAGER.lock();
+    windows.window_at(layers.as_ref()?, pos.x(), pos.y())
+}
+
+pub fn handle_mouse(pos: Vector2D<usize>, prev_buttons: u8, buttons: u8) {
+    let mut windows = WINDOW_MANAGER.lock();
+    if let Some(layers) = LAYER_MANAGER.lock().as_mut() {
+        windows.handle_mouse(layers, pos, prev_buttons, buttons);
+        layers.composite();
+    }
+}
+
+// client 領域の大きさから枠を含めた大きさへ
+fn frame_size(width: usize, height: usize) -> (usize, usize) {
+    (width.max(MIN_CLIENT_WIDTH) + 2 * BORDER, height + TITLE_BAR_HEIGHT + 3 * BORDER)
+}
+
+// 枠を含めて frame_width x frame_height に収まる client 領域の大きさ
+pub fn client_size(frame_width: usize, frame_height: usize) -> (usize, usize) {
+    (frame_width.saturating_sub(2 * BORDER), frame_height.saturating_sub(TITLE_BAR_HEIGHT + 3 * BORDER))
+}
+
+fn title_bar_rect(frame_width: usize) -> Rectangle {
+    Rectangle::new(Vector2D::new(BORDER, BORDER), Vector2D::new(frame_width - 2 * BORDER, TITLE_BAR_HEIGHT))
+}
+
+fn close_button_rect(frame_width: usize) -> Rectangle {
+    let margin = (TITLE_BAR_HEIGHT - CLOSE_BUTTON_SIZE) / 2;
+    Rectangle::new(
+        Vector2D::new(frame_width - BORDER - margin - CLOSE_BUTTON_SIZE, BORDER + margin),
+        Vector2D::new(CLOSE_BUTTON_SIZE, CLOSE_BUTTON_SIZE),
+    )
+}
+
+fn client_rect(frame: Rectangle) -> Rectangle {
+    Rectangle::new(
+        Vector2D::new(BORDER, TITLE_BAR_HEIGHT + 2 * BORDER),
+        Vector2D::new(frame.width() - 2 * BORDER, frame.height() - TITLE_BAR_HEIGHT - 3 * BORDER),
+    )
+}
+
+// pos は window の左上から見た位置. 枠の上なら None
+fn hit_test(frame_width: usize, pos: Vector2D<usize>) -> Option<Hit> {
+    if close_button_rect(frame_width).contains_point(pos.x(), pos.y()) {
+        Some(Hit::CloseButton)
+    } else if title_bar_rect(frame_width).contains_point(pos.x(), pos.y()) {
+        Some(Hit::TitleBar)
+    } else if pos.y() >= TITLE_BAR_HEIGHT + 2 * BORDER {
+        Some(Hit::Client)
+    } else {
+        None
+    }
+}
+
+fn draw_frame(layer: &Layer, title: &str, active: bool) {
+    let (width, height) = layer.resolution();
+    layer.fill_rect(Vector2D::new(0, 0), Vector2D::new(width, height), &FRAME_COLOR);
+    let client = client_rect(layer.rect());
+    layer.fill_rect(client.pos(), client.size(), &CLIENT_COLOR);
+    draw_title_bar(layer, title, active);
+}
+
+fn draw_title_bar(layer: &Layer, title: &str, active: bool) {
+    let (width, _) = layer.resolution();
+    let bar = title_bar_rect(width);
+    let color = if active { ACTIVE_TITLE_COLOR } else { INACTIVE_TITLE_COLOR };
+    layer.fill_rect(bar.pos(), bar.size(), &color);
+
+    // 閉じるボタンに掛からない所まで title を描く
+    let button = close_button_rect(width);
+    let (_, font_height) = TITLE_FONT.char_size();
+    let (mut x, y) = (bar.x() + 4, bar.y() + (TITLE_BAR_HEIGHT - font_height.min(TITLE_BAR_HEIGHT)) / 2);
+    for c in title.chars() {
+        if x + TITLE_FONT.advance(c) > button.x() {
+            break;
+        }
+        x += TITLE_FONT.write_char(layer, x, y, c, &TITLE_TEXT_COLOR, &color);
+    }
+
+    layer.fill_rect(button.pos(), button.size(), &FRAME_COLOR);
+    let painter = Painter::new(layer);
+    let (left, top) = (button.x() as isize + 3, button.y() as isize + 3);
+    let (right, bottom) = (button.right() as isize - 4, button.bottom() as isize - 4);
+    painter.draw_line(Vector2D::new(left, top), Vector2D::new(right, bottom), &PixelColor::BLACK);
+    painter.draw_line(Vector2D::new(left, bottom), Vector2D::new(right, top), &PixelColor::BLACK);
+}
+
+// window の client 領域. 座標は client 領域の左上からで, 外には描かない
+pub struct ClientArea<'a> {
+    layer: &'a Layer,
+    // layer 内の位置
+    rect: Rectangle,
+}
+
+impl<'a> ClientArea<'a> {
+    fn new(layer: &'a Layer) -> Self {
+        let (width, height) = layer.resolution();
+        let frame = Rectangle::new(Vector2D::new(0, 0), Vector2D::new(width, height));
+        Self { layer, rect: client_rect(frame) }
+    }
+
+    // client 領域内の rect を layer 内の位置に直す
+    fn to_layer(&self, rect: Rectangle) -> Option<Rectangle> {
+        let bounds = Rectangle::new(Vector2D::new(0, 0), self.rect.size());
+        let rect = rect.intersection(&bounds)?;
+        Some(Rectangle::new(Vector2D::new(self.rect.x() + rect.x(), self.rect.y() + rect.y()), rect.size()))
+    }
+}
+
+impl PixelWriter for ClientArea<'_> {
+    fn horizontal_resolution(&self) -> usize {
+        self.rect.width()
+    }
+    fn vertical_resolution(&self) -> usize {
+        self.rect.height()
+    }
+    fn draw_pixel(&self, x: usize, y: usize, color: &PixelColor) {
+        if x < self.rect.width() && y < self.rect.height() {
+            self.layer.draw_pixel(self.rect.x() + x, self.rect.y() + y, color);
+        }
+    }
+
+    fn read_pixel(&self, x: usize, y: usize) -> Option<PixelColor> {
+        if x < self.rect.width() && y < self.rect.height() {
+            self.layer.read_pixel(self.rect.x() + x, self.rect.y() + y)
+        } else {
+            None
+        }
+    }
+
+    fn fill_rect(&self, pos: Vector2D<usize>, size: Vector2D<usize>, color: &PixelColor) {
+        if let Some(rect) = self.to_layer(Rectangle::new(pos, size)) {
+            self.layer.fill_rect(rect.pos(), rect.size(), color);
+        }
+    }
+
+    fn blit(&self, pos: Vector2D<usize>, width: usize, pixels: &[PixelColor]) {
+        if width == 0 || pos.x() >= self.rect.width() {
+            return;
+        }
+        // client 領域からはみ出す列は行ごとに切り落とす
+        let visible = width.min(self.rect.width() - pos.x());
+        for (dy, row) in pixels.chunks(width).enumerate() {
+            let y = pos.y() + dy;
+            if y >= self.rect.height() {
+                break;
+            }
+            let row = &row[..visible.min(row.len())];
+            self.layer.blit(Vector2D::new(self.rect.x() + pos.x(), self.rect.y() + y), row.len(), row);
+        }
+    }
+
+    fn scroll_up(&self, area: Rectangle, dy: usize, fill: &PixelColor) {
+        if let Some(area) = self.to_layer(area) {
+            self.layer.scroll_up(area, dy, fill);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! boot protocol に対応しない USB HID device (tablet など)
+//! mikanos_usb の generic HID driver から Report descriptor と report を受け取る.
+//! descriptor から pointer の field が見つかった device の report を mouse に渡す.
+
+pub mod report_descriptor;
+
+use crate::mouse::{self, PointerMotion};
+use crate::sync::SpinMutex;
+use crate::utils::fixed_vec::FixedVec;
+use crate::{info, warn};
+use report_descriptor::{Field, PointerLayout, MAX_BUTTONS};
+
+const MAX_DEVICES: usize = 4;
+
+// (driver, pointer の field)
+static DEVICES: SpinMutex<FixedVec<'static, (usize, PointerLayout), MAX_DEVICES>> = SpinMutex::new(FixedVec::new());
+
+fn as_slice<'a>(data: *const u8, len: i32) -> &'a [u8] {
+    if data.is_null() || len <= 0 {
+        return &[];
+    }
+    unsafe { core::slice::from_raw_parts(data, len as usize) }
+}
+
+// mikanos_usb の generic HID driver から, 初期化のときに呼ばれる
+pub extern "C" fn descriptor_observer(driver: usize, data: *const u8, len: i32) {
+    let layout = match report_descriptor::parse_pointer(as_slice(data, len)) {
+        Ok(layout) => layout,
+        Err(e) => {
+            info!("hid: {:x}: not a pointer device ({:?})", driver, e);
+            return;
+        },
+    };
+    info!("hid: {:x}: {} pointer", driver, if layout.x.relative { "relative" } else { "absolute" });
+    if unsafe { DEVICES.lock().try_push((driver, layout)) }.is_err() {
+        warn!("hid: too many devices");
+    }
+}
+
+// mikanos_usb の generic HID driver から, report を受け取るたびに呼ばれる
+pub extern "C" fn report_observer(driver: usize, data: *const u8, len: i32) {
+    let layout = match DEVICES.lock().as_slice().iter().find(|(d, _)| *d == driver) {
+        Some(&(_, layout)) => layout,
+        None => return,
+    };
+    let report = as_slice(data, len);
+    let read = |field: &Field| field.read(report, layout.uses_report_id);
+    // report id が違えば X も読めない
+    let (x, y) = match (read(&layout.x), read(&layout.y)) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return,
+    };
+
+    let motion = if layout.x.relative {
+        PointerMotion::Relative(x as isize, y as isize)
+    } else {
+        // logical minimum からの位置にする. i32 の引き算は溢れることがあるので i64 で計算する
+        let extent = |field: &Field| (field.logical_max as i64 - field.logical_min as i64).max(1) as u32;
+        let position = |field: &Field, value: i32| {
+            (value as i64 - field.logical_min as i64).max(0).min(extent(field) as i64) as u32
+        };
+        PointerMotion::Absolute {
+            x: position(&layout.x, x),
+            y: position(&layout.y, y),
+            x_max: extent(&layout.x),
+            y_max: extent(&layout.y),
+        }
+    };
+    let mut buttons = 0;
+    for i in 0..MAX_BUTTONS {
+        if layout.buttons[i].and_then(|field| read(&field)).unwrap_or(0) != 0 {
+            buttons |= 1 << i;
+        }
+    }
+    let wheel = layout.wheel.and_then(|field| read(&field)).unwrap_or(0);
+    mouse::handle_pointer(motion, buttons, wheel.max(i8::MIN as i32).min(i8::MAX as i32) as i8);
+}
@@ -0,0 +1,249 @@
+//! HID Report descriptor
+//! descriptor の item を順に読み, pointer (mouse, tablet) として使う field の位置を求める.
+//! 参考: Device Class Definition for HID 1.11, 6.2.2 Report Descriptor
+
+// usage page と usage
+const GENERIC_DESKTOP: u16 = 0x01;
+const BUTTON: u16 = 0x09;
+const USAGE_X: u16 = 0x30;
+const USAGE_Y: u16 = 0x31;
+const USAGE_WHEEL: u16 = 0x38;
+
+// 扱うボタンの数 (mouse::MouseButton と同じ順)
+pub const MAX_BUTTONS: usize = 3;
+
+// Report Size, Report Count として受け付ける最大値 (Field::read は 32 bit までしか読めない)
+const MAX_REPORT_SIZE: usize = 32;
+const MAX_REPORT_COUNT: usize = 256;
+
+#[derive(Debug)]
+pub enum ReportDescriptorError {
+    // item が途中で切れている
+    Truncated,
+    // Push が深すぎる, または対応する Push のない Pop
+    InvalidStack,
+    // X, Y の field がない
+    NotPointer,
+    // Report Size, Report Count が大きすぎる, または report が長すぎる
+    ReportTooLarge,
+}
+pub type Result<T> = core::result::Result<T, ReportDescriptorError>;
+
+// report の中の 1 つの値
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    report_id: u8,
+    // report id の byte を除いた report の先頭からの bit 位置
+    bit_offset: usize,
+    bit_size: usize,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    // 前回からの変化量なら true, 絶対値なら false
+    pub relative: bool,
+}
+
+impl Field {
+    // report (report id の byte を含む) から値を読む. 違う report のものなら None
+    pub fn read(&self, report: &[u8], uses_report_id: bool) -> Option<i32> {
+        let data = if uses_report_id {
+            let (&id, data) = report.split_first()?;
+            if id != self.report_id {
+                return None;
+            }
+            data
+        } else {
+            report
+        };
+        if self.bit_size == 0 || self.bit_size > 32 || (self.bit_offset + self.bit_size + 7) / 8 > data.len() {
+            return None;
+        }
+        let mut value: u32 = 0;
+        for i in 0..self.bit_size {
+            let bit = self.bit_offset + i;
+            value |= ((data[bit / 8] >> (bit % 8)) as u32 & 1) << i;
+        }
+        // logical minimum が負なら符号付き
+        if self.logical_min < 0 && self.bit_size < 32 && value & (1 << (self.bit_size - 1)) != 0 {
+            value |= !0 << self.bit_size;
+        }
+        Some(value as i32)
+    }
+}
+
+// pointer として使う field
+#[derive(Debug, Clone, Copy)]
+pub struct PointerLayout {
+    pub uses_report_id: bool,
+    pub x: Field,
+    pub y: Field,
+    pub wheel: Option<Field>,
+    pub buttons: [Option<Field>; MAX_BUTTONS],
+}
+
+// Push, Pop で保存する global item
+#[derive(Clone, Copy, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+const MAX_STACK: usize = 4;
+const MAX_USAGES: usize = 16;
+// report id は 1 byte なので, 全部の id に bit 位置を持てる
+const NUM_REPORT_IDS: usize = 256;
+
+// local item. main item ごとに消える
+#[derive(Default)]
+struct LocalState {
+    // (usage page, usage). page は 4 byte の Usage で指定されたときだけ
+    usages: [(Option<u16>, u16); MAX_USAGES],
+    usage_count: usize,
+    usage_min: Option<u32>,
+    usage_max: Option<u32>,
+}
+
+impl LocalState {
+    // i 番目の値の usage. 足りなければ最後のものを繰り返す
+    fn usage(&self, i: usize, page: u16) -> Option<(u16, u16)> {
+        if self.usage_count > 0 {
+            let (usage_page, usage) = self.usages[i.min(self.usage_count - 1)];
+            return Some((usage_page.unwrap_or(page), usage));
+        }
+        let (min, max) = (self.usage_min?, self.usage_max?);
+        let usage = (min + i as u32).min(max);
+        // 4 byte なら上位 16 bit が usage page
+        let usage_page = if min > 0xffff { (min >> 16) as u16 } else { page };
+        Some((usage_page, usage as u16))
+    }
+}
+
+// Report descriptor を読み, X, Y を持つ最初の report の field を返す
+pub fn parse_pointer(desc: &[u8]) -> Result<PointerLayout> {
+    let mut global = GlobalState::default();
+    let mut stack = [GlobalState::default(); MAX_STACK];
+    let mut depth = 0;
+    let mut local = LocalState::default();
+    let mut uses_report_id = false;
+    // report id ごとの, 次の field の bit 位置
+    let mut offsets = [0usize; NUM_REPORT_IDS];
+
+    let (mut x, mut y, mut wheel) = (None, None, None);
+    let mut buttons = [None; MAX_BUTTONS];
+
+    let mut i = 0;
+    while i < desc.len() {
+        let prefix = desc[i];
+        // long item (使われていない) は飛ばす
+        if prefix == 0xfe {
+            let size = *desc.get(i + 1).ok_or(ReportDescriptorError::Truncated)? as usize;
+            i += 3 + size;
+            continue;
+        }
+        let size = match prefix & 0b11 {
+            3 => 4,
+            n => n as usize,
+        };
+        let data = desc.get(i + 1..i + 1 + size).ok_or(ReportDescriptorError::Truncated)?;
+        i += 1 + size;
+        let unsigned = data.iter().rev().fold(0u32, |acc, &b| acc << 8 | b as u32);
+        let signed = match size {
+            1 => data[0] as i8 as i32,
+            2 => unsigned as u16 as i16 as i32,
+            _ => unsigned as i32,
+        };
+
+        match (prefix >> 2 & 0b11, prefix >> 4) {
+            // Main: Input
+            (0, 0x8) => {
+                let flags = unsigned;
+                let constant = flags & 1 != 0;
+                let variable = flags & 0b10 != 0;
+                let offset = &mut offsets[global.report_id as usize];
+                // report_size, report_count は受け付けるときに上限を確かめてある
+                let report_bits = global.report_count * global.report_size;
+                let end = offset.checked_add(report_bits).ok_or(ReportDescriptorError::ReportTooLarge)?;
+                for n in 0..global.report_count {
+                    let field = Field {
+                        report_id: global.report_id,
+                        bit_offset: *offset + n * global.report_size,
+                        bit_size: global.report_size,
+                        logical_min: global.logical_min,
+                        logical_max: global.logical_max,
+                        relative: flags & 0b100 != 0,
+                    };
+                    // array (variable でない) は押されているキーの一覧なので使わない
+                    if constant || !variable {
+                        continue;
+                    }
+                    // 最初に見つかった report のものだけを使う
+                    let same_report = x.map_or(true, |x: Field| x.report_id == field.report_id);
+                    match local.usage(n, global.usage_page) {
+                        Some((GENERIC_DESKTOP, USAGE_X)) if x.is_none() => x = Some(field),
+                        Some((GENERIC_DESKTOP, USAGE_Y)) if y.is_none() && same_report => y = Some(field),
+                        Some((GENERIC_DESKTOP, USAGE_WHEEL)) if wheel.is_none() && same_report => wheel = Some(field),
+                        Some((BUTTON, button @ 1..=3)) if same_report => {
+                            let slot = &mut buttons[button as usize - 1];
+                            if slot.is_none() {
+                                *slot = Some(field);
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+                *offset = end;
+                local = LocalState::default();
+            },
+            // Main: Output, Feature, Collection, End Collection
+            (0, _) => local = LocalState::default(),
+            // Global
+            (1, 0x0) => global.usage_page = unsigned as u16,
+            (1, 0x1) => global.logical_min = signed,
+            // 最小値が 0 以上なら最大値は符号なしとして読む
+            (1, 0x2) => global.logical_max = if global.logical_min >= 0 { unsigned as i32 } else { signed },
+            (1, 0x7) => {
+                global.report_size = unsigned as usize;
+                if global.report_size > MAX_REPORT_SIZE {
+                    return Err(ReportDescriptorError::ReportTooLarge);
+                }
+            },
+            (1, 0x8) => {
+                global.report_id = unsigned as u8;
+                uses_report_id = true;
+            },
+            (1, 0x9) => {
+                global.report_count = unsigned as usize;
+                if global.report_count > MAX_REPORT_COUNT {
+                    return Err(ReportDescriptorError::ReportTooLarge);
+                }
+            },
+            (1, 0xa) => {
+                *stack.get_mut(depth).ok_or(ReportDescriptorError::InvalidStack)? = global;
+                depth += 1;
+            },
+            (1, 0xb) => {
+                depth = depth.checked_sub(1).ok_or(ReportDescriptorError::InvalidStack)?;
+                global = stack[depth];
+            },
+            // Local
+            (2, 0x0) => {
+                if local.usage_count < MAX_USAGES {
+                    let page = if size == 4 { Some((unsigned >> 16) as u16) } else { None };
+                    local.usages[local.usage_count] = (page, unsigned as u16);
+                    local.usage_count += 1;
+                }
+            },
+            (2, 0x1) => local.usage_min = Some(unsigned),
+            (2, 0x2) => local.usage_max = Some(unsigned),
+            _ => {},
+        }
+    }
+
+    match (x, y) {
+        (Some(x), Some(y)) => Ok(PointerLayout { uses_report_id, x, y, wheel, buttons }),
+        _ => Err(ReportDescriptorError::NotPointer),
+    }
+}
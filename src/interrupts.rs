@@ -1,22 +1,27 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use idt::InterruptVector;
 
+// vector ごとの割り込みの回数 (shell の irq で表示する)
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static INTERRUPT_COUNTS: [AtomicU64; 256] = [ZERO; 256];
 
+fn count_interrupt(vector: InterruptVector) {
+    INTERRUPT_COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn interrupt_count(vector: InterruptVector) -> u64 {
+    INTERRUPT_COUNTS[vector as usize].load(Ordering::Relaxed)
+}
 
 mod interrupt_handler {
-    use crate::xhc::XHC_CONTROLLER;
-    use crate::error;
-    use super::idt::InterruptStackFrame;
+    use super::count_interrupt;
+    use super::idt::{InterruptStackFrame, InterruptVector};
 
+    // observer が CONSOLE などを lock するので, event の処理は main loop (xhc::process_events) で行う.
+    // ここでは hlt から起こすだけ
     pub extern "x86-interrupt" fn xhc_handler(_frame: *mut InterruptStackFrame) {
-        panic!("xhc_handler");
-        /*
-        let mut controller = XHC_CONTROLLER.lock();
-        let controller = controller.as_mut().unwrap();
-        while controller.has_event() {
-            if let Err(e) = controller.process_event() {
-                error!("{:?}", e);
-            }
-        }
-        */
+        count_interrupt(InterruptVector::XHCI);
         notify_end_of_interrupt();
     }
 
@@ -25,36 +30,62 @@ mod interrupt_handler {
         unsafe { core::ptr::write_volatile(EOI_REGISTER, 0) }
     }
 
+    pub extern "x86-interrupt" fn com1_handler(_frame: *mut InterruptStackFrame) {
+        count_interrupt(InterruptVector::COM1);
+        crate::serial::handle_interrupt();
+        notify_end_of_interrupt();
+    }
+
+    pub extern "x86-interrupt" fn virtio_blk_handler(_frame: *mut InterruptStackFrame) {
+        count_interrupt(InterruptVector::VirtioBlk);
+        crate::virtio::blk::handle_interrupt();
+        notify_end_of_interrupt();
+    }
+
+    pub extern "x86-interrupt" fn local_apic_timer_handler(_frame: *mut InterruptStackFrame) {
+        count_interrupt(InterruptVector::LocalApicTimer);
+        crate::timer::handle_interrupt();
+        notify_end_of_interrupt();
+    }
+
     pub extern "x86-interrupt" fn divide_by_zero_handler(_frame: *mut InterruptStackFrame) {
+        count_interrupt(InterruptVector::DivideByZeroError);
         panic!("divide by zero");
     }
 
     pub extern "x86-interrupt" fn breakpoint_handler(_frame: *mut InterruptStackFrame) {
+        count_interrupt(InterruptVector::Breakpoint);
         crate::kprintln!("breakpoint");
     }
 
     pub extern "x86-interrupt" fn double_fault_handler(_frame: *mut InterruptStackFrame, _error_code: u64) {
+        count_interrupt(InterruptVector::DoubleFault);
         panic!("double fault");
     }
 
     pub extern "x86-interrupt" fn invalid_tss_handler(_frame: *mut InterruptStackFrame, _error_code: u64) {
+        count_interrupt(InterruptVector::InvalidTss);
         panic!("invalid tss");
     }
 
     pub extern "x86-interrupt" fn segment_not_present_handler(_frame: *mut InterruptStackFrame, _error_code: u64) {
+        count_interrupt(InterruptVector::SegmentNotPresent);
         panic!("segment not present");
  
     }
 
     pub extern "x86-interrupt" fn stack_segment_fault_handler(_frame: *mut InterruptStackFrame, _error_code: u64) {
+        count_interrupt(InterruptVector::Stack);
         panic!("stack segment fault");
     }
 
     pub extern "x86-interrupt" fn general_protection_fault_handler(_frame: *mut InterruptStackFrame, _error_code: u64) {
+        count_interrupt(InterruptVector::GeneralProtection);
         panic!("general protection fault");
     }
 
     pub extern "x86-interrupt" fn page_fault_handler(_frame: *mut InterruptStackFrame, _error_code: u64) {
+        count_interrupt(InterruptVector::PageFault);
         panic!("page fault");
     }
 
@@ -246,6 +277,30 @@ pub mod idt {
                 .set_dpl(0) // ring 0
                 .set_present(true),
         );
+        idt.set_handler(
+            InterruptVector::COM1 as u8, 
+            super::interrupt_handler::com1_handler as usize as u64, 
+            InterruptDescriptorAttribute::missing()
+                .set_type(14) // interrupt gate == 14
+                .set_dpl(0) // ring 0
+                .set_present(true),
+        );
+        idt.set_handler(
+            InterruptVector::VirtioBlk as u8, 
+            super::interrupt_handler::virtio_blk_handler as usize as u64, 
+            InterruptDescriptorAttribute::missing()
+                .set_type(14) // interrupt gate == 14
+                .set_dpl(0) // ring 0
+                .set_present(true),
+        );
+        idt.set_handler(
+            InterruptVector::LocalApicTimer as u8, 
+            super::interrupt_handler::local_apic_timer_handler as usize as u64, 
+            InterruptDescriptorAttribute::missing()
+                .set_type(14) // interrupt gate == 14
+                .set_dpl(0) // ring 0
+                .set_present(true),
+        );
         idt.set_handler(
             InterruptVector::DivideByZeroError as u8,
             super::interrupt_handler::divide_by_zero_handler as usize as u64,
@@ -353,6 +408,26 @@ pub mod idt {
         GeneralProtection = 0x0D,
         PageFault = 0x0E,
         XHCI = 0x40,
+        COM1 = 0x41,
+        VirtioBlk = 0x42,
+        LocalApicTimer = 0x43,
+    }
+
+    impl InterruptVector {
+        pub const ALL: [InterruptVector; 12] = [
+            InterruptVector::DivideByZeroError,
+            InterruptVector::Breakpoint,
+            InterruptVector::DoubleFault,
+            InterruptVector::InvalidTss,
+            InterruptVector::SegmentNotPresent,
+            InterruptVector::Stack,
+            InterruptVector::GeneralProtection,
+            InterruptVector::PageFault,
+            InterruptVector::XHCI,
+            InterruptVector::COM1,
+            InterruptVector::VirtioBlk,
+            InterruptVector::LocalApicTimer,
+        ];
     }
 
     #[derive(Debug)]
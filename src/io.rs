@@ -0,0 +1,133 @@
+//! x86 I/O port access
+//! 参考: https://github.com/rust-osdev/x86_64 (instructions::port)
+
+use core::arch::asm;
+use core::marker::PhantomData;
+
+// in/out 命令で扱えるデータ幅 (u8, u16, u32)
+pub trait PortWidth: Copy {
+    unsafe fn read_from_port(port: u16) -> Self;
+    unsafe fn write_to_port(port: u16, val: Self);
+    unsafe fn read_string_from_port(port: u16, buf: &mut [Self]);
+    unsafe fn write_string_to_port(port: u16, buf: &[Self]);
+}
+
+macro_rules! impl_port_width {
+    ($t:ty, $reg:tt, $ins:literal, $outs:literal) => {
+        impl PortWidth for $t {
+            #[inline]
+            unsafe fn read_from_port(port: u16) -> Self {
+                let val: $t;
+                asm!(
+                    concat!("in ", $reg, ", dx"),
+                    out($reg) val,
+                    in("dx") port,
+                    options(nomem, nostack, preserves_flags),
+                );
+                val
+            }
+
+            #[inline]
+            unsafe fn write_to_port(port: u16, val: Self) {
+                asm!(
+                    concat!("out dx, ", $reg),
+                    in("dx") port,
+                    in($reg) val,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+
+            // DF (direction flag) は 0 であることを前提とする
+            #[inline]
+            unsafe fn read_string_from_port(port: u16, buf: &mut [Self]) {
+                asm!(
+                    $ins,
+                    in("dx") port,
+                    inout("rdi") buf.as_mut_ptr() => _,
+                    inout("rcx") buf.len() => _,
+                    options(nostack, preserves_flags),
+                );
+            }
+
+            #[inline]
+            unsafe fn write_string_to_port(port: u16, buf: &[Self]) {
+                asm!(
+                    $outs,
+                    in("dx") port,
+                    inout("rsi") buf.as_ptr() => _,
+                    inout("rcx") buf.len() => _,
+                    options(readonly, nostack, preserves_flags),
+                );
+            }
+        }
+    };
+}
+
+impl_port_width!(u8, "al", "rep insb", "rep outsb");
+impl_port_width!(u16, "ax", "rep insw", "rep outsw");
+impl_port_width!(u32, "eax", "rep insd", "rep outsd");
+
+pub struct Port<T: PortWidth> {
+    port: u16,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: PortWidth> Port<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _phantom: PhantomData }
+    }
+
+    pub fn read(&mut self) -> T {
+        unsafe { T::read_from_port(self.port) }
+    }
+
+    pub fn write(&mut self, val: T) {
+        unsafe { T::write_to_port(self.port, val) }
+    }
+
+    pub fn read_string(&mut self, buf: &mut [T]) {
+        unsafe { T::read_string_from_port(self.port, buf) }
+    }
+
+    pub fn write_string(&mut self, buf: &[T]) {
+        unsafe { T::write_string_to_port(self.port, buf) }
+    }
+}
+
+pub struct PortReadOnly<T: PortWidth> {
+    port: u16,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: PortWidth> PortReadOnly<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _phantom: PhantomData }
+    }
+
+    pub fn read(&mut self) -> T {
+        unsafe { T::read_from_port(self.port) }
+    }
+
+    pub fn read_string(&mut self, buf: &mut [T]) {
+        unsafe { T::read_string_from_port(self.port, buf) }
+    }
+}
+
+pub struct PortWriteOnly<T: PortWidth> {
+    port: u16,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: PortWidth> PortWriteOnly<T> {
+    pub const fn new(port: u16) -> Self {
+        Self { port, _phantom: PhantomData }
+    }
+
+    pub fn write(&mut self, val: T) {
+        unsafe { T::write_to_port(self.port, val) }
+    }
+
+    pub fn write_string(&mut self, buf: &[T]) {
+        unsafe { T::write_string_to_port(self.port, buf) }
+    }
+}
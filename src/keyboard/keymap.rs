@@ -0,0 +1,100 @@
+//! キー配列
+//! 配列によって刻印が違うキー (数字と記号) だけを (キー, Shift なし, Shift あり) の表にする.
+//! 英字, Space, テンキーなどはどの配列でも同じなので mod.rs で変換する.
+
+use super::Key::{self, *};
+
+pub struct Keymap {
+    pub name: &'static str,
+    keys: &'static [(Key, Option<char>, Option<char>)],
+    // 右 Alt を AltGr として使う配列の (キー, AltGr, AltGr+Shift). 空なら右 Alt は Alt
+    alt_gr: &'static [(Key, Option<char>, Option<char>)],
+}
+
+impl Keymap {
+    pub fn has_alt_gr(&self) -> bool {
+        !self.alt_gr.is_empty()
+    }
+
+    // 表にないキーは None
+    pub fn lookup(&self, key: Key, shift: bool, alt_gr: bool) -> Option<Option<char>> {
+        let table = if alt_gr { self.alt_gr } else { self.keys };
+        let &(_, normal, shifted) = table.iter().find(|(k, _, _)| *k == key)?;
+        Some(if shift { shifted } else { normal })
+    }
+}
+
+pub static US: Keymap = Keymap {
+    name: "us",
+    keys: &[
+        (Digit1, Some('1'), Some('!')),
+        (Digit2, Some('2'), Some('@')),
+        (Digit3, Some('3'), Some('#')),
+        (Digit4, Some('4'), Some('$')),
+        (Digit5, Some('5'), Some('%')),
+        (Digit6, Some('6'), Some('^')),
+        (Digit7, Some('7'), Some('&')),
+        (Digit8, Some('8'), Some('*')),
+        (Digit9, Some('9'), Some('(')),
+        (Digit0, Some('0'), Some(')')),
+        (Minus, Some('-'), Some('_')),
+        (Equal, Some('='), Some('+')),
+        (LeftBracket, Some('['), Some('{')),
+        (RightBracket, Some(']'), Some('}')),
+        (Backslash, Some('\\'), Some('|')),
+        (NonUsHash, Some('#'), Some('~')),
+        (Semicolon, Some(';'), Some(':')),
+        (Quote, Some('\''), Some('"')),
+        (Grave, Some('`'), Some('~')),
+        (Comma, Some(','), Some('<')),
+        (Period, Some('.'), Some('>')),
+        (Slash, Some('/'), Some('?')),
+        (NonUsBackslash, Some('\\'), Some('|')),
+    ],
+    alt_gr: &[],
+};
+
+// 日本語 106/109 キーボード. 半角/全角, 変換, 無変換, カタカナ/ひらがなは文字を出さない
+pub static JIS: Keymap = Keymap {
+    name: "jis",
+    keys: &[
+        (Digit1, Some('1'), Some('!')),
+        (Digit2, Some('2'), Some('"')),
+        (Digit3, Some('3'), Some('#')),
+        (Digit4, Some('4'), Some('$')),
+        (Digit5, Some('5'), Some('%')),
+        (Digit6, Some('6'), Some('&')),
+        (Digit7, Some('7'), Some('\'')),
+        (Digit8, Some('8'), Some('(')),
+        (Digit9, Some('9'), Some(')')),
+        (Digit0, Some('0'), None),
+        (Minus, Some('-'), Some('=')),
+        (Equal, Some('^'), Some('~')),
+        // ¥ キー. hankaku font にないので \ にする
+        (International3, Some('\\'), Some('|')),
+        (LeftBracket, Some('@'), Some('`')),
+        (RightBracket, Some('['), Some('{')),
+        // ] キーは機種によって 0x31 か 0x32
+        (Backslash, Some(']'), Some('}')),
+        (NonUsHash, Some(']'), Some('}')),
+        (Semicolon, Some(';'), Some('+')),
+        (Quote, Some(':'), Some('*')),
+        (Grave, None, None),
+        (Comma, Some(','), Some('<')),
+        (Period, Some('.'), Some('>')),
+        (Slash, Some('/'), Some('?')),
+        // ろ キー
+        (International1, Some('\\'), Some('_')),
+    ],
+    alt_gr: &[],
+};
+
+// Cargo の feature jis-keymap で既定を JIS 配列にする
+#[cfg(feature = "jis-keymap")]
+pub static DEFAULT: &Keymap = &JIS;
+#[cfg(not(feature = "jis-keymap"))]
+pub static DEFAULT: &Keymap = &US;
+
+pub fn find(name: &str) -> Option<&'static Keymap> {
+    [&US, &JIS].iter().find(|keymap| keymap.name == name).copied()
+}
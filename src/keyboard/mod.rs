@@ -0,0 +1,444 @@
+//! USB HID keyboard
+//! mikanos_usb の keyboard driver から (修飾キーの状態, usage ID, 押したか離したか) を受け取って溜めておき,
+//! main loop の process_events で keymap に従って KeyEvent にし, subscribe した関数に渡す.
+//! 修飾キーも usage ID 0xe0..0xe7 のキーとして届く.
+//! 参考: HID Usage Tables, 10 Keyboard/Keypad Page (0x07)
+
+pub mod keymap;
+
+use crate::sync::SpinMutex;
+use crate::timer::TIMER_FREQUENCY;
+use crate::utils::fixed_vec::FixedVec;
+use crate::utils::ring_buffer::RingBuffer;
+use keymap::Keymap;
+
+// 下位 8 bit は boot protocol の report の 1 byte 目. 上位は AltGr と lock キーの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers(u16);
+
+impl Modifiers {
+    pub const LEFT_CONTROL: u8 = 1 << 0;
+    pub const LEFT_SHIFT: u8 = 1 << 1;
+    pub const LEFT_ALT: u8 = 1 << 2;
+    pub const LEFT_GUI: u8 = 1 << 3;
+    pub const RIGHT_CONTROL: u8 = 1 << 4;
+    pub const RIGHT_SHIFT: u8 = 1 << 5;
+    pub const RIGHT_ALT: u8 = 1 << 6;
+    pub const RIGHT_GUI: u8 = 1 << 7;
+    // keymap が AltGr を使うときの右 Alt (RIGHT_ALT の代わりに立つ)
+    pub const ALT_GR: u16 = 1 << 8;
+    pub const CAPS_LOCK: u16 = 1 << 9;
+    pub const NUM_LOCK: u16 = 1 << 10;
+    pub const SCROLL_LOCK: u16 = 1 << 11;
+
+    pub const fn new(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    fn contains(&self, bits: u16) -> bool {
+        self.0 & bits != 0
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.contains((Self::LEFT_CONTROL | Self::RIGHT_CONTROL) as u16)
+    }
+
+    pub fn shift(&self) -> bool {
+        self.contains((Self::LEFT_SHIFT | Self::RIGHT_SHIFT) as u16)
+    }
+
+    pub fn alt(&self) -> bool {
+        self.contains((Self::LEFT_ALT | Self::RIGHT_ALT) as u16)
+    }
+
+    pub fn alt_gr(&self) -> bool {
+        self.contains(Self::ALT_GR)
+    }
+
+    pub fn gui(&self) -> bool {
+        self.contains((Self::LEFT_GUI | Self::RIGHT_GUI) as u16)
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.contains(Self::CAPS_LOCK)
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.contains(Self::NUM_LOCK)
+    }
+
+    pub fn scroll_lock(&self) -> bool {
+        self.contains(Self::SCROLL_LOCK)
+    }
+}
+
+// 物理的なキー. 文字は配列によって違うので, 名前は US 配列の刻印にする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Digit0,
+    Enter,
+    Escape,
+    Backspace,
+    Tab,
+    Space,
+    Minus,
+    Equal,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    // US 配列にはない. JIS 配列の ]
+    NonUsHash,
+    Semicolon,
+    Quote,
+    Grave,
+    Comma,
+    Period,
+    Slash,
+    CapsLock,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Insert,
+    Home,
+    PageUp,
+    Delete,
+    End,
+    PageDown,
+    Right,
+    Left,
+    Down,
+    Up,
+    NumLock,
+    KeypadSlash,
+    KeypadAsterisk,
+    KeypadMinus,
+    KeypadPlus,
+    KeypadEnter,
+    Keypad1, Keypad2, Keypad3, Keypad4, Keypad5, Keypad6, Keypad7, Keypad8, Keypad9, Keypad0,
+    KeypadPeriod,
+    // ISO 配列の \ (左 Shift の右)
+    NonUsBackslash,
+    Application,
+    // JIS 配列の \ _ (ろ)
+    International1,
+    // JIS 配列のカタカナ/ひらがな
+    International2,
+    // JIS 配列の ¥ |
+    International3,
+    // JIS 配列の変換
+    International4,
+    // JIS 配列の無変換
+    International5,
+    LeftControl,
+    LeftShift,
+    LeftAlt,
+    LeftGui,
+    RightControl,
+    RightShift,
+    RightAlt,
+    RightGui,
+    // 上にない usage ID
+    Unknown(u8),
+}
+
+impl Key {
+    pub fn from_usage(usage: u8) -> Self {
+        use Key::*;
+        const LETTERS: [Key; 26] = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+        const DIGITS: [Key; 10] = [Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Digit0];
+        const FUNCTIONS: [Key; 12] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+        const KEYPAD: [Key; 10] = [Keypad1, Keypad2, Keypad3, Keypad4, Keypad5, Keypad6, Keypad7, Keypad8, Keypad9, Keypad0];
+        const MODIFIERS: [Key; 8] = [LeftControl, LeftShift, LeftAlt, LeftGui, RightControl, RightShift, RightAlt, RightGui];
+        match usage {
+            0x04..=0x1d => LETTERS[(usage - 0x04) as usize],
+            0x1e..=0x27 => DIGITS[(usage - 0x1e) as usize],
+            0x28 => Enter,
+            0x29 => Escape,
+            0x2a => Backspace,
+            0x2b => Tab,
+            0x2c => Space,
+            0x2d => Minus,
+            0x2e => Equal,
+            0x2f => LeftBracket,
+            0x30 => RightBracket,
+            0x31 => Backslash,
+            0x32 => NonUsHash,
+            0x33 => Semicolon,
+            0x34 => Quote,
+            0x35 => Grave,
+            0x36 => Comma,
+            0x37 => Period,
+            0x38 => Slash,
+            0x39 => CapsLock,
+            0x3a..=0x45 => FUNCTIONS[(usage - 0x3a) as usize],
+            0x46 => PrintScreen,
+            0x47 => ScrollLock,
+            0x48 => Pause,
+            0x49 => Insert,
+            0x4a => Home,
+            0x4b => PageUp,
+            0x4c => Delete,
+            0x4d => End,
+            0x4e => PageDown,
+            0x4f => Right,
+            0x50 => Left,
+            0x51 => Down,
+            0x52 => Up,
+            0x53 => NumLock,
+            0x54 => KeypadSlash,
+            0x55 => KeypadAsterisk,
+            0x56 => KeypadMinus,
+            0x57 => KeypadPlus,
+            0x58 => KeypadEnter,
+            0x59..=0x62 => KEYPAD[(usage - 0x59) as usize],
+            0x63 => KeypadPeriod,
+            0x64 => NonUsBackslash,
+            0x65 => Application,
+            0x87 => International1,
+            0x88 => International2,
+            0x89 => International3,
+            0x8a => International4,
+            0x8b => International5,
+            0xe0..=0xe7 => MODIFIERS[(usage - 0xe0) as usize],
+            _ => Unknown(usage),
+        }
+    }
+
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self,
+            Key::LeftControl | Key::LeftShift | Key::LeftAlt | Key::LeftGui
+                | Key::RightControl | Key::RightShift | Key::RightAlt | Key::RightGui
+        )
+    }
+
+    fn is_lock(&self) -> bool {
+        matches!(self, Key::CapsLock | Key::NumLock | Key::ScrollLock)
+    }
+
+    // NumLock が off のときのテンキー
+    fn keypad_navigation(self) -> Self {
+        match self {
+            Key::Keypad1 => Key::End,
+            Key::Keypad2 => Key::Down,
+            Key::Keypad3 => Key::PageDown,
+            Key::Keypad4 => Key::Left,
+            Key::Keypad6 => Key::Right,
+            Key::Keypad7 => Key::Home,
+            Key::Keypad8 => Key::Up,
+            Key::Keypad9 => Key::PageUp,
+            Key::Keypad0 => Key::Insert,
+            Key::KeypadPeriod => Key::Delete,
+            key => key,
+        }
+    }
+
+    // どの配列でも同じ文字になるキー. 英字は小文字
+    fn common_char(&self) -> Option<char> {
+        use Key::*;
+        const LETTERS: [Key; 26] = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+        const KEYPAD: [Key; 10] = [Keypad0, Keypad1, Keypad2, Keypad3, Keypad4, Keypad5, Keypad6, Keypad7, Keypad8, Keypad9];
+        if let Some(i) = LETTERS.iter().position(|key| key == self) {
+            return Some((b'a' + i as u8) as char);
+        }
+        if let Some(i) = KEYPAD.iter().position(|key| key == self) {
+            return Some((b'0' + i as u8) as char);
+        }
+        match self {
+            Enter | KeypadEnter => Some('\n'),
+            Escape => Some('\x1b'),
+            Backspace => Some('\x08'),
+            Tab => Some('\t'),
+            Space => Some(' '),
+            Delete => Some('\x7f'),
+            KeypadSlash => Some('/'),
+            KeypadAsterisk => Some('*'),
+            KeypadMinus => Some('-'),
+            KeypadPlus => Some('+'),
+            KeypadPeriod => Some('.'),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    // NumLock が off ならテンキーは矢印などのキーになる
+    pub key: Key,
+    // この event の後の修飾キーと lock キーの状態
+    pub modifiers: Modifiers,
+    // auto repeat の event も true
+    pub pressed: bool,
+    // 入力される文字. Ctrl との組み合わせは制御文字になる
+    pub char: Option<char>,
+}
+
+// driver から届いたままの event
+#[derive(Clone, Copy)]
+struct RawKeyEvent {
+    modifier: u8,
+    usage: u8,
+    pressed: bool,
+}
+
+// 押し続けてから repeat が始まるまでと, その後の間隔 (timer の tick 数)
+const REPEAT_DELAY: u64 = TIMER_FREQUENCY / 2;
+const REPEAT_INTERVAL: u64 = TIMER_FREQUENCY / 30 + 1;
+
+pub struct Keyboard {
+    keymap: &'static Keymap,
+    // report の修飾キーの byte
+    modifier: u8,
+    locks: u16,
+    // 押し続けているキーの usage ID と, 次に repeat する tick
+    repeat: Option<(u8, u64)>,
+}
+
+impl Keyboard {
+    pub const fn new(keymap: &'static Keymap) -> Self {
+        // PC の起動時と同じく NumLock は on にしておく
+        Self { keymap, modifier: 0, locks: Modifiers::NUM_LOCK, repeat: None }
+    }
+
+    pub fn keymap(&self) -> &'static Keymap {
+        self.keymap
+    }
+
+    pub fn set_keymap(&mut self, keymap: &'static Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        let mut bits = self.modifier as u16 | self.locks;
+        if self.keymap.has_alt_gr() && bits & Modifiers::RIGHT_ALT as u16 != 0 {
+            bits = bits & !(Modifiers::RIGHT_ALT as u16) | Modifiers::ALT_GR;
+        }
+        Modifiers::new(bits)
+    }
+
+    // lock キーと repeat の状態を更新して KeyEvent にする
+    fn handle(&mut self, raw: RawKeyEvent, now: u64) -> KeyEvent {
+        self.modifier = raw.modifier;
+        let key = Key::from_usage(raw.usage);
+        if raw.pressed {
+            match key {
+                // LED は driver が SetReport に対応していないので点けない
+                Key::CapsLock => self.locks ^= Modifiers::CAPS_LOCK,
+                Key::NumLock => self.locks ^= Modifiers::NUM_LOCK,
+                Key::ScrollLock => self.locks ^= Modifiers::SCROLL_LOCK,
+                _ => {},
+            }
+            if !key.is_modifier() && !key.is_lock() {
+                self.repeat = Some((raw.usage, now + REPEAT_DELAY));
+            }
+        } else if self.repeat.map(|(usage, _)| usage) == Some(raw.usage) {
+            self.repeat = None;
+        }
+        self.translate(raw.usage, raw.pressed)
+    }
+
+    // 押し続けているキーの repeat の時刻になっていれば, その event
+    fn next_repeat(&mut self, now: u64) -> Option<KeyEvent> {
+        let (usage, next) = self.repeat?;
+        if now < next {
+            return None;
+        }
+        self.repeat = Some((usage, now + REPEAT_INTERVAL));
+        Some(self.translate(usage, true))
+    }
+
+    fn translate(&self, usage: u8, pressed: bool) -> KeyEvent {
+        let modifiers = self.modifiers();
+        let mut key = Key::from_usage(usage);
+        if !modifiers.num_lock() {
+            // 矢印などにならない Keypad5 は文字を出さない
+            if key == Key::Keypad5 {
+                return KeyEvent { key, modifiers, pressed, char: None };
+            }
+            key = key.keypad_navigation();
+        }
+        KeyEvent { key, modifiers, pressed, char: self.char_of(key, modifiers) }
+    }
+
+    fn char_of(&self, key: Key, modifiers: Modifiers) -> Option<char> {
+        let c = match self.keymap.lookup(key, modifiers.shift(), modifiers.alt_gr()) {
+            Some(c) => c?,
+            None if modifiers.alt_gr() => return None,
+            None => {
+                let c = key.common_char()?;
+                // CapsLock は英字だけ Shift を反転する
+                if c.is_ascii_lowercase() && modifiers.shift() != modifiers.caps_lock() {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            },
+        };
+        if !modifiers.ctrl() {
+            return Some(c);
+        }
+        // Ctrl+A .. Ctrl+Z, Ctrl+@ [ \ ] ^ _ は制御文字, Ctrl+? は DEL
+        match c {
+            'a'..='z' | 'A'..='Z' => Some((c.to_ascii_uppercase() as u8 - b'@') as char),
+            '@' | ' ' => Some('\0'),
+            '[' | '\\' | ']' | '^' | '_' => Some((c as u8 - b'@') as char),
+            '?' => Some('\x7f'),
+            _ => Some(c),
+        }
+    }
+}
+
+pub type KeyHandler = fn(&KeyEvent);
+
+const MAX_SUBSCRIBERS: usize = 8;
+
+static RAW_EVENTS: SpinMutex<RingBuffer<RawKeyEvent, 64>> = SpinMutex::new(RingBuffer::new());
+pub static KEYBOARD: SpinMutex<Keyboard> = SpinMutex::new(Keyboard::new(keymap::DEFAULT));
+static SUBSCRIBERS: SpinMutex<FixedVec<'static, KeyHandler, MAX_SUBSCRIBERS>> = SpinMutex::new(FixedVec::new());
+
+// mikanos_usb の keyboard driver から呼ばれる
+pub extern "C" fn keyboard_observer(modifier: u8, keycode: u8, press: bool) {
+    // 溢れたら古いものから捨てる
+    RAW_EVENTS.lock().push_overwrite(RawKeyEvent { modifier, usage: keycode, pressed: press });
+}
+
+// KeyEvent を受け取る関数を登録する. 登録しきれなければ false
+pub fn subscribe(handler: KeyHandler) -> bool {
+    unsafe { SUBSCRIBERS.lock().try_push(handler) }.is_ok()
+}
+
+pub fn set_keymap(keymap: &'static Keymap) {
+    KEYBOARD.lock().set_keymap(keymap);
+}
+
+fn pop_raw_event() -> Option<RawKeyEvent> {
+    RAW_EVENTS.lock().pop()
+}
+
+// handler の中から subscribe できるように lock を外してから呼ぶ
+fn notify(event: &KeyEvent) {
+    let mut handlers: [Option<KeyHandler>; MAX_SUBSCRIBERS] = [None; MAX_SUBSCRIBERS];
+    for (slot, &handler) in handlers.iter_mut().zip(SUBSCRIBERS.lock().as_slice()) {
+        *slot = Some(handler);
+    }
+    handlers.iter().flatten().for_each(|handler| handler(event));
+}
+
+// main loop から呼ぶ. 届いた event と auto repeat を subscriber に渡す. now は timer の tick
+pub fn process_events(now: u64) {
+    while let Some(raw) = pop_raw_event() {
+        let event = KEYBOARD.lock().handle(raw, now);
+        notify(&event);
+    }
+    let repeat = KEYBOARD.lock().next_repeat(now);
+    if let Some(event) = repeat {
+        notify(&event);
+    }
+}
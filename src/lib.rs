@@ -5,6 +5,8 @@
 pub mod graphics;
 pub mod console;
 pub mod mouse;
+pub mod keyboard;
+pub mod hid;
 pub mod sync;
 pub mod pci;
 pub mod interrupts;
@@ -12,10 +14,27 @@ pub mod logger;
 pub mod xhc;
 pub mod utils;
 pub mod asm;
+pub mod apic;
+pub mod io;
+pub mod serial;
+pub mod block;
+pub mod virtio;
+pub mod ahci;
+pub mod acpi;
+pub mod power;
+pub mod memory;
+pub mod timer;
+pub mod shell;
 
 use core::panic::PanicInfo;
 // TODO: write another panic function for release build
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
+fn panic(info: &PanicInfo) -> ! {
+    // SERIAL などを lock したまま panic することがある (IrqSpinMutex の二重 lock など) ので, lock せずに COM1 に直接書く
+    use core::fmt::Write;
+    let _ = writeln!(serial::SerialPort::new(serial::COM1), "{}", info);
+    #[cfg(feature = "qemu-exit")]
+    power::exit_qemu(power::QemuExitCode::Failed);
+    #[cfg(not(feature = "qemu-exit"))]
     loop {}
 }
\ No newline at end of file
@@ -0,0 +1,309 @@
+//! kernel logger
+//! log は record (level, 時刻, CPU, file と行, 本文) にして ring buffer に残し, 登録された sink に渡す.
+//! 残っている record は for_each_record で dmesg のように読み返せる.
+//! level は全体の設定のほかに module ごとに変えられる.
+
+pub mod sink;
+mod usb;
+
+use crate::sync::IrqSpinMutex;
+use crate::utils::ring_buffer::RingBuffer;
+use crate::{apic, timer};
+use core::fmt::{self, Arguments, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
+use sink::{LogSink, CONSOLE_SINK, SERIAL_SINK};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 0,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    const ALL: [LogLevel; 5] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+
+    fn from_u8(value: u8) -> Self {
+        Self::ALL[(value as usize).min(Self::ALL.len() - 1)]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|level| level.name() == name).copied()
+    }
+
+    // 色は SGR (console と serial の端末で表示される)
+    fn label(self) -> (&'static str, &'static str) {
+        match self {
+            LogLevel::Error => ("ERROR", "\x1b[1;31m"),
+            LogLevel::Warn => ("WARN", "\x1b[33m"),
+            LogLevel::Info => ("INFO", "\x1b[32m"),
+            LogLevel::Debug => ("DEBUG", "\x1b[36m"),
+            LogLevel::Trace => ("TRACE", "\x1b[90m"),
+        }
+    }
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Error as u8);
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+    usb::sync_log_level();
+}
+
+pub fn log_level() -> LogLevel {
+    LogLevel::from_u8(LOG_LEVEL.load(Ordering::Relaxed))
+}
+
+// ------------------------------------------------------
+// module ごとの level
+// ------------------------------------------------------
+const MAX_FILTERS: usize = 8;
+const MAX_MODULE_NAME: usize = 32;
+
+#[derive(Clone, Copy)]
+struct ModuleFilter {
+    name: [u8; MAX_MODULE_NAME],
+    len: usize,
+    level: LogLevel,
+}
+
+impl ModuleFilter {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.len]).unwrap_or("")
+    }
+
+    // "xhc" は xhc と xhc::... に当てはまる
+    fn matches(&self, module: &str) -> bool {
+        let name = self.name();
+        module.starts_with(name) && matches!(module.as_bytes().get(name.len()), None | Some(b':'))
+    }
+}
+
+static FILTERS: IrqSpinMutex<[Option<ModuleFilter>; MAX_FILTERS]> = IrqSpinMutex::new([None; MAX_FILTERS]);
+
+// module (crate 名を除いた module path, "xhc" や "console::ansi") の level を変える.
+// None なら全体の設定に戻す. 登録しきれなかったら false
+pub fn set_module_log_level(module: &str, level: Option<LogLevel>) -> bool {
+    if module.len() > MAX_MODULE_NAME {
+        return false;
+    }
+    let mut filters = FILTERS.lock();
+    let index = filters.iter().position(|filter| matches!(filter, Some(filter) if filter.name() == module));
+    match (index, level) {
+        (Some(i), Some(level)) => {
+            if let Some(filter) = filters[i].as_mut() {
+                filter.level = level;
+            }
+        },
+        (Some(i), None) => filters[i] = None,
+        (None, Some(level)) => {
+            let slot = match filters.iter_mut().find(|filter| filter.is_none()) {
+                Some(slot) => slot,
+                None => return false,
+            };
+            let mut name = [0; MAX_MODULE_NAME];
+            name[..module.len()].copy_from_slice(module.as_bytes());
+            *slot = Some(ModuleFilter { name, len: module.len(), level });
+        },
+        (None, None) => {},
+    }
+    drop(filters);
+    usb::sync_log_level();
+    true
+}
+
+// module ごとに設定した level を f に渡す
+pub fn for_each_module_log_level<F: FnMut(&str, LogLevel)>(mut f: F) {
+    let filters = *FILTERS.lock();
+    filters.iter().flatten().for_each(|filter| f(filter.name(), filter.level));
+}
+
+// 一番長い名前で当てはまる設定を使う
+fn module_log_level(module: &str) -> LogLevel {
+    FILTERS
+        .lock()
+        .iter()
+        .flatten()
+        .filter(|filter| filter.matches(module))
+        .max_by_key(|filter| filter.len)
+        .map_or_else(log_level, |filter| filter.level)
+}
+
+pub fn enabled(level: LogLevel, module: &str) -> bool {
+    level <= module_log_level(module)
+}
+
+// ------------------------------------------------------
+// record
+// ------------------------------------------------------
+const MAX_MESSAGE: usize = 160;
+const MAX_RECORDS: usize = 256;
+
+#[derive(Clone, Copy)]
+pub struct Record {
+    pub level: LogLevel,
+    // timer の tick 数
+    pub ticks: u64,
+    // local APIC ID
+    pub cpu: u8,
+    pub module: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+    // 長すぎる本文は切る
+    message: [u8; MAX_MESSAGE],
+    message_len: usize,
+}
+
+impl Record {
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or("")
+    }
+
+    // color が true なら level に色を付ける
+    pub fn display(&self, color: bool) -> RecordDisplay<'_> {
+        RecordDisplay { record: self, color }
+    }
+}
+
+// 本文を Record の buffer に書く. 入りきらない分は捨てる
+struct MessageWriter<'a> {
+    record: &'a mut Record,
+}
+
+impl fmt::Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let record = &mut *self.record;
+        let mut len = s.len().min(MAX_MESSAGE - record.message_len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        record.message[record.message_len..record.message_len + len].copy_from_slice(&s.as_bytes()[..len]);
+        record.message_len += len;
+        Ok(())
+    }
+}
+
+pub struct RecordDisplay<'a> {
+    record: &'a Record,
+    color: bool,
+}
+
+impl fmt::Display for RecordDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = self.record;
+        let seconds = record.ticks / timer::TIMER_FREQUENCY;
+        let hundredths = record.ticks % timer::TIMER_FREQUENCY * 100 / timer::TIMER_FREQUENCY;
+        let (label, color) = record.level.label();
+        let (color, reset) = if self.color { (color, "\x1b[0m") } else { ("", "") };
+        write!(f, "[{:>5}.{:02}] [{}{}{} cpu{} ({}", seconds, hundredths, color, label, reset, record.cpu, record.file)?;
+        // 行が 0 なら file (mikanos_usb など) しかわからない
+        if record.line != 0 {
+            write!(f, ":{}", record.line)?;
+        }
+        write!(f, ")] {}", record.message())
+    }
+}
+
+static RECORDS: IrqSpinMutex<RingBuffer<Record, MAX_RECORDS>> = IrqSpinMutex::new(RingBuffer::new());
+
+// 残っている record を古い順に f に渡す. f の中で log を出すと止まる
+pub fn for_each_record<F: FnMut(&Record)>(f: F) {
+    RECORDS.lock().iter().for_each(f);
+}
+
+pub fn clear_records() {
+    RECORDS.lock().clear();
+}
+
+// ------------------------------------------------------
+// sink
+// ------------------------------------------------------
+const MAX_SINKS: usize = 4;
+
+static SINKS: IrqSpinMutex<[Option<&'static dyn LogSink>; MAX_SINKS]> =
+    IrqSpinMutex::new([Some(&CONSOLE_SINK), Some(&SERIAL_SINK), None, None]);
+
+// 登録しきれなかったら false
+pub fn add_sink(sink: &'static dyn LogSink) -> bool {
+    match SINKS.lock().iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(sink);
+            true
+        },
+        None => false,
+    }
+}
+
+// module は crate 名を除いた module path
+pub fn log(level: LogLevel, module: &'static str, file: &'static str, line: u32, args: Arguments) {
+    if !enabled(level, module) {
+        return;
+    }
+
+    let mut record = Record {
+        level,
+        ticks: timer::ticks(),
+        cpu: apic::local_apic_id(),
+        module,
+        file,
+        line,
+        message: [0; MAX_MESSAGE],
+        message_len: 0,
+    };
+    let _ = MessageWriter { record: &mut record }.write_fmt(args);
+    RECORDS.lock().push_overwrite(record);
+
+    // sink が log を出しても止まらないように, lock を外してから呼ぶ
+    let sinks = *SINKS.lock();
+    sinks.iter().flatten().for_each(|sink| sink.write(&record));
+}
+
+// macro から呼ばれる. module_path! の先頭の crate 名を除く
+pub fn _log(level: LogLevel, module_path: &'static str, file: &'static str, line: u32, args: Arguments) {
+    let module = module_path.split_once("::").map_or(module_path, |(_, module)| module);
+    log(level, module, file, line, args);
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($args:tt)*) => (
+        $crate::logger::_log($level, module_path!(), file!(), line!(), format_args!($($args)*))
+    );
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($args:tt)*) => ($crate::log!($crate::logger::LogLevel::Error, $($args)*));
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($args:tt)*) => ($crate::log!($crate::logger::LogLevel::Warn, $($args)*));
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($args:tt)*) => ($crate::log!($crate::logger::LogLevel::Info, $($args)*));
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($args:tt)*) => ($crate::log!($crate::logger::LogLevel::Debug, $($args)*));
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($args:tt)*) => ($crate::log!($crate::logger::LogLevel::Trace, $($args)*));
+}
@@ -0,0 +1,118 @@
+//! log の出力先
+//! logger::add_sink で登録すると, level で絞った後の record を受け取る.
+
+use super::Record;
+use crate::block::{self, SECTOR_SIZE};
+use crate::sync::SpinMutex;
+use core::fmt::{self, Write};
+
+pub trait LogSink: Sync {
+    fn write(&self, record: &Record);
+}
+
+pub struct ConsoleSink;
+
+impl LogSink for ConsoleSink {
+    fn write(&self, record: &Record) {
+        crate::console::write_console(format_args!("{}\n", record.display(true)));
+    }
+}
+
+pub struct SerialSink;
+
+impl LogSink for SerialSink {
+    fn write(&self, record: &Record) {
+        crate::serial::_print(format_args!("{}\n", record.display(true)));
+    }
+}
+
+// 最初から登録されている
+pub static CONSOLE_SINK: ConsoleSink = ConsoleSink;
+pub static SERIAL_SINK: SerialSink = SerialSink;
+
+// (lba, buf) を書く. device が使用中なら None
+pub type WriteSectors = fn(u64, &[u8]) -> Option<block::Result<()>>;
+
+// file system がないので, disk の start_lba から sector_count sector を log の file として text をそのまま書く.
+// 最後まで書いたら先頭に戻る. start を呼ぶまでは何もしない
+pub struct DiskSink {
+    write_sectors: WriteSectors,
+    state: SpinMutex<DiskSinkState>,
+}
+
+struct DiskSinkState {
+    start_lba: u64,
+    sector_count: u64,
+    // 書いている sector (start_lba からの位置) と, その中身
+    sector: u64,
+    buf: [u8; SECTOR_SIZE],
+    len: usize,
+}
+
+impl DiskSink {
+    pub const fn new(write_sectors: WriteSectors) -> Self {
+        Self {
+            write_sectors,
+            state: SpinMutex::new(DiskSinkState {
+                start_lba: 0,
+                sector_count: 0,
+                sector: 0,
+                buf: [0; SECTOR_SIZE],
+                len: 0,
+            }),
+        }
+    }
+
+    pub fn start(&self, start_lba: u64, sector_count: u64) {
+        let mut state = self.state.lock();
+        state.start_lba = start_lba;
+        state.sector_count = sector_count;
+        state.sector = 0;
+        state.buf = [0; SECTOR_SIZE];
+        state.len = 0;
+    }
+}
+
+impl LogSink for DiskSink {
+    fn write(&self, record: &Record) {
+        let mut state = self.state.lock();
+        if state.sector_count == 0 {
+            return;
+        }
+        let mut writer = SectorWriter { state: &mut state, write_sectors: self.write_sectors };
+        let _ = writeln!(writer, "{}", record.display(false));
+        // 途中の sector も書いておく. 使用中で書けなかった分は次の record と一緒に書く
+        writer.flush();
+    }
+}
+
+struct SectorWriter<'a> {
+    state: &'a mut DiskSinkState,
+    write_sectors: WriteSectors,
+}
+
+impl SectorWriter<'_> {
+    fn flush(&mut self) {
+        let state = &mut *self.state;
+        let _ = (self.write_sectors)(state.start_lba + state.sector, &state.buf);
+    }
+}
+
+impl fmt::Write for SectorWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            let state = &mut *self.state;
+            state.buf[state.len] = byte;
+            state.len += 1;
+            if state.len == SECTOR_SIZE {
+                // 書けなくても先に進む
+                self.flush();
+                let state = &mut *self.state;
+                state.sector = (state.sector + 1) % state.sector_count;
+                state.buf = [0; SECTOR_SIZE];
+                state.len = 0;
+            }
+        }
+        Ok(())
+    }
+}
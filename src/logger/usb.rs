@@ -0,0 +1,41 @@
+//! mikanos_usb (C++) の log
+//! C++ の Log() は usb_log を呼ぶので, module "usb" の record にする.
+//! C++ 側のしきい値は module "usb" の level に合わせる.
+
+use super::{log, module_log_level, LogLevel};
+use mikanos_usb as usb;
+
+const MODULE: &str = "usb";
+
+fn from_usb_level(level: i32) -> LogLevel {
+    match level {
+        i32::MIN..=3 => LogLevel::Error,
+        4 => LogLevel::Warn,
+        5 | 6 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+// level の設定が変わったら呼ぶ
+pub(super) fn sync_log_level() {
+    let level = match module_log_level(MODULE) {
+        LogLevel::Error => usb::LogLevel::Error,
+        LogLevel::Warn => usb::LogLevel::Warn,
+        LogLevel::Info => usb::LogLevel::Info,
+        LogLevel::Debug | LogLevel::Trace => usb::LogLevel::Debug,
+    };
+    usb::set_log_level(level);
+}
+
+#[no_mangle]
+pub extern "C" fn usb_log(level: i32, msg: *const u8, msg_len: usize) {
+    if msg.is_null() {
+        return;
+    }
+    let s = unsafe { core::slice::from_raw_parts(msg, msg_len) };
+    let s = core::str::from_utf8(s).unwrap_or("(invalid UTF-8)");
+    // 改行は record ごとに sink が付ける
+    let s = s.strip_suffix('\n').unwrap_or(s);
+    // C++ からは file と行がわからない
+    log(from_usb_level(level), MODULE, "mikanos_usb", 0, format_args!("{}", s));
+}
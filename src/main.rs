@@ -6,8 +6,12 @@ use potatOS::graphics::{
     FrameBuffer, 
     PixelColor, 
     PixelWriter,
+    Vector2D,
     init_global_writer,
 };
+use potatOS::graphics::layer::init_layers;
+use potatOS::graphics::screenshot;
+use potatOS::console::{init_console, blink_cursor, scroll_console_page, DEFAULT_SCROLLBACK_LINES};
 use potatOS::{kprintln, debug, trace};
 use potatOS::mouse::{mouse_observer, init_mouse};
 use potatOS::pci::{
@@ -16,47 +20,116 @@ use potatOS::pci::{
     Device,
 };
 use potatOS::interrupts::idt::init_idt;
-use potatOS::xhc::{XHC_CONTROLLER, init_xhc};
-use potatOS::logger::set_log_level;
+use potatOS::xhc::{self, XHC_CONTROLLER, init_xhc};
+use potatOS::keyboard::{self, Key, KeyEvent};
+use potatOS::logger::{set_log_level, LogLevel};
+use potatOS::virtio::blk::init_virtio_blk;
+use potatOS::ahci::init_ahci;
+use potatOS::acpi::init_acpi;
+use potatOS::serial::{init_serial, enable_serial_interrupt, read_byte};
+use potatOS::memory::{MemoryMap, init_memory};
+use potatOS::timer::{init_timer, ticks};
+use potatOS::shell::{self, init_shell};
 use mikanos_usb as usb;
 use core::arch::asm;
 
 
-fn init(fb: FrameBuffer) {
+fn init(fb: FrameBuffer, acpi_rsdp: u64, memory_map: MemoryMap) {
+    // graphics の shadow buffer などが frame を使うので最初に初期化する
+    init_memory(memory_map);
     set_log_level(LogLevel::Error);
+    init_serial();
     init_global_writer(fb);
+    init_layers(&PixelColor::WHITE);
+    init_console(DEFAULT_SCROLLBACK_LINES);
+    #[cfg(feature = "cjk-font")]
+    init_cjk_font();
     init_mouse();
     init_idt();
+    enable_serial_interrupt();
+    init_acpi(acpi_rsdp);
+    init_timer();
     scan_all_bus().unwrap();
     init_xhc();
+    init_virtio_blk();
+    init_ahci();
+    keyboard::subscribe(handle_key_event);
     kprintln!("Welcome to potatOS!");
     trace!("finished initialization");
+    init_shell();
 }
 
-#[no_mangle]
-pub extern "C" fn kernel_main(frame_buffer: FrameBuffer) -> ! { // TODO: 引数を参照にする. 8 byte を超える値は参照渡しにすべき.
-    for x in 0..frame_buffer.h() {
-        for y in 0..frame_buffer.v() {
-            frame_buffer.draw_pixel(x, y, &PixelColor::new(255, 255, 255))
-        }
+// hankaku.bin にない日本語などを埋め込んだ Unifont で表示する
+#[cfg(feature = "cjk-font")]
+fn init_cjk_font() {
+    use potatOS::console::add_console_font;
+    use potatOS::graphics::font::bdf::BdfFont;
+    use potatOS::warn;
+
+    static mut CJK_FONT: Option<BdfFont> = None;
+    let font = match BdfFont::parse(include_bytes!("../assets/unifont.bdf")) {
+        Ok(font) => font,
+        Err(e) => {
+            warn!("cjk font: {:?}", e);
+            return;
+        },
+    };
+    let font = unsafe {
+        CJK_FONT = Some(font);
+        CJK_FONT.as_ref().unwrap()
+    };
+    if !add_console_font(font) {
+        warn!("cjk font: too many fallback fonts");
     }
+}
+
+#[no_mangle]
+pub extern "C" fn kernel_main(frame_buffer: FrameBuffer, acpi_rsdp: u64, memory_map: MemoryMap) -> ! { // TODO: 引数を参照にする. 8 byte を超える値は参照渡しにすべき.
+    frame_buffer.fill_rect(
+        Vector2D::new(0, 0),
+        Vector2D::new(frame_buffer.h(), frame_buffer.v()),
+        &PixelColor::WHITE,
+    );
 
     // init 
-    init(frame_buffer);
+    init(frame_buffer, acpi_rsdp, memory_map);
     // end init
 
+    // 起動できたことを QEMU の終了コードで知らせる
+    #[cfg(feature = "qemu-exit")]
+    potatOS::power::exit_qemu(potatOS::power::QemuExitCode::Success);
 
-    // loop { XHC_CONTROLLER.lock().as_mut().unwrap().process_event().unwrap(); }
 
     // breakpoint test
     // x86_64::instructions::interrupts::int3();
 
     loop {
+        xhc::process_events();
+        keyboard::process_events(ticks());
+        // host から要求があれば screenshot を serial に送り, それ以外は shell の入力にする
+        while let Some(byte) = read_byte() {
+            if !screenshot::handle_request(byte) {
+                shell::handle_serial_byte(byte);
+            }
+        }
+        blink_cursor(ticks());
         x86_64::instructions::hlt();
     }
 
 }
 
+fn handle_key_event(event: &KeyEvent) {
+    if !event.pressed {
+        return;
+    }
+    match event.key {
+        // Shift+PageUp / PageDown で console の履歴を見る
+        Key::PageUp if event.modifiers.shift() => scroll_console_page(true),
+        Key::PageDown if event.modifiers.shift() => scroll_console_page(false),
+        _ => {},
+    }
+}
+
 #[allow(unused)]
 unsafe fn divide_by_zero() {
     // asm 
@@ -0,0 +1,218 @@
+//! physical memory (frame) management
+//! loader から受け取った UEFI のメモリマップをもとに, 4KiB の frame 単位で空き領域を管理する
+
+use crate::sync::SpinMutex;
+use crate::utils::fixed_vec::FixedVec;
+
+pub const FRAME_SIZE: usize = 4096;
+// 管理できる物理メモリの上限 (bitmap の大きさは 512KiB)
+const MAX_PHYSICAL_MEMORY: usize = 16 * 1024 * 1024 * 1024;
+const FRAME_COUNT: usize = MAX_PHYSICAL_MEMORY / FRAME_SIZE;
+const BITS_PER_WORD: usize = 64;
+// 0 番地付近 (BIOS 領域など) は使わない
+const RESERVED_LOW_MEMORY: usize = 0x100000;
+const MAX_MEMORY_REGIONS: usize = 256;
+
+// UEFI の EFI_MEMORY_TYPE
+pub mod memory_type {
+    pub const LOADER_CODE: u32 = 1;
+    pub const LOADER_DATA: u32 = 2;
+    pub const BOOT_SERVICES_CODE: u32 = 3;
+    pub const BOOT_SERVICES_DATA: u32 = 4;
+    pub const CONVENTIONAL: u32 = 7;
+}
+
+// potato_loader の MemoryRegion, MemoryMap と同じレイアウト
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct MemoryRegion {
+    pub ty: u32,
+    pub phys_start: u64,
+    pub page_count: u64,
+    pub attribute: u64,
+}
+
+impl MemoryRegion {
+    // カーネルが自由に使ってよい領域か.
+    // LOADER_DATA にはカーネル自身とメモリマップが, BOOT_SERVICES_DATA には今使っているスタックがあるので使わない
+    pub fn is_available(&self) -> bool {
+        matches!(
+            self.ty,
+            memory_type::LOADER_CODE | memory_type::BOOT_SERVICES_CODE | memory_type::CONVENTIONAL
+        )
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.ty {
+            0 => "Reserved",
+            memory_type::LOADER_CODE => "LoaderCode",
+            memory_type::LOADER_DATA => "LoaderData",
+            memory_type::BOOT_SERVICES_CODE => "BootServicesCode",
+            memory_type::BOOT_SERVICES_DATA => "BootServicesData",
+            5 => "RuntimeServicesCode",
+            6 => "RuntimeServicesData",
+            memory_type::CONVENTIONAL => "Conventional",
+            8 => "Unusable",
+            9 => "ACPIReclaim",
+            10 => "ACPINVS",
+            11 => "MMIO",
+            12 => "MMIOPortSpace",
+            13 => "PalCode",
+            14 => "Persistent",
+            _ => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct MemoryMap {
+    regions: *const MemoryRegion,
+    len: usize,
+}
+
+impl MemoryMap {
+    pub fn regions(&self) -> &[MemoryRegion] {
+        unsafe { core::slice::from_raw_parts(self.regions, self.len) }
+    }
+}
+
+pub struct BitmapFrameManager {
+    // bit が 1 なら空き (0 で初期化して .bss に置くため)
+    bitmap: [u64; FRAME_COUNT / BITS_PER_WORD],
+    range_end: usize,
+    free_frames: usize,
+}
+
+impl BitmapFrameManager {
+    pub const fn new() -> Self {
+        Self {
+            bitmap: [0; FRAME_COUNT / BITS_PER_WORD],
+            range_end: 0,
+            free_frames: 0,
+        }
+    }
+
+    fn is_allocated(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) == 0
+    }
+
+    fn set_bit(&mut self, frame: usize, allocated: bool) {
+        let (word, bit) = (frame / BITS_PER_WORD, frame % BITS_PER_WORD);
+        if allocated {
+            self.bitmap[word] &= !(1 << bit);
+        } else {
+            self.bitmap[word] |= 1 << bit;
+        }
+    }
+
+    fn mark(&mut self, start: usize, count: usize, allocated: bool) {
+        for frame in start..start + count {
+            if self.is_allocated(frame) != allocated {
+                self.set_bit(frame, allocated);
+                if allocated {
+                    self.free_frames -= 1;
+                } else {
+                    self.free_frames += 1;
+                }
+            }
+        }
+    }
+
+    fn add_free_region(&mut self, start: usize, count: usize) {
+        let end = (start + count).min(FRAME_COUNT);
+        let start = start.max(RESERVED_LOW_MEMORY / FRAME_SIZE);
+        if start >= end {
+            return;
+        }
+        self.mark(start, end - start, false);
+        self.range_end = self.range_end.max(end);
+    }
+
+    // 連続した count 個の frame を first fit で確保し, 先頭の frame 番号を返す
+    pub fn allocate(&mut self, count: usize) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+        let mut start = 0;
+        while start + count <= self.range_end {
+            // 64 frame まとめて使用中ならまとめて飛ばす
+            if start % BITS_PER_WORD == 0 && self.bitmap[start / BITS_PER_WORD] == 0 {
+                start += BITS_PER_WORD;
+                continue;
+            }
+            match (start..start + count).find(|&frame| self.is_allocated(frame)) {
+                Some(used) => start = used + 1,
+                None => {
+                    self.mark(start, count, true);
+                    return Some(start);
+                },
+            }
+        }
+        None
+    }
+
+    pub fn free(&mut self, start: usize, count: usize) {
+        self.mark(start, count, false);
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    pub fn total_frames(&self) -> usize {
+        self.range_end
+    }
+}
+
+pub static FRAME_MANAGER: SpinMutex<BitmapFrameManager> = SpinMutex::new(BitmapFrameManager::new());
+static MEMORY_REGIONS: SpinMutex<FixedVec<MemoryRegion, MAX_MEMORY_REGIONS>> = SpinMutex::new(FixedVec::new());
+
+// メモリマップは LOADER_DATA にあるので, 最初にカーネル側へコピーしておく
+pub fn init_memory(memory_map: MemoryMap) {
+    let mut regions = MEMORY_REGIONS.lock();
+    let mut manager = FRAME_MANAGER.lock();
+    for region in memory_map.regions().iter().take(MAX_MEMORY_REGIONS) {
+        regions.push(*region);
+        if region.is_available() {
+            manager.add_free_region(region.phys_start as usize / FRAME_SIZE, region.page_count as usize);
+        }
+    }
+}
+
+// init_memory でコピーしたメモリマップを順に f に渡す
+pub fn for_each_memory_region<F: FnMut(&MemoryRegion)>(mut f: F) {
+    MEMORY_REGIONS.lock().as_slice().iter().for_each(|region| f(region));
+}
+
+// 連続した物理 frame. drop すると解放される
+pub struct Frames {
+    start: usize,
+    count: usize,
+}
+
+impl Frames {
+    pub fn allocate(count: usize) -> Option<Self> {
+        let start = FRAME_MANAGER.lock().allocate(count)?;
+        Some(Self { start, count })
+    }
+
+    pub fn allocate_bytes(bytes: usize) -> Option<Self> {
+        Self::allocate((bytes + FRAME_SIZE - 1) / FRAME_SIZE)
+    }
+
+    // identity mapping なので物理アドレスをそのままポインタとして使える
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        (self.start * FRAME_SIZE) as *mut u8
+    }
+
+    pub fn len(&self) -> usize {
+        self.count * FRAME_SIZE
+    }
+}
+
+impl Drop for Frames {
+    fn drop(&mut self) {
+        FRAME_MANAGER.lock().free(self.start, self.count);
+    }
+}
@@ -2,11 +2,19 @@
 use crate::graphics::{
     WRITER,
     PixelColor,
+    PixelWriter,
+    Vector2D,
 };
+use crate::graphics::layer::{self, LayerId, LAYER_MANAGER};
+use crate::graphics::window;
 use crate::sync::SpinMutex;
+use crate::timer::{self, TIMER_FREQUENCY};
+use crate::utils::fixed_vec::FixedVec;
 
 pub const MOUSE_CURSOR_WIDTH: usize = 15;
 pub const MOUSE_CURSOR_HEIGHT: usize = 24;
+// cursor layer の透過色 (cursor 自体には使わない色)
+const MOUSE_TRANSPARENT_COLOR: PixelColor = PixelColor::new(1, 1, 1);
 pub const MOUSE_CURSOR_SHAPE: [&'static str; MOUSE_CURSOR_HEIGHT] = [
     "@              ",
     "@@             ",
@@ -34,20 +42,110 @@ pub const MOUSE_CURSOR_SHAPE: [&'static str; MOUSE_CURSOR_HEIGHT] = [
     "         @@@   ",
 ];
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MouseButton {
     Left = 0b001,
     Right = 0b010,
     Middle = 0b100,
 }
 
+const BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
 
+impl MouseButton {
+    pub fn is_pressed(self, buttons: u8) -> bool {
+        buttons & self as u8 != 0
+    }
 
+    fn index(self) -> usize {
+        BUTTONS.iter().position(|&button| button == self).unwrap()
+    }
+}
 
-pub extern "C" fn mouse_observer(dx: i8, dy: i8) {
-    // kprintln!("mouse_observer({}, {})", dx, dy);
-    let mut mouse = MOUSE.lock();
-    let (dx, dy) = (dx as isize, dy as isize);
-    mouse.move_relative(dx, dy);
+// 押した位置からこれより動かしたら click ではなく drag にする (pixel)
+const DRAG_THRESHOLD: isize = 4;
+// 2 回の click をこの間隔 (timer の tick 数) 以内にしたら double click
+const DOUBLE_CLICK_TICKS: u64 = TIMER_FREQUENCY / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseEventKind {
+    // drag していない移動
+    Move,
+    Press(MouseButton),
+    Release(MouseButton),
+    // 押した位置からあまり動かさずに離した (Release の後に届く)
+    Click(MouseButton),
+    // 同じ位置で続けて 2 回 click した (2 回目の Click の後に届く)
+    DoubleClick(MouseButton),
+    // 押したまま DRAG_THRESHOLD より動かした. DragStart, Drag, ..., DragEnd の順に届く
+    DragStart(MouseButton),
+    Drag(MouseButton),
+    DragEnd(MouseButton),
+    // 正なら奥に回した
+    Wheel(i8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+    // cursor の位置
+    pub pos: Vector2D<usize>,
+    // event の後に押されているボタン
+    pub buttons: u8,
+}
+
+// 1 回の report から作られる event の最大数
+const MAX_EVENTS_PER_REPORT: usize = 16;
+type MouseEvents = FixedVec<'static, MouseEvent, MAX_EVENTS_PER_REPORT>;
+
+pub type MouseHandler = fn(&MouseEvent);
+
+const MAX_SUBSCRIBERS: usize = 8;
+static SUBSCRIBERS: SpinMutex<FixedVec<'static, MouseHandler, MAX_SUBSCRIBERS>> = SpinMutex::new(FixedVec::new());
+
+// MouseEvent を受け取る関数を登録する. 登録しきれなければ false
+pub fn subscribe(handler: MouseHandler) -> bool {
+    unsafe { SUBSCRIBERS.lock().try_push(handler) }.is_ok()
+}
+
+// handler の中から subscribe できるように lock を外してから呼ぶ
+fn notify(event: &MouseEvent) {
+    let mut handlers: [Option<MouseHandler>; MAX_SUBSCRIBERS] = [None; MAX_SUBSCRIBERS];
+    for (slot, &handler) in handlers.iter_mut().zip(SUBSCRIBERS.lock().as_slice()) {
+        *slot = Some(handler);
+    }
+    handlers.iter().flatten().for_each(|handler| handler(event));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerMotion {
+    // mouse の移動量
+    Relative(isize, isize),
+    // tablet の位置. 0..=x_max, 0..=y_max を画面全体に合わせる
+    Absolute { x: u32, y: u32, x_max: u32, y_max: u32 },
+}
+
+// mikanos_usb の mouse driver から呼ばれる (xhc::process_events の中)
+pub extern "C" fn mouse_observer(buttons: u8, dx: i8, dy: i8, wheel: i8) {
+    // kprintln!("mouse_observer({:03b}, {}, {}, {})", buttons, dx, dy, wheel);
+    handle_pointer(PointerMotion::Relative(dx as isize, dy as isize), buttons, wheel);
+}
+
+// mouse や tablet の report を cursor, window, console と subscriber に反映する
+pub fn handle_pointer(motion: PointerMotion, buttons: u8, wheel: i8) {
+    let mut events = MouseEvents::new();
+    let (pos, prev_buttons) = {
+        let mut mouse = MOUSE.lock();
+        let prev_buttons = mouse.buttons;
+        mouse.handle_report(motion, buttons, wheel, timer::ticks(), &mut events);
+        (mouse.pos(), prev_buttons)
+    };
+    // window の操作中に cursor を描けるように MOUSE の lock は外しておく
+    let pos = Vector2D::new(pos.0 as usize, pos.1 as usize);
+    window::handle_mouse(pos, prev_buttons, buttons);
+    if wheel != 0 {
+        crate::console::handle_wheel(pos, wheel);
+    }
+    events.as_slice().iter().for_each(notify);
 }
 
 pub static MOUSE: SpinMutex<Mouse> = SpinMutex::new(Mouse::new());
@@ -62,6 +160,16 @@ pub struct Mouse {
     y: isize,
     max_x: isize,
     max_y: isize,
+    // 押されているボタン (MouseButton の bit の組み合わせ)
+    buttons: u8,
+    // 押されているボタンごとの押した位置
+    pressed_at: [Option<(isize, isize)>; 3],
+    // drag しているボタン (同時には 1 つだけ)
+    dragging: Option<MouseButton>,
+    // 最後の click (double click になったら忘れる)
+    last_click: Option<(MouseButton, (isize, isize), u64)>,
+    // cursor を描いた layer (なければ WRITER に直接描画する)
+    layer: Option<LayerId>,
 }
 
 use core::fmt;
@@ -74,31 +182,141 @@ impl fmt::Display for SpinMutex<Mouse> {
 
 impl Mouse {
     pub const fn new() -> Self {
-        Self { x: 0, y: 0, max_x: 0, max_y: 0 }
+        Self {
+            x: 0,
+            y: 0,
+            max_x: 0,
+            max_y: 0,
+            buttons: 0,
+            pressed_at: [None; 3],
+            dragging: None,
+            last_click: None,
+            layer: None,
+        }
     }
 
     pub fn init(&mut self, x: isize, y: isize) {
         (self.x, self.y) = (x, y);
         let writer = unsafe { WRITER.lock().assume_init() };
         (self.max_x, self.max_y) = (writer.horizontal_resolution() as isize, writer.vertical_resolution() as isize);
+        // cursor は layer に一度だけ描いておき, 移動は layer の移動で行う
+        self.layer = layer::new_layer(MOUSE_CURSOR_WIDTH, MOUSE_CURSOR_HEIGHT, Some(MOUSE_TRANSPARENT_COLOR));
+        if let Some(id) = self.layer {
+            // window を最前面に移しても cursor が隠れないようにする
+            if let Some(manager) = LAYER_MANAGER.lock().as_mut() {
+                manager.set_topmost(id, true);
+            }
+            layer::draw_on_layer(id, |layer| draw_cursor(layer, 0, 0, Some(&MOUSE_TRANSPARENT_COLOR)));
+        }
     }
 
     pub fn pos(&self) -> (isize, isize) {
         (self.x, self.y)
     }
 
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        button.is_pressed(self.buttons)
+    }
+
+    // driver からの report で cursor を動かし, 起きたことを events に積む. now は timer の tick
+    fn handle_report(&mut self, motion: PointerMotion, buttons: u8, wheel: i8, now: u64, events: &mut MouseEvents) {
+        let old_pos = self.pos();
+        match motion {
+            PointerMotion::Relative(0, 0) => {},
+            PointerMotion::Relative(dx, dy) => self.move_relative(dx, dy),
+            PointerMotion::Absolute { x, y, x_max, y_max } => {
+                // 右端, 下端は画面の最後の pixel にする
+                let scale = |v: u32, max: u32, screen: isize| (v as u64 * (screen - 1).max(0) as u64 / max.max(1) as u64) as isize;
+                let pos = (scale(x, x_max, self.max_x), scale(y, y_max, self.max_y));
+                if pos != old_pos {
+                    self.move_to(pos.0, pos.1);
+                }
+            },
+        }
+        let pos = self.pos();
+        let moved = pos != old_pos;
+        let event_pos = Vector2D::new(pos.0 as usize, pos.1 as usize);
+        let mut push = |kind, buttons| events.push(MouseEvent { kind, pos: event_pos, buttons });
+
+        // 移動は前のボタンの状態で扱う
+        if moved {
+            if self.dragging.is_none() {
+                self.dragging = BUTTONS.iter().copied().find(|button| {
+                    self.pressed_at[button.index()].map_or(false, |start| distance(start, pos) > DRAG_THRESHOLD)
+                });
+                if let Some(button) = self.dragging {
+                    push(MouseEventKind::DragStart(button), self.buttons);
+                }
+            }
+            match self.dragging {
+                Some(button) => push(MouseEventKind::Drag(button), self.buttons),
+                None => push(MouseEventKind::Move, self.buttons),
+            }
+        }
+
+        let prev_buttons = self.buttons;
+        self.buttons = buttons;
+        for &button in BUTTONS.iter() {
+            match (button.is_pressed(prev_buttons), button.is_pressed(buttons)) {
+                (false, true) => {
+                    self.pressed_at[button.index()] = Some(pos);
+                    push(MouseEventKind::Press(button), buttons);
+                },
+                (true, false) => {
+                    push(MouseEventKind::Release(button), buttons);
+                    let start = self.pressed_at[button.index()].take();
+                    if self.dragging == Some(button) {
+                        self.dragging = None;
+                        push(MouseEventKind::DragEnd(button), buttons);
+                        continue;
+                    }
+                    // 他のボタンの drag 中に動いた分も click にはしない
+                    if !start.map_or(false, |start| distance(start, pos) <= DRAG_THRESHOLD) {
+                        continue;
+                    }
+                    push(MouseEventKind::Click(button), buttons);
+                    match self.last_click {
+                        Some((last, last_pos, at))
+                            if last == button && now - at <= DOUBLE_CLICK_TICKS && distance(last_pos, pos) <= DRAG_THRESHOLD =>
+                        {
+                            push(MouseEventKind::DoubleClick(button), buttons);
+                            self.last_click = None;
+                        },
+                        _ => self.last_click = Some((button, pos, now)),
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if wheel != 0 {
+            push(MouseEventKind::Wheel(wheel), buttons);
+        }
+    }
+
     pub fn move_relative(&mut self, dx: isize, dy: isize) {
-        // 1. if x + self.x < 0 { self.x = 0 }
-        // 2. else if x + self.x > self.max_x { self.x = self.max_x }
-        // 3. else { self.x += x }
-        self.erase();
         // todo: usize でもつなら self.x + dx で負になるか事前に判定
-        self.x = match self.x + dx {
+        self.move_to(self.x + dx, self.y + dy);
+    }
+
+    // 画面の外なら端に合わせる
+    pub fn move_to(&mut self, x: isize, y: isize) {
+        // 1. if x < 0 { self.x = 0 }
+        // 2. else if x > self.max_x { self.x = self.max_x }
+        // 3. else { self.x = x }
+        if self.layer.is_none() {
+            self.erase();
+        }
+        self.x = match x {
             v if v < 0 => { 0 },
             v if v > self.max_x => { self.max_x },
             v => { v },
         };
-        self.y = match self.y + dy {
+        self.y = match y {
             v if v < 0 => { 0 },
             v if v > self.max_y => { self.max_y },
             v => { v },
@@ -125,22 +343,44 @@ impl Mouse {
 
     }
 
+    // layer があれば移動させ, なければ WRITER に直接描く
     pub fn draw(&self) {
-        let writer = unsafe { WRITER.lock().assume_init() };
-        for dy in 0..MOUSE_CURSOR_SHAPE.len() {
-            MOUSE_CURSOR_SHAPE[dy].chars()
-                .enumerate()
-                .for_each(|(dx, c)| {
-                    let x = self.x as usize + dx;
-                    let y = self.y as usize + dy;
-                    match c {
-                        '@' => writer.draw_pixel(x, y, &PixelColor::BLACK),
-                        '.' => writer.draw_pixel(x, y, &PixelColor::WHITE),
-                        ' ' => { /* do nothing */ },
-                        c => panic!("Unexpected cursor shape: {}", c),
-                    }
-            });
+        let (x, y) = (self.x as usize, self.y as usize);
+        match self.layer {
+            Some(id) => {
+                if let Some(manager) = LAYER_MANAGER.lock().as_mut() {
+                    manager.move_to(id, Vector2D::new(x, y));
+                    manager.composite();
+                }
+            },
+            None => {
+                let writer = unsafe { WRITER.lock().assume_init() };
+                draw_cursor(writer, x, y, None);
+                crate::graphics::flush_screen();
+            },
         }
-        
+    }
+}
+
+fn distance(a: (isize, isize), b: (isize, isize)) -> isize {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs())
+}
+
+// (x, y) に cursor を描く. 空白の部分は space_color で塗る (None なら何もしない)
+fn draw_cursor(writer: &dyn PixelWriter, x: usize, y: usize, space_color: Option<&PixelColor>) {
+    for dy in 0..MOUSE_CURSOR_SHAPE.len() {
+        MOUSE_CURSOR_SHAPE[dy].chars()
+            .enumerate()
+            .for_each(|(dx, c)| {
+                let color = match c {
+                    '@' => Some(&PixelColor::BLACK),
+                    '.' => Some(&PixelColor::WHITE),
+                    ' ' => space_color,
+                    c => panic!("Unexpected cursor shape: {}", c),
+                };
+                if let Some(color) = color {
+                    writer.draw_pixel(x + dx, y + dy, color);
+                }
+        });
     }
 }
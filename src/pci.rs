@@ -1,5 +1,5 @@
-use core::arch::asm;
 use crate::interrupts::idt::InterruptVector;
+use crate::io::Port;
 
 type Result<T> = core::result::Result<T, ()>;
 
@@ -156,6 +156,26 @@ impl Device {
         Some(bar)
     }
 
+    // command register の memory space (bit 1) と bus master (bit 2) を有効にする.
+    // DMA を行うデバイスはこれがないとメモリにアクセスできない
+    pub fn enable_bus_master(&self) {
+        let mut command = self.read_register(0x04);
+        // 上位 16bit は status register (write 1 to clear) なので 0 を書く
+        command = *command
+            .set_bits(16..32, 0)
+            .set_bit(1, true)
+            .set_bit(2, true);
+        self.write_register(0x04, command);
+    }
+
+    pub fn capabilities(&self) -> CapabilityIter<'_> {
+        CapabilityIter {
+            device: self,
+            // 最初の capability pointer を読む (32bit から下位 8bit のみ必要)
+            cap_addr: self.read_register(0x34).get_bits(0..8) as u8,
+        }
+    }
+
     pub fn configure_msi_fixed_destination(
         &self, 
         apic_id: u8, 
@@ -164,16 +184,56 @@ impl Device {
         vector: InterruptVector,
         num_vector_exponent: u32,
     ) -> Result<()> {
-        let msg_addr: u32 = *0xfee00000.set_bits(12..20, apic_id as u32);
+        let (msg_addr, msg_data) = msi_message(apic_id, trigger_mode, derivary_mode, vector);
+        self.configure_msi(msg_addr, msg_data, num_vector_exponent)
+    }
+
+    // MSI-X テーブルの table_index 番目のエントリを設定し, MSI-X を有効にする
+    pub fn configure_msix_fixed_destination(
+        &self, 
+        apic_id: u8, 
+        trigger_mode: MSITriggerMode,
+        derivary_mode: MSIDeliveryMode,
+        vector: InterruptVector,
+        table_index: u16,
+    ) -> Result<()> {
+        let (msg_addr, msg_data) = msi_message(apic_id, trigger_mode, derivary_mode, vector);
+        self.configure_msix(msg_addr, msg_data, table_index)
+    }
+
+    fn configure_msix(
+        &self, 
+        msg_addr: u32, 
+        msg_data: u32, 
+        table_index: u16,
+    ) -> Result<()> {
+        let cap_addr = self.capabilities()
+            .find(|&(_, cap_id)| cap_id == CapabilityHeader::CAPABILITY_ID_MSIX)
+            .map(|(cap_addr, _)| cap_addr)
+            .ok_or(())?;
+
+        let mut header = self.read_register(cap_addr);
+        let table_size = header.get_bits(16..27) as u16 + 1;
+        if table_index >= table_size {
+            return Err(());
+        }
 
-        let mut msg_data = *0_u32
-            .set_bits(8..11, derivary_mode as u32)
-            .set_bits(0..8, vector as u32);
-        if trigger_mode == MSITriggerMode::Level {
-            msg_data |= 0xc000; // trigger mode, level を 1 をセット
+        // table offset/BIR: 下位 3bit が BAR の番号, 残りが BAR からのオフセット
+        let table = self.read_register(cap_addr + 4);
+        let bir = table.get_bits(0..3) as u8;
+        let bar = self.read_bar(bir).ok_or(())? & !0x0f;
+        let entry = (bar + (table & !0b111) as u64 + 16 * table_index as u64) as *mut u32;
+        unsafe {
+            entry.write_volatile(msg_addr);
+            entry.add(1).write_volatile(0); // message upper address
+            entry.add(2).write_volatile(msg_data);
+            entry.add(3).write_volatile(0); // vector control: unmask
         }
-        
-        self.configure_msi(msg_addr, msg_data, num_vector_exponent)
+
+        // MSI-X enable (bit 31) を立て, function mask (bit 30) を外す
+        header = *header.set_bit(31, true).set_bit(30, false);
+        self.write_register(cap_addr, header);
+        Ok(())
     }
 
     fn configure_msi(
@@ -280,6 +340,44 @@ impl Device {
     }
 }
 
+fn msi_message(
+    apic_id: u8, 
+    trigger_mode: MSITriggerMode,
+    derivary_mode: MSIDeliveryMode,
+    vector: InterruptVector,
+) -> (u32, u32) {
+    let msg_addr: u32 = *0xfee00000.set_bits(12..20, apic_id as u32);
+
+    let mut msg_data = *0_u32
+        .set_bits(8..11, derivary_mode as u32)
+        .set_bits(0..8, vector as u32);
+    if trigger_mode == MSITriggerMode::Level {
+        msg_data |= 0xc000; // trigger mode, level を 1 をセット
+    }
+    (msg_addr, msg_data)
+}
+
+// (capability のアドレス, capability ID) を順に返す
+pub struct CapabilityIter<'a> {
+    device: &'a Device,
+    cap_addr: u8,
+}
+
+impl Iterator for CapabilityIter<'_> {
+    type Item = (u8, u8);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cap_addr == 0 {
+            return None;
+        }
+        let cap_addr = self.cap_addr;
+        let header = CapabilityHeader {
+            data: self.device.read_register(cap_addr),
+        };
+        self.cap_addr = header.next_ptr();
+        Some((cap_addr, header.cap_id()))
+    }
+}
+
 #[derive(Debug)]
 #[repr(transparent)]
 struct CapabilityHeader {
@@ -358,16 +456,16 @@ pub enum MSIDeliveryMode {
 }
 
 pub fn write_config_addr(addr: u32) {
-    let mut port = IOPort::new(CONFIG_ADDRESS);
-    port.write32(addr);
+    let mut port = Port::<u32>::new(CONFIG_ADDRESS);
+    port.write(addr);
 }
 pub fn write_config_data(data: u32) {
-    let mut port = IOPort::new(CONFIG_DATA);
-    port.write32(data);
+    let mut port = Port::<u32>::new(CONFIG_DATA);
+    port.write(data);
 }
 pub fn read_config_data() -> u32 {
-    let mut port = IOPort::new(CONFIG_DATA);
-    port.read32()
+    let mut port = Port::<u32>::new(CONFIG_DATA);
+    port.read()
 }
 
 fn is_single_function_device(header_type: u8) -> bool {
@@ -461,49 +559,3 @@ fn add_device(config: Config) -> Result<()> {
     }
 }
 
-pub struct IOPort {
-    port: u16,
-}
-
-impl IOPort {
-    pub fn new(port: u16) -> Self {
-        Self { port }
-    }
-
-    pub fn read16(&mut self) -> u16 {
-        let eax: u16;
-        unsafe { asm!(
-            "in eax, dx",
-            out("eax") eax,
-            in("dx") self.port,
-        ) };
-        eax
-    }
-
-    pub fn read32(&mut self) -> u32 {
-        let eax: u32;
-        unsafe { asm!(
-            "in eax, dx",
-            out("eax") eax,
-            in("dx") self.port,
-        ) };
-        eax
-    }
-
-    pub fn write16(&mut self, data: u16) {
-        unsafe { asm!(
-            "out dx, eax",
-            in("dx") self.port,
-            in("eax") data,
-        ) };
-    }
-
-    pub fn write32(&mut self, data: u32) {
-        unsafe { asm!(
-            "out dx, eax",
-            in("dx") self.port,
-            in("eax") data,
-        ) };
-    }
-
-}
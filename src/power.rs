@@ -0,0 +1,120 @@
+//! shutdown, reboot and QEMU exit
+
+use crate::acpi::{ACPI, GenericAddress};
+use crate::io::{Port, PortReadOnly, PortWriteOnly};
+use crate::utils::bit_field::BitField;
+use crate::warn;
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::VirtAddr;
+
+// PM1 control register
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_EN: u16 = 1 << 13;
+
+// keyboard controller
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+const KBC_CMD_PULSE_RESET: u8 = 0xfe;
+// input buffer が空くのを待つ回数の上限 (keyboard controller がないと空かないことがある)
+const KBC_WAIT_LIMIT: usize = 100_000;
+
+// PCI reset control register (Intel chipset). SYS_RST | RST_CPU で hard reset
+const RESET_CONTROL: u16 = 0xcf9;
+const RESET_CONTROL_SYS_RST: u8 = 1 << 1;
+const RESET_CONTROL_RST_CPU: u8 = 1 << 2;
+
+// reset の方法を試すたびに, 効くまでこの回数だけ待つ
+const RESET_WAIT: usize = 1_000_000;
+
+// QEMU の isa-debug-exit デバイス (-device isa-debug-exit,iobase=0xf4,iosize=0x04)
+const QEMU_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+// QEMU の終了ステータスは (code << 1) | 1 になる (Success: 33, Failed: 35)
+#[derive(Debug, Clone, Copy)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+fn halt_forever() -> ! {
+    x86_64::instructions::interrupts::disable();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+// ACPI S5 (soft off) に遷移する. 失敗した場合は停止する
+pub fn shutdown() -> ! {
+    let target = ACPI.lock().as_ref().and_then(|acpi| {
+        let (pm1a, pm1b) = acpi.fadt().pm1_control_ports();
+        acpi.s5_sleep_type().map(|slp_typ| (pm1a, pm1b, slp_typ))
+    });
+
+    match target {
+        Some((pm1a, pm1b, (slp_typ_a, slp_typ_b))) => {
+            x86_64::instructions::interrupts::disable();
+            let write_pm1 = |port: u16, slp_typ: u8| {
+                let mut port = Port::<u16>::new(port);
+                let val = port.read() & !(0b111 << SLP_TYP_SHIFT);
+                port.write(val | (slp_typ as u16) << SLP_TYP_SHIFT | SLP_EN);
+            };
+            if let Some(pm1b) = pm1b {
+                write_pm1(pm1b, slp_typ_b);
+            }
+            write_pm1(pm1a, slp_typ_a);
+        },
+        None => {
+            warn!("shutdown: ACPI S5 is not available");
+        },
+    }
+    halt_forever()
+}
+
+fn wait_reset() {
+    for _ in 0..RESET_WAIT {
+        core::hint::spin_loop();
+    }
+}
+
+// FADT の reset register, keyboard controller, reset control register, triple fault の順に試す
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    let reset_reg = ACPI.lock().as_ref().and_then(|acpi| acpi.fadt().reset_register());
+    if let Some((reg, value)) = reset_reg {
+        match reg.address_space_id {
+            GenericAddress::SYSTEM_IO => PortWriteOnly::<u8>::new(reg.address as u16).write(value),
+            GenericAddress::SYSTEM_MEMORY => unsafe { (reg.address as *mut u8).write_volatile(value) },
+            // PCI configuration space など
+            _ => {},
+        }
+        wait_reset();
+    }
+
+    // input buffer が空くのを待ってから reset line を pulse する
+    let mut status = PortReadOnly::<u8>::new(KBC_STATUS);
+    if (0..KBC_WAIT_LIMIT).any(|_| !status.read().get_bit(1)) {
+        PortWriteOnly::<u8>::new(KBC_COMMAND).write(KBC_CMD_PULSE_RESET);
+        wait_reset();
+    }
+
+    // 0 -> 1 の変化で reset するので, SYS_RST を立ててから RST_CPU を立てる
+    let mut reset_control = PortWriteOnly::<u8>::new(RESET_CONTROL);
+    reset_control.write(RESET_CONTROL_SYS_RST);
+    reset_control.write(RESET_CONTROL_SYS_RST | RESET_CONTROL_RST_CPU);
+    wait_reset();
+
+    // 空の IDT で例外を起こすと triple fault になり, CPU がリセットされる
+    let empty_idt = DescriptorTablePointer { limit: 0, base: VirtAddr::new(0) };
+    unsafe { x86_64::instructions::tables::lidt(&empty_idt) };
+    x86_64::instructions::interrupts::int3();
+
+    halt_forever()
+}
+
+// isa-debug-exit デバイスがなければ何も起きないので, 戻ってきたら停止する
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    PortWriteOnly::<u32>::new(QEMU_DEBUG_EXIT_PORT).write(code as u32);
+    halt_forever()
+}
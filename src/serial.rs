@@ -0,0 +1,181 @@
+//! 16550 UART (COM1) driver
+//! 参考: https://wiki.osdev.org/Serial_Ports
+
+use core::convert::TryFrom;
+use crate::sync::{IrqSpinMutex, SpinMutex};
+use crate::io::Port;
+use crate::utils::bit_field::BitField;
+use crate::utils::ring_buffer::RingBuffer;
+use crate::interrupts::idt::InterruptVector;
+use crate::apic;
+
+pub const COM1: u16 = 0x3f8;
+const COM1_IRQ: u8 = 4;
+
+const UART_CLOCK: u32 = 115200;
+const FIFO_SIZE: usize = 16;
+
+// register offsets
+const DATA: u16 = 0; // RBR (read) / THR (write), DLL if DLAB = 1
+const INTERRUPT_ENABLE: u16 = 1; // IER, DLM if DLAB = 1
+const FIFO_CONTROL: u16 = 2; // FCR
+const LINE_CONTROL: u16 = 3; // LCR
+const MODEM_CONTROL: u16 = 4; // MCR
+const LINE_STATUS: u16 = 5; // LSR
+
+#[derive(Debug)]
+pub enum SerialError {
+    NotPresent,
+    InvalidBaudRate,
+}
+type Result<T> = core::result::Result<T, SerialError>;
+
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn port(&self, offset: u16) -> Port<u8> {
+        Port::new(self.base + offset)
+    }
+
+    pub fn init(&mut self, baud_rate: u32) -> Result<()> {
+        self.port(INTERRUPT_ENABLE).write(0x00);
+        self.set_baud_rate(baud_rate)?;
+        // 8 bit, no parity, 1 stop bit
+        self.port(LINE_CONTROL).write(0x03);
+        // enable FIFO, clear them, 14-byte threshold
+        self.port(FIFO_CONTROL).write(0xc7);
+
+        // loopback mode で受信できるか確認する
+        self.port(MODEM_CONTROL).write(0x1e);
+        self.port(DATA).write(0xae);
+        if self.port(DATA).read() != 0xae {
+            return Err(SerialError::NotPresent);
+        }
+
+        // normal mode: DTR, RTS, OUT2 (OUT2 は IRQ を有効にするために必要)
+        self.port(MODEM_CONTROL).write(0x0b);
+        Ok(())
+    }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        if baud_rate == 0 || UART_CLOCK % baud_rate != 0 {
+            return Err(SerialError::InvalidBaudRate);
+        }
+        let divisor = u16::try_from(UART_CLOCK / baud_rate).map_err(|_| SerialError::InvalidBaudRate)?;
+        let mut lcr = self.port(LINE_CONTROL).read();
+        // DLAB = 1 の間は DATA, INTERRUPT_ENABLE が divisor latch になる
+        self.port(LINE_CONTROL).write(*lcr.set_bit(7, true));
+        self.port(DATA).write(divisor.get_bits(0..8) as u8);
+        self.port(INTERRUPT_ENABLE).write(divisor.get_bits(8..16) as u8);
+        self.port(LINE_CONTROL).write(*lcr.set_bit(7, false));
+        Ok(())
+    }
+
+    pub fn enable_rx_interrupt(&mut self) {
+        self.port(INTERRUPT_ENABLE).write(0x01);
+    }
+
+    fn is_transmit_empty(&self) -> bool {
+        self.port(LINE_STATUS).read().get_bit(5)
+    }
+
+    fn is_data_ready(&self) -> bool {
+        self.port(LINE_STATUS).read().get_bit(0)
+    }
+
+    // THR が空になったら FIFO が埋まるまでまとめて書き込む
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(FIFO_SIZE) {
+            while !self.is_transmit_empty() {
+                core::hint::spin_loop();
+            }
+            let mut data = self.port(DATA);
+            for &b in chunk {
+                data.write(b);
+            }
+        }
+    }
+
+    pub fn receive(&mut self) -> Option<u8> {
+        if self.is_data_ready() {
+            Some(self.port(DATA).read())
+        } else {
+            None
+        }
+    }
+}
+
+use core::fmt;
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.write_bytes(b"\r\n");
+            }
+            self.write_bytes(line.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+// 割り込みハンドラからは lock しないので, 送信中に割り込みを止めない SpinMutex にする
+pub static SERIAL: SpinMutex<Option<SerialPort>> = SpinMutex::new(None);
+// 受信の割り込みハンドラと read_byte の両方が lock する
+static RX_BUFFER: IrqSpinMutex<RingBuffer<u8, 256>> = IrqSpinMutex::new(RingBuffer::new());
+
+// COM1 を 115200 baud で初期化する.
+// ポートが存在しなければ何もしない (出力は framebuffer のみになる)
+pub fn init_serial() {
+    let mut port = SerialPort::new(COM1);
+    if port.init(115200).is_ok() {
+        *SERIAL.lock() = Some(port);
+    }
+}
+
+// IDT の設定後に呼び出すこと
+pub fn enable_serial_interrupt() {
+    let mut serial = SERIAL.lock();
+    if let Some(port) = serial.as_mut() {
+        apic::disable_pic();
+        apic::redirect_irq(COM1_IRQ, InterruptVector::COM1, apic::local_apic_id());
+        port.enable_rx_interrupt();
+    }
+}
+
+// 割り込みハンドラから呼ばれる. SERIAL は送信中にロックされている可能性があるのでロックしない
+pub fn handle_interrupt() {
+    let mut port = SerialPort::new(COM1);
+    let mut rx = RX_BUFFER.lock();
+    while let Some(b) = port.receive() {
+        // 溢れたら古いものから捨てる
+        rx.push_overwrite(b);
+    }
+}
+
+pub fn read_byte() -> Option<u8> {
+    RX_BUFFER.lock().pop()
+}
+
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if let Some(port) = SERIAL.lock().as_mut() {
+        port.write_fmt(args).unwrap();
+    }
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
@@ -0,0 +1,458 @@
+//! kernel shell
+//! keyboard と serial から 1 行ずつ読み, 組み込みのコマンドを実行する.
+//! 入力中の行は kprint! で console と serial の両方に表示する.
+//! 行が console の幅を超えて折り返すと表示が崩れる.
+
+use crate::console::ansi::{Action, Parser};
+use crate::interrupts::{self, idt::InterruptVector};
+use crate::keyboard::{self, Key, KeyEvent};
+use crate::logger::{self, sink::DiskSink, LogLevel};
+use crate::memory::{self, FRAME_MANAGER, FRAME_SIZE};
+use crate::sync::SpinMutex;
+use crate::{kprint, kprintln, pci, power, virtio, xhc};
+use core::str::SplitWhitespace;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+const PROMPT: &str = "> ";
+const MAX_LINE: usize = 128;
+const MAX_HISTORY: usize = 16;
+
+// 行編集の操作
+#[derive(Clone, Copy)]
+enum Input {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    // Ctrl+C: 入力中の行を捨てる
+    Cancel,
+    // Ctrl+L: 画面を消す
+    Clear,
+}
+
+impl Input {
+    // Enter は keyboard では '\n', serial の端末では '\r'. Backspace は端末によって 0x7f
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '\n' | '\r' => Some(Input::Enter),
+            '\x08' | '\x7f' => Some(Input::Backspace),
+            '\t' => Some(Input::Tab),
+            '\x01' => Some(Input::Home),
+            '\x05' => Some(Input::End),
+            '\x03' => Some(Input::Cancel),
+            '\x0c' => Some(Input::Clear),
+            // 表示できるのは ASCII だけ
+            ' '..='~' => Some(Input::Char(c)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Line {
+    bytes: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self { bytes: [0; MAX_LINE], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // ASCII しか入れないので UTF-8 として正しい
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+
+    fn insert(&mut self, at: usize, c: u8) -> bool {
+        if self.len == MAX_LINE {
+            return false;
+        }
+        self.bytes.copy_within(at..self.len, at + 1);
+        self.bytes[at] = c;
+        self.len += 1;
+        true
+    }
+
+    fn remove(&mut self, at: usize) {
+        self.bytes.copy_within(at + 1..self.len, at);
+        self.len -= 1;
+    }
+
+    fn set(&mut self, s: &str) {
+        let len = s.len().min(MAX_LINE);
+        self.bytes[..len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len = len;
+    }
+}
+
+struct Shell {
+    line: Line,
+    cursor: usize,
+    // 古いものから順に history_start から history_len 個
+    history: [Line; MAX_HISTORY],
+    history_start: usize,
+    history_len: usize,
+    // 表示している履歴 (0 が最新). None なら入力中の行
+    browsing: Option<usize>,
+    // 履歴を見る前に入力していた行
+    editing: Line,
+    // serial から受け取った矢印キーなどの escape sequence を読む
+    serial_parser: Parser,
+}
+
+impl Shell {
+    const fn new() -> Self {
+        Self {
+            line: Line::new(),
+            cursor: 0,
+            history: [Line::new(); MAX_HISTORY],
+            history_start: 0,
+            history_len: 0,
+            browsing: None,
+            editing: Line::new(),
+            serial_parser: Parser::new(),
+        }
+    }
+
+    // 入力を行に反映する. Enter なら実行する行を返す
+    fn edit(&mut self, input: Input) -> Option<Line> {
+        match input {
+            Input::Char(c) => {
+                if self.line.insert(self.cursor, c as u8) {
+                    self.cursor += 1;
+                }
+            },
+            Input::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            },
+            Input::Delete if self.cursor < self.line.len => self.line.remove(self.cursor),
+            Input::Left => self.cursor = self.cursor.saturating_sub(1),
+            Input::Right => self.cursor = (self.cursor + 1).min(self.line.len),
+            Input::Home => self.cursor = 0,
+            Input::End => self.cursor = self.line.len,
+            Input::Up => self.browse(self.browsing.map_or(0, |i| i + 1)),
+            Input::Down => match self.browsing {
+                Some(0) => {
+                    self.browsing = None;
+                    self.line = self.editing;
+                    self.cursor = self.line.len;
+                },
+                Some(i) => self.browse(i - 1),
+                None => {},
+            },
+            Input::Tab => self.complete(),
+            Input::Enter => {
+                kprintln!();
+                let line = self.line;
+                self.push_history(line);
+                self.reset();
+                return Some(line);
+            },
+            Input::Cancel => {
+                kprintln!("^C");
+                self.reset();
+                kprint!("{}", PROMPT);
+                return None;
+            },
+            Input::Clear => kprint!("\x1b[2J\x1b[H"),
+            _ => {},
+        }
+        self.redraw();
+        None
+    }
+
+    fn reset(&mut self) {
+        self.line = Line::new();
+        self.cursor = 0;
+        self.browsing = None;
+    }
+
+    // 行を書き直し, カーソルを編集位置に戻す
+    fn redraw(&self) {
+        let back = self.line.len - self.cursor;
+        if back > 0 {
+            kprint!("\r{}{}\x1b[K\x1b[{}D", PROMPT, self.line.as_str(), back);
+        } else {
+            kprint!("\r{}{}\x1b[K", PROMPT, self.line.as_str());
+        }
+    }
+
+    fn push_history(&mut self, line: Line) {
+        if line.as_str().trim().is_empty() || self.history_entry(0).map(Line::as_str) == Some(line.as_str()) {
+            return;
+        }
+        if self.history_len < MAX_HISTORY {
+            self.history[(self.history_start + self.history_len) % MAX_HISTORY] = line;
+            self.history_len += 1;
+        } else {
+            self.history[self.history_start] = line;
+            self.history_start = (self.history_start + 1) % MAX_HISTORY;
+        }
+    }
+
+    // back 個前の履歴. 0 が最新
+    fn history_entry(&self, back: usize) -> Option<&Line> {
+        if back >= self.history_len {
+            return None;
+        }
+        Some(&self.history[(self.history_start + self.history_len - 1 - back) % MAX_HISTORY])
+    }
+
+    fn browse(&mut self, back: usize) {
+        let entry = match self.history_entry(back) {
+            Some(&entry) => entry,
+            None => return,
+        };
+        if self.browsing.is_none() {
+            self.editing = self.line;
+        }
+        self.browsing = Some(back);
+        self.line = entry;
+        self.cursor = self.line.len;
+    }
+
+    // コマンド名を補完する. 候補が複数あれば共通部分まで伸ばし, 伸ばせなければ一覧を表示する
+    fn complete(&mut self) {
+        let prefix = &self.line.as_str()[..self.cursor];
+        if prefix.contains(' ') || self.cursor != self.line.len {
+            return;
+        }
+        let mut candidates = COMMANDS.iter().map(|command| command.name).filter(|name| name.starts_with(prefix));
+        let first = match candidates.next() {
+            Some(first) => first,
+            None => return,
+        };
+        let common = candidates.clone().fold(first.len(), |len, name| {
+            first.bytes().zip(name.bytes()).take(len).take_while(|(a, b)| a == b).count()
+        });
+        let is_unique = candidates.next().is_none();
+
+        if is_unique {
+            let mut line = Line::new();
+            line.set(first);
+            line.insert(line.len, b' ');
+            self.line = line;
+        } else if common > prefix.len() {
+            self.line.set(&first[..common]);
+        } else {
+            kprintln!();
+            COMMANDS.iter().filter(|command| command.name.starts_with(prefix)).for_each(|command| kprint!("{}  ", command.name));
+            kprintln!();
+        }
+        self.cursor = self.line.len;
+    }
+}
+
+static SHELL: SpinMutex<Shell> = SpinMutex::new(Shell::new());
+
+fn handle_input(input: Input) {
+    // コマンドは SHELL を lock せずに実行する
+    let line = SHELL.lock().edit(input);
+    if let Some(line) = line {
+        execute(line.as_str());
+        kprint!("{}", PROMPT);
+    }
+}
+
+fn handle_key_event(event: &KeyEvent) {
+    if !event.pressed {
+        return;
+    }
+    let input = match event.key {
+        Key::Left => Some(Input::Left),
+        Key::Right => Some(Input::Right),
+        Key::Up => Some(Input::Up),
+        Key::Down => Some(Input::Down),
+        Key::Home => Some(Input::Home),
+        Key::End => Some(Input::End),
+        // Delete の文字 (0x7f) は serial の Backspace と同じなので先に見る
+        Key::Delete => Some(Input::Delete),
+        _ => event.char.and_then(Input::from_char),
+    };
+    if let Some(input) = input {
+        handle_input(input);
+    }
+}
+
+// main loop から呼ぶ. screenshot の要求でない serial の入力を渡す
+pub fn handle_serial_byte(byte: u8) {
+    if !byte.is_ascii() {
+        return;
+    }
+    let action = SHELL.lock().serial_parser.advance(byte as char);
+    let input = match action {
+        Some(Action::Print(c)) | Some(Action::Control(c)) => Input::from_char(c),
+        Some(Action::Csi { params, private: false, action }) => match (action, params.get(0)) {
+            ('A', _) => Some(Input::Up),
+            ('B', _) => Some(Input::Down),
+            ('C', _) => Some(Input::Right),
+            ('D', _) => Some(Input::Left),
+            ('H', _) | ('~', Some(1)) | ('~', Some(7)) => Some(Input::Home),
+            ('F', _) | ('~', Some(4)) | ('~', Some(8)) => Some(Input::End),
+            ('~', Some(3)) => Some(Input::Delete),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(input) = input {
+        handle_input(input);
+    }
+}
+
+// 初期化の最後に呼ぶ. keyboard の入力を受け取り, prompt を表示する
+pub fn init_shell() {
+    if !keyboard::subscribe(handle_key_event) {
+        kprintln!("shell: keyboard is not available");
+    }
+    kprint!("{}", PROMPT);
+}
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    run: fn(&mut SplitWhitespace),
+}
+
+const COMMANDS: [Command; 11] = [
+    Command { name: "help", usage: "help: show commands", run: help },
+    Command { name: "lspci", usage: "lspci: list PCI devices", run: lspci },
+    Command { name: "mem", usage: "mem: show memory map and free frames", run: mem },
+    Command { name: "loglevel", usage: "loglevel [module] [error|warn|info|debug|trace|default]: show or set log level", run: loglevel },
+    Command { name: "dmesg", usage: "dmesg [-c]: show (or clear) kernel log", run: dmesg },
+    Command { name: "logdisk", usage: "logdisk <lba> <sectors>: also write log to virtio-blk sectors (overwrites them)", run: logdisk },
+    Command { name: "clear", usage: "clear: clear the screen", run: clear },
+    Command { name: "irq", usage: "irq: show interrupt counts", run: irq },
+    Command { name: "usb", usage: "usb: list USB devices", run: usb },
+    Command { name: "reboot", usage: "reboot: reset the machine", run: reboot },
+    Command { name: "poweroff", usage: "poweroff: shut down the machine", run: poweroff },
+];
+
+fn execute(line: &str) {
+    let mut args = line.split_whitespace();
+    let name = match args.next() {
+        Some(name) => name,
+        None => return,
+    };
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(&mut args),
+        None => kprintln!("{}: command not found", name),
+    }
+}
+
+fn help(_args: &mut SplitWhitespace) {
+    COMMANDS.iter().for_each(|command| kprintln!("{}", command.usage));
+}
+
+fn lspci(_args: &mut SplitWhitespace) {
+    for device in pci::devices() {
+        kprintln!("{:?}, device {:x}", device, device.as_config().read_device_id());
+    }
+}
+
+fn mem(_args: &mut SplitWhitespace) {
+    memory::for_each_memory_region(|region| {
+        let end = region.phys_start + region.page_count * FRAME_SIZE as u64;
+        kprintln!("{:#012x}-{:#012x} {:>8} pages {}", region.phys_start, end, region.page_count, region.type_name());
+    });
+    let (free, total) = {
+        let manager = FRAME_MANAGER.lock();
+        (manager.free_frames(), manager.total_frames())
+    };
+    kprintln!(
+        "frames: {} / {} free ({} / {} MiB)",
+        free, total, (free * FRAME_SIZE) >> 20, (total * FRAME_SIZE) >> 20,
+    );
+}
+
+fn loglevel(args: &mut SplitWhitespace) {
+    let unknown = |name: &str| kprintln!("loglevel: unknown level {}", name);
+    match (args.next(), args.next()) {
+        (None, _) => {
+            kprintln!("{}", logger::log_level().name());
+            logger::for_each_module_log_level(|module, level| kprintln!("{}: {}", module, level.name()));
+        },
+        (Some(name), None) => match LogLevel::from_name(name) {
+            Some(level) => logger::set_log_level(level),
+            None => unknown(name),
+        },
+        (Some(module), Some("default")) => {
+            logger::set_module_log_level(module, None);
+        },
+        (Some(module), Some(name)) => match LogLevel::from_name(name) {
+            Some(level) => {
+                if !logger::set_module_log_level(module, Some(level)) {
+                    kprintln!("loglevel: cannot set level of {}", module);
+                }
+            },
+            None => unknown(name),
+        },
+    }
+}
+
+fn dmesg(args: &mut SplitWhitespace) {
+    match args.next() {
+        Some("-c") => logger::clear_records(),
+        _ => logger::for_each_record(|record| kprintln!("{}", record.display(true))),
+    }
+}
+
+static DISK_LOG: DiskSink = DiskSink::new(virtio::blk::try_write_sectors);
+static DISK_LOG_ADDED: AtomicBool = AtomicBool::new(false);
+
+fn logdisk(args: &mut SplitWhitespace) {
+    let mut number = || args.next().and_then(|arg| arg.parse::<u64>().ok());
+    match (number(), number()) {
+        (Some(lba), Some(sectors)) if sectors > 0 => {
+            DISK_LOG.start(lba, sectors);
+            if !DISK_LOG_ADDED.swap(true, Ordering::Relaxed) && !logger::add_sink(&DISK_LOG) {
+                kprintln!("logdisk: too many sinks");
+            }
+        },
+        _ => kprintln!("usage: logdisk <lba> <sectors>"),
+    }
+}
+
+fn clear(_args: &mut SplitWhitespace) {
+    kprint!("\x1b[2J\x1b[H");
+}
+
+fn irq(_args: &mut SplitWhitespace) {
+    for &vector in InterruptVector::ALL.iter() {
+        kprintln!("{:#04x} {:>10}  {:?}", vector as u8, interrupts::interrupt_count(vector), vector);
+    }
+}
+
+fn usb(_args: &mut SplitWhitespace) {
+    xhc::for_each_device(|info| {
+        let kind = match (info.has_class_driver, info.interface_class, info.interface_sub_class, info.interface_protocol) {
+            (false, ..) => "unsupported",
+            (true, 3, 1, 1) => "keyboard",
+            (true, 3, 1, 2) => "mouse",
+            (true, 3, ..) => "HID",
+            _ => "",
+        };
+        kprintln!(
+            "slot {}: {:04x}:{:04x} class {:02x}/{:02x}/{:02x} {}{}",
+            info.slot_id, info.vendor_id, info.product_id,
+            info.interface_class, info.interface_sub_class, info.interface_protocol,
+            kind, if info.initialized { "" } else { " (initializing)" },
+        );
+    });
+}
+
+fn reboot(_args: &mut SplitWhitespace) {
+    power::reboot();
+}
+
+fn poweroff(_args: &mut SplitWhitespace) {
+    power::shutdown();
+}
@@ -74,4 +74,143 @@ impl<T> DerefMut for SpinMutexGuard<'_, T> {
     }
 }
 
-pub struct SpinMutexErr<'a>(&'a str);
\ No newline at end of file
+pub struct SpinMutexErr<'a>(&'a str);
+
+// ------------------------------------------------------
+// IrqSpinMutex
+// ------------------------------------------------------
+// 割り込みハンドラからも lock されるもの (CONSOLE, serial の RX_BUFFER など) 用.
+// lock している間は割り込みを止め, unlock で lock する前の RFLAGS.IF に戻す.
+// guard は lock した順と逆に drop すること.
+// 割り込みが止まっているので, 同じ CPU で待ち続けるのは二重に lock したときだけになる.
+// debug build では lock した CPU と場所を覚えておき, 二重に lock したら panic する
+#[cfg(debug_assertions)]
+use core::panic::Location;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicPtr, AtomicU32};
+use x86_64::instructions::interrupts;
+
+pub struct IrqSpinMutex<T> {
+    lock: AtomicBool,
+    #[cfg(debug_assertions)]
+    owner: Owner,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Send for IrqSpinMutex<T> {}
+unsafe impl<T> Sync for IrqSpinMutex<T> {}
+
+impl<T> IrqSpinMutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            lock: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            owner: Owner::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Result<IrqSpinMutexGuard<'_, T>, SpinMutexErr<'_>> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        if !self.lock.swap(true, Ordering::Acquire) {
+            #[cfg(debug_assertions)]
+            self.owner.set(Location::caller());
+            Ok(IrqSpinMutexGuard { mutex: self, interrupts_enabled })
+        } else {
+            if interrupts_enabled {
+                interrupts::enable();
+            }
+            Err(SpinMutexErr("lock error"))
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> IrqSpinMutexGuard<'_, T> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        if self.lock.swap(true, Ordering::Acquire) {
+            #[cfg(debug_assertions)]
+            self.owner.check_reentrant();
+            while self.lock.swap(true, Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+        }
+        #[cfg(debug_assertions)]
+        self.owner.set(Location::caller());
+        IrqSpinMutexGuard { mutex: self, interrupts_enabled }
+    }
+}
+
+// lock している CPU (local APIC ID) と, lock した場所
+#[cfg(debug_assertions)]
+struct Owner {
+    cpu: AtomicU32,
+    location: AtomicPtr<Location<'static>>,
+}
+
+#[cfg(debug_assertions)]
+impl Owner {
+    const NONE: u32 = u32::MAX;
+
+    const fn new() -> Self {
+        Self {
+            cpu: AtomicU32::new(Self::NONE),
+            location: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    fn set(&self, location: &'static Location<'static>) {
+        self.location.store(location as *const Location as *mut Location, Ordering::Relaxed);
+        self.cpu.store(crate::apic::local_apic_id() as u32, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.cpu.store(Self::NONE, Ordering::Relaxed);
+    }
+
+    // 他の CPU が書いた値は古いかもしれないが, 自分の CPU の値は unlock で消しているので誤検出しない
+    #[track_caller]
+    fn check_reentrant(&self) {
+        let cpu = crate::apic::local_apic_id() as u32;
+        if self.cpu.load(Ordering::Relaxed) != cpu {
+            return;
+        }
+        let location = self.location.load(Ordering::Relaxed);
+        match unsafe { location.as_ref() } {
+            Some(held_at) => panic!("IrqSpinMutex: locked again on cpu {} at {} (held since {})", cpu, Location::caller(), held_at),
+            None => panic!("IrqSpinMutex: locked again on cpu {} at {}", cpu, Location::caller()),
+        }
+    }
+}
+
+pub struct IrqSpinMutexGuard<'a, T> {
+    mutex: &'a IrqSpinMutex<T>,
+    // lock する前に割り込みが有効だったか
+    interrupts_enabled: bool,
+}
+
+impl<T> Drop for IrqSpinMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.mutex.owner.clear();
+        self.mutex.lock.store(false, Ordering::Release);
+        if self.interrupts_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+impl<T> Deref for IrqSpinMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for IrqSpinMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
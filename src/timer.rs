@@ -0,0 +1,80 @@
+//! Local APIC timer
+//! 周期モードで毎秒 TIMER_FREQUENCY 回割り込みを起こし, tick を数える.
+//! local APIC timer の速さは機種によって違うので, 起動時に ACPI の PM timer で測る.
+
+use crate::acpi::ACPI;
+use crate::interrupts::idt::InterruptVector;
+use crate::io::PortReadOnly;
+use crate::utils::bit_field::BitField;
+use crate::{info, warn};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const LVT_TIMER: *mut u32 = 0xfee00320 as *mut u32;
+const INITIAL_COUNT: *mut u32 = 0xfee00380 as *mut u32;
+const CURRENT_COUNT: *const u32 = 0xfee00390 as *const u32;
+const DIVIDE_CONFIG: *mut u32 = 0xfee003e0 as *mut u32;
+
+// divide configuration: 1 分周
+const DIVIDE_BY_1: u32 = 0b1011;
+const LVT_MASKED: usize = 16;
+const LVT_PERIODIC: usize = 17;
+
+pub const TIMER_FREQUENCY: u64 = 100;
+
+const PM_TIMER_FREQUENCY: u64 = 3_579_545;
+// 測る時間 (ms)
+const CALIBRATION_MS: u64 = 50;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+// init_timer からの tick 数. timer が動いていなければ 0 のまま
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+// 割り込みハンドラから呼ばれる
+pub fn handle_interrupt() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+// init_acpi と init_idt の後に呼ぶ. PM timer がなければ timer は止めたままにする
+pub fn init_timer() {
+    let pm_timer = ACPI.lock().as_ref().and_then(|acpi| acpi.fadt().pm_timer());
+    let (port, extended) = match pm_timer {
+        Some(pm_timer) => pm_timer,
+        None => {
+            warn!("timer: PM timer not found");
+            return;
+        },
+    };
+
+    // 1 秒あたりの count を測る
+    unsafe {
+        DIVIDE_CONFIG.write_volatile(DIVIDE_BY_1);
+        LVT_TIMER.write_volatile(*0_u32.set_bit(LVT_MASKED, true));
+        INITIAL_COUNT.write_volatile(u32::MAX);
+    }
+    wait_pm_timer(port, extended, CALIBRATION_MS);
+    let elapsed = u32::MAX - unsafe { CURRENT_COUNT.read_volatile() };
+    let count_per_second = elapsed as u64 * 1000 / CALIBRATION_MS;
+    info!("timer: local APIC timer {} Hz", count_per_second);
+
+    let lvt = *0_u32
+        .set_bits(0..8, InterruptVector::LocalApicTimer as u32)
+        .set_bit(LVT_PERIODIC, true);
+    unsafe {
+        LVT_TIMER.write_volatile(lvt);
+        INITIAL_COUNT.write_volatile((count_per_second / TIMER_FREQUENCY).max(1) as u32);
+    }
+}
+
+// PM timer で ms ミリ秒待つ. 24 bit の timer は 4 秒ほどで一周するので長くは待てない
+fn wait_pm_timer(port: u16, extended: bool, ms: u64) {
+    let mut timer = PortReadOnly::<u32>::new(port);
+    let mask: u64 = if extended { 0xffff_ffff } else { 0xff_ffff };
+    let start = timer.read() as u64;
+    let count = PM_TIMER_FREQUENCY * ms / 1000;
+    while (timer.read() as u64).wrapping_sub(start) & mask < count {
+        core::hint::spin_loop();
+    }
+}
@@ -36,6 +36,11 @@ impl<'vec_lifetime, T, const CAPACITY: usize> FixedVec<'vec_lifetime, T, CAPACIT
         unsafe { core::slice::from_raw_parts(p, self.len) }
     }
 
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        let p = self.data.as_mut_ptr() as *mut T;
+        unsafe { core::slice::from_raw_parts_mut(p, self.len) }
+    }
+
     pub fn push(&mut self, val: T) {
         unsafe {
             self.try_push(val).unwrap();
@@ -1,3 +1,5 @@
 pub mod bit_field;
 pub mod fixed_vec;
-pub mod init_once;
\ No newline at end of file
+pub mod init_once;
+pub mod ring_buffer;
+pub mod crc32;
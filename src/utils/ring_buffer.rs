@@ -0,0 +1,111 @@
+use core::mem::MaybeUninit;
+
+#[derive(Debug)]
+pub enum RingBufferError {
+    Full,
+    Empty,
+}
+type Result<T> = core::result::Result<T, RingBufferError>;
+
+pub struct RingBuffer<T: Copy, const CAPACITY: usize> {
+    data: [MaybeUninit<T>; CAPACITY],
+    head: usize, // index of the oldest element
+    len: usize,
+}
+
+impl<T: Copy, const CAPACITY: usize> RingBuffer<T, CAPACITY> {
+
+    pub const fn new() -> Self {
+        Self {
+            data: unsafe { MaybeUninit::uninit().assume_init() },
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == CAPACITY
+    }
+
+    pub fn capacity(&self) -> usize {
+        CAPACITY
+    }
+
+    pub fn try_push(&mut self, val: T) -> Result<()> {
+        if self.is_full() {
+            return Err(RingBufferError::Full);
+        }
+        let tail = (self.head + self.len) % CAPACITY;
+        self.data[tail].write(val);
+        self.len += 1;
+        Ok(())
+    }
+
+    // 満杯なら最も古い要素を捨てて追加する
+    pub fn push_overwrite(&mut self, val: T) {
+        if self.is_full() {
+            self.head = (self.head + 1) % CAPACITY;
+            self.len -= 1;
+        }
+        // never fails: there is at least one free slot
+        let _ = self.try_push(val);
+    }
+
+    pub fn try_pop(&mut self) -> Result<T> {
+        if self.is_empty() {
+            return Err(RingBufferError::Empty);
+        }
+        let val = unsafe { self.data[self.head].assume_init() };
+        self.head = (self.head + 1) % CAPACITY;
+        self.len -= 1;
+        Ok(val)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.try_pop().ok()
+    }
+
+    // idx == 0 が最も古い要素
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        if idx < self.len {
+            let idx = (self.head + idx) % CAPACITY;
+            Some(unsafe { self.data[idx].assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> RingBufferIter<'_, T, CAPACITY> {
+        RingBufferIter {
+            ring_buffer: self,
+            idx: 0,
+        }
+    }
+}
+
+pub struct RingBufferIter<'a, T: Copy, const CAPACITY: usize> {
+    ring_buffer: &'a RingBuffer<T, CAPACITY>,
+    idx: usize,
+}
+
+impl<'a, T: Copy, const CAPACITY: usize> Iterator for RingBufferIter<'a, T, CAPACITY> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let val = self.ring_buffer.get(self.idx);
+        self.idx += 1;
+        val
+    }
+}
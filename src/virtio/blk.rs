@@ -0,0 +1,205 @@
+//! virtio-blk (5.2 Block Device)
+
+use super::queue::{Buffer, VirtQueue, VirtQueueMemory};
+use super::{VirtioError, VirtioPciDevice, NO_MSIX_VECTOR, VIRTIO_VENDOR_ID};
+use crate::block::{self, BlockDevice, BlockError};
+use crate::interrupts::idt::InterruptVector;
+use crate::pci::{self, Device};
+use crate::sync::SpinMutex;
+use crate::{apic, info};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use x86_64::instructions::interrupts;
+
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+// struct virtio_blk_req の先頭部分
+#[repr(C)]
+struct RequestHeader {
+    request_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+static mut QUEUE_MEMORY: VirtQueueMemory = VirtQueueMemory::new();
+static INTERRUPT_COUNT: AtomicUsize = AtomicUsize::new(0);
+// 割り込みハンドラが立て, request が完了を待つときに下ろす
+static COMPLETED: AtomicBool = AtomicBool::new(false);
+
+pub struct VirtioBlk {
+    transport: VirtioPciDevice,
+    queue: VirtQueue,
+    capacity: u64,
+    read_only: bool,
+    // MSI-X で完了の割り込みが届くか
+    interrupt_driven: bool,
+}
+
+impl VirtioBlk {
+    // 1 つしかない QUEUE_MEMORY を使うので, 呼び出しは一度だけにすること
+    pub fn new(device: &'static Device) -> Result<Self, VirtioError> {
+        device.enable_bus_master();
+        let mut transport = VirtioPciDevice::new(device)?;
+        let features = transport.init(VIRTIO_BLK_F_RO)?;
+
+        let mut queue = VirtQueue::new(unsafe { &mut QUEUE_MEMORY });
+        // MSI-X が使えなければ割り込みなし (ポーリングのみ) で動かす
+        let msix_vector = match device.configure_msix_fixed_destination(
+            apic::local_apic_id(),
+            pci::MSITriggerMode::Edge,
+            pci::MSIDeliveryMode::Fixed,
+            InterruptVector::VirtioBlk,
+            0,
+        ) {
+            Ok(()) => 0,
+            Err(()) => NO_MSIX_VECTOR,
+        };
+        transport.setup_queue(0, &mut queue, msix_vector)?;
+        transport.driver_ok();
+
+        // capacity (offset 0) は 512 byte セクタ単位の le64. 32 bit ずつ読むので generation を確かめる
+        let capacity = transport.read_device_config_consistent(|transport| {
+            transport.read_device_config::<u32>(0) as u64 | (transport.read_device_config::<u32>(4) as u64) << 32
+        });
+
+        Ok(Self {
+            transport,
+            queue,
+            capacity,
+            read_only: features & VIRTIO_BLK_F_RO != 0,
+            interrupt_driven: msix_vector != NO_MSIX_VECTOR,
+        })
+    }
+
+    pub fn pci_device(&self) -> &'static Device {
+        self.transport.pci_device()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // header, data, status の 3 つの descriptor からなるリクエストを投げ, 完了まで待つ
+    fn request(&mut self, request_type: u32, lba: u64, data: u64, len: usize) -> block::Result<()> {
+        let header = RequestHeader {
+            request_type,
+            reserved: 0,
+            sector: lba,
+        };
+        let mut status: u8 = 0xff;
+        let buffers = [
+            Buffer {
+                addr: &header as *const RequestHeader as u64,
+                len: core::mem::size_of::<RequestHeader>() as u32,
+                device_writable: false,
+            },
+            Buffer {
+                addr: data,
+                len: len as u32,
+                device_writable: request_type == VIRTIO_BLK_T_IN,
+            },
+            Buffer {
+                addr: &mut status as *mut u8 as u64,
+                len: 1,
+                device_writable: true,
+            },
+        ];
+        COMPLETED.store(false, Ordering::SeqCst);
+        if self.queue.add(&buffers).is_none() {
+            return Err(BlockError::DeviceError);
+        }
+        self.queue.notify();
+
+        // 同時に 1 リクエストしか出さないので, 返ってくるのは今出したもの
+        while self.queue.pop_used().is_none() {
+            self.wait_completion();
+        }
+
+        match unsafe { core::ptr::read_volatile(&status) } {
+            VIRTIO_BLK_S_OK => Ok(()),
+            _ => Err(BlockError::DeviceError),
+        }
+    }
+
+    // 完了の割り込みまで hlt で待つ. 割り込みが使えない (MSI-X がない, 割り込み禁止中に呼ばれた) ときはポーリングする
+    fn wait_completion(&self) {
+        if !self.interrupt_driven || !interrupts::are_enabled() {
+            core::hint::spin_loop();
+            return;
+        }
+        // 確かめてから hlt するまでの間に割り込みが来ると起きられないので, 割り込みを止めて確かめる
+        interrupts::disable();
+        if COMPLETED.swap(false, Ordering::SeqCst) || self.queue.has_used() {
+            interrupts::enable();
+        } else {
+            interrupts::enable_and_hlt();
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn sector_count(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> block::Result<()> {
+        self.check_request(lba, buf.len())?;
+        self.request(VIRTIO_BLK_T_IN, lba, buf.as_mut_ptr() as u64, buf.len())
+    }
+
+    fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> block::Result<()> {
+        if self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+        self.check_request(lba, buf.len())?;
+        self.request(VIRTIO_BLK_T_OUT, lba, buf.as_ptr() as u64, buf.len())
+    }
+}
+
+pub static VIRTIO_BLK: SpinMutex<Option<VirtioBlk>> = SpinMutex::new(None);
+
+pub fn init_virtio_blk() {
+    let device = pci::devices().iter().find(|device| {
+        let config = device.as_config();
+        // 0x1001: transitional, 0x1042: modern (0x1040 + device type 2)
+        config.read_vendor_id() == VIRTIO_VENDOR_ID
+            && matches!(config.read_device_id(), 0x1001 | 0x1042)
+    });
+    let device = match device {
+        Some(device) => device,
+        None => return,
+    };
+
+    match VirtioBlk::new(device) {
+        Ok(blk) => {
+            info!("virtio-blk: {} sectors{}", blk.sector_count(), if blk.is_read_only() { " (read only)" } else { "" });
+            *VIRTIO_BLK.lock() = Some(blk);
+        },
+        Err(e) => {
+            info!("virtio-blk: initialization failed: {:?}", e);
+        },
+    }
+}
+
+// logger の DiskSink 用. 使用中 (この driver が log を出している間など) なら待たずに None を返す
+pub fn try_write_sectors(lba: u64, buf: &[u8]) -> Option<block::Result<()>> {
+    let mut blk = VIRTIO_BLK.try_lock().ok()?;
+    match blk.as_mut() {
+        Some(blk) => Some(blk.write_sectors(lba, buf)),
+        None => Some(Err(BlockError::DeviceError)),
+    }
+}
+
+// 割り込みハンドラから呼ばれる. hlt で待っている request を起こす
+pub fn handle_interrupt() {
+    INTERRUPT_COUNT.fetch_add(1, Ordering::Relaxed);
+    COMPLETED.store(true, Ordering::SeqCst);
+}
+
+pub fn interrupt_count() -> usize {
+    INTERRUPT_COUNT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,258 @@
+//! virtio over PCI (modern interface, virtio 1.1)
+//! 参考: https://docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.html (4.1 Virtio Over PCI Bus)
+
+pub mod queue;
+pub mod blk;
+
+use crate::pci::Device;
+use crate::utils::bit_field::BitField;
+use queue::VirtQueue;
+
+pub const VIRTIO_VENDOR_ID: u16 = 0x1af4;
+const CAPABILITY_ID_VENDOR_SPECIFIC: u8 = 0x09;
+
+// virtio_pci_cap.cfg_type
+const VIRTIO_PCI_CAP_COMMON_CFG: u8 = 1;
+const VIRTIO_PCI_CAP_NOTIFY_CFG: u8 = 2;
+const VIRTIO_PCI_CAP_ISR_CFG: u8 = 3;
+const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
+
+// device status
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 0x80;
+
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// MSI-X を使わない場合の vector 番号
+pub const NO_MSIX_VECTOR: u16 = 0xffff;
+
+#[derive(Debug)]
+pub enum VirtioError {
+    // modern interface の capability が見つからない (legacy only device)
+    CapabilityNotFound,
+    FeaturesNotAccepted,
+    QueueNotAvailable,
+    MsixVectorNotAccepted,
+}
+pub type Result<T> = core::result::Result<T, VirtioError>;
+
+// struct virtio_pci_common_cfg
+#[repr(C)]
+struct CommonConfig {
+    device_feature_select: u32,
+    device_feature: u32,
+    driver_feature_select: u32,
+    driver_feature: u32,
+    msix_config: u16,
+    num_queues: u16,
+    device_status: u8,
+    config_generation: u8,
+    queue_select: u16,
+    queue_size: u16,
+    queue_msix_vector: u16,
+    queue_enable: u16,
+    queue_notify_off: u16,
+    queue_desc_lo: u32,
+    queue_desc_hi: u32,
+    queue_driver_lo: u32,
+    queue_driver_hi: u32,
+    queue_device_lo: u32,
+    queue_device_hi: u32,
+}
+
+macro_rules! read_common {
+    ($self:ident.$field:ident) => {
+        unsafe { core::ptr::addr_of!((*$self.common).$field).read_volatile() }
+    };
+}
+
+macro_rules! write_common {
+    ($self:ident.$field:ident, $val:expr) => {
+        unsafe { core::ptr::addr_of_mut!((*$self.common).$field).write_volatile($val) }
+    };
+}
+
+pub struct VirtioPciDevice {
+    device: &'static Device,
+    common: *mut CommonConfig,
+    notify_base: u64,
+    notify_off_multiplier: u32,
+    isr: *mut u8,
+    device_config: u64,
+}
+
+impl VirtioPciDevice {
+    pub fn new(device: &'static Device) -> Result<Self> {
+        let (mut common, mut notify, mut isr, mut device_config) = (None, None, None, None);
+        let mut notify_off_multiplier = 0;
+
+        for (cap_addr, cap_id) in device.capabilities() {
+            if cap_id != CAPABILITY_ID_VENDOR_SPECIFIC {
+                continue;
+            }
+            // struct virtio_pci_cap: cfg_type (byte 3), bar (byte 4), offset (byte 8..12)
+            let cfg_type = device.read_register(cap_addr).get_bits(24..32) as u8;
+            let bar_idx = device.read_register(cap_addr + 4).get_bits(0..8) as u8;
+            let offset = device.read_register(cap_addr + 8) as u64;
+            let bar = match device.read_bar(bar_idx) {
+                // I/O space の BAR は使わない
+                Some(bar) if bar & 0b1 == 0 => bar & !0x0f,
+                _ => continue,
+            };
+            let addr = bar + offset;
+            // 同じ種類の capability が複数ある場合は最初のものを使う
+            match cfg_type {
+                VIRTIO_PCI_CAP_COMMON_CFG => { common.get_or_insert(addr); },
+                VIRTIO_PCI_CAP_NOTIFY_CFG => {
+                    if notify.is_none() {
+                        notify = Some(addr);
+                        notify_off_multiplier = device.read_register(cap_addr + 16);
+                    }
+                },
+                VIRTIO_PCI_CAP_ISR_CFG => { isr.get_or_insert(addr); },
+                VIRTIO_PCI_CAP_DEVICE_CFG => { device_config.get_or_insert(addr); },
+                _ => {},
+            }
+        }
+
+        match (common, notify, isr, device_config) {
+            (Some(common), Some(notify), Some(isr), Some(device_config)) => Ok(Self {
+                device,
+                common: common as *mut CommonConfig,
+                notify_base: notify,
+                notify_off_multiplier,
+                isr: isr as *mut u8,
+                device_config,
+            }),
+            _ => Err(VirtioError::CapabilityNotFound),
+        }
+    }
+
+    pub fn pci_device(&self) -> &'static Device {
+        self.device
+    }
+
+    pub fn status(&self) -> u8 {
+        read_common!(self.device_status)
+    }
+
+    fn add_status(&mut self, status: u8) {
+        let status = self.status() | status;
+        write_common!(self.device_status, status);
+    }
+
+    pub fn reset(&mut self) {
+        write_common!(self.device_status, 0);
+        // 0 が読めるまでリセットは完了していない
+        while self.status() != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn device_features(&mut self) -> u64 {
+        write_common!(self.device_feature_select, 0);
+        let low = read_common!(self.device_feature) as u64;
+        write_common!(self.device_feature_select, 1);
+        let high = read_common!(self.device_feature) as u64;
+        high << 32 | low
+    }
+
+    fn set_driver_features(&mut self, features: u64) {
+        write_common!(self.driver_feature_select, 0);
+        write_common!(self.driver_feature, features as u32);
+        write_common!(self.driver_feature_select, 1);
+        write_common!(self.driver_feature, (features >> 32) as u32);
+    }
+
+    // 3.1.1 Driver Requirements: Device Initialization の 1 ~ 6 を行う.
+    // wanted のうちデバイスが対応しているものを有効にし, 有効にした feature bits を返す
+    pub fn init(&mut self, wanted: u64) -> Result<u64> {
+        self.reset();
+        self.add_status(STATUS_ACKNOWLEDGE);
+        self.add_status(STATUS_DRIVER);
+
+        let features = self.device_features() & (wanted | VIRTIO_F_VERSION_1);
+        if features & VIRTIO_F_VERSION_1 == 0 {
+            self.add_status(STATUS_FAILED);
+            return Err(VirtioError::FeaturesNotAccepted);
+        }
+        self.set_driver_features(features);
+        self.add_status(STATUS_FEATURES_OK);
+        if self.status() & STATUS_FEATURES_OK == 0 {
+            self.add_status(STATUS_FAILED);
+            return Err(VirtioError::FeaturesNotAccepted);
+        }
+        Ok(features)
+    }
+
+    pub fn num_queues(&self) -> u16 {
+        read_common!(self.num_queues)
+    }
+
+    // queue を登録し, 有効にする. msix_vector は MSI-X テーブルのインデックス (NO_MSIX_VECTOR なら割り込みなし)
+    pub fn setup_queue(&mut self, index: u16, queue: &mut VirtQueue, msix_vector: u16) -> Result<()> {
+        write_common!(self.queue_select, index);
+        let max_size = read_common!(self.queue_size);
+        if max_size == 0 || read_common!(self.queue_enable) != 0 {
+            return Err(VirtioError::QueueNotAvailable);
+        }
+        let size = queue.size().min(max_size);
+        queue.set_size(size);
+        write_common!(self.queue_size, size);
+
+        if msix_vector != NO_MSIX_VECTOR {
+            write_common!(self.queue_msix_vector, msix_vector);
+            if read_common!(self.queue_msix_vector) != msix_vector {
+                return Err(VirtioError::MsixVectorNotAccepted);
+            }
+        }
+
+        let (desc, driver, device) = queue.addresses();
+        write_common!(self.queue_desc_lo, desc as u32);
+        write_common!(self.queue_desc_hi, (desc >> 32) as u32);
+        write_common!(self.queue_driver_lo, driver as u32);
+        write_common!(self.queue_driver_hi, (driver >> 32) as u32);
+        write_common!(self.queue_device_lo, device as u32);
+        write_common!(self.queue_device_hi, (device >> 32) as u32);
+
+        let notify_off = read_common!(self.queue_notify_off) as u64;
+        let notify_addr = self.notify_base + notify_off * self.notify_off_multiplier as u64;
+        queue.set_notify_address(notify_addr as *mut u16, index);
+
+        write_common!(self.queue_enable, 1);
+        Ok(())
+    }
+
+    pub fn driver_ok(&mut self) {
+        self.add_status(STATUS_DRIVER_OK);
+    }
+
+    // 読むとクリアされる. bit 0: queue interrupt, bit 1: configuration change
+    pub fn read_isr_status(&self) -> u8 {
+        unsafe { self.isr.read_volatile() }
+    }
+
+    // device specific configuration が変わるたびに変わる
+    pub fn config_generation(&self) -> u8 {
+        read_common!(self.config_generation)
+    }
+
+    // device specific configuration を offset から読む
+    pub fn read_device_config<T: Copy>(&self, offset: usize) -> T {
+        unsafe { ((self.device_config as usize + offset) as *const T).read_volatile() }
+    }
+
+    // 複数回に分けて読む値が途中で変わらないように, config_generation が変わらなくなるまで read を繰り返す (4.1.4.3.1)
+    pub fn read_device_config_consistent<T, F: Fn(&Self) -> T>(&self, read: F) -> T {
+        loop {
+            let generation = self.config_generation();
+            let value = read(self);
+            if self.config_generation() == generation {
+                return value;
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! split virtqueue (2.6 Split Virtqueues)
+
+use core::sync::atomic::{fence, Ordering};
+
+pub const QUEUE_SIZE: usize = 16;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+impl Descriptor {
+    const EMPTY: Self = Self { addr: 0, len: 0, flags: 0, next: 0 };
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C, align(4))]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+// デバイスと共有するメモリ. (identity mapping なので) アドレスをそのままデバイスに渡す
+#[repr(C, align(4096))]
+pub struct VirtQueueMemory {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    used: UsedRing,
+}
+
+impl VirtQueueMemory {
+    pub const fn new() -> Self {
+        Self {
+            desc: [Descriptor::EMPTY; QUEUE_SIZE],
+            avail: AvailRing { flags: 0, idx: 0, ring: [0; QUEUE_SIZE], used_event: 0 },
+            used: UsedRing {
+                flags: 0,
+                idx: 0,
+                ring: [UsedElem { id: 0, len: 0 }; QUEUE_SIZE],
+                avail_event: 0,
+            },
+        }
+    }
+}
+
+// デバイスに渡すバッファ. device_writable ならデバイスが書き込む (読み出し用)
+#[derive(Clone, Copy)]
+pub struct Buffer {
+    pub addr: u64,
+    pub len: u32,
+    pub device_writable: bool,
+}
+
+pub struct VirtQueue {
+    mem: &'static mut VirtQueueMemory,
+    size: u16,
+    free_head: u16,
+    num_free: u16,
+    last_used_idx: u16,
+    notify_addr: *mut u16,
+    index: u16,
+}
+
+impl VirtQueue {
+    pub fn new(mem: &'static mut VirtQueueMemory) -> Self {
+        let mut queue = Self {
+            mem,
+            size: QUEUE_SIZE as u16,
+            free_head: 0,
+            num_free: 0,
+            last_used_idx: 0,
+            notify_addr: core::ptr::null_mut(),
+            index: 0,
+        };
+        queue.set_size(QUEUE_SIZE as u16);
+        queue
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    // デバイスが対応する大きさに合わせて縮める. descriptor の free list を作り直す
+    pub(super) fn set_size(&mut self, size: u16) {
+        let size = size.min(QUEUE_SIZE as u16);
+        *self.mem = VirtQueueMemory::new();
+        for i in 0..size {
+            self.mem.desc[i as usize].next = i + 1;
+        }
+        self.size = size;
+        self.free_head = 0;
+        self.num_free = size;
+        self.last_used_idx = 0;
+    }
+
+    // (descriptor table, available ring, used ring) のアドレス
+    pub(super) fn addresses(&self) -> (u64, u64, u64) {
+        (
+            self.mem.desc.as_ptr() as u64,
+            &self.mem.avail as *const AvailRing as u64,
+            &self.mem.used as *const UsedRing as u64,
+        )
+    }
+
+    pub(super) fn set_notify_address(&mut self, addr: *mut u16, index: u16) {
+        self.notify_addr = addr;
+        self.index = index;
+    }
+
+    // buffers を descriptor chain として available ring に積む. 空きがなければ None
+    pub fn add(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.num_free as usize {
+            return None;
+        }
+
+        let head = self.free_head;
+        let mut idx = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let desc = &mut self.mem.desc[idx as usize];
+            desc.addr = buffer.addr;
+            desc.len = buffer.len;
+            desc.flags = if buffer.device_writable { VIRTQ_DESC_F_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                desc.flags |= VIRTQ_DESC_F_NEXT;
+            }
+            let next = desc.next;
+            if i + 1 < buffers.len() {
+                idx = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+        self.num_free -= buffers.len() as u16;
+
+        let avail_idx = unsafe { core::ptr::addr_of!(self.mem.avail.idx).read_volatile() };
+        self.mem.avail.ring[(avail_idx % self.size) as usize] = head;
+        // descriptor と ring の書き込みが idx の更新より先に見えるようにする
+        fence(Ordering::SeqCst);
+        unsafe { core::ptr::addr_of_mut!(self.mem.avail.idx).write_volatile(avail_idx.wrapping_add(1)) };
+        fence(Ordering::SeqCst);
+        Some(head)
+    }
+
+    pub fn notify(&self) {
+        if !self.notify_addr.is_null() {
+            unsafe { self.notify_addr.write_volatile(self.index) };
+        }
+    }
+
+    pub fn has_used(&self) -> bool {
+        let used_idx = unsafe { core::ptr::addr_of!(self.mem.used.idx).read_volatile() };
+        used_idx != self.last_used_idx
+    }
+
+    // デバイスが処理を終えた descriptor chain を回収する. (chain の先頭, 書き込まれた長さ)
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.has_used() {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let elem = unsafe {
+            core::ptr::addr_of!(self.mem.used.ring[(self.last_used_idx % self.size) as usize]).read_volatile()
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        // chain を free list に戻す
+        let head = elem.id as u16;
+        let mut idx = head;
+        loop {
+            self.num_free += 1;
+            let desc = &self.mem.desc[idx as usize];
+            if desc.flags & VIRTQ_DESC_F_NEXT == 0 {
+                break;
+            }
+            idx = desc.next;
+        }
+        self.mem.desc[idx as usize].next = self.free_head;
+        self.free_head = head;
+
+        Some((head, elem.len))
+    }
+}
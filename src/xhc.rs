@@ -1,11 +1,11 @@
 
 use crate::sync::SpinMutex;
 use crate::pci::{self, Device};
-use crate::{trace, interrupts};
-use crate::utils::bit_field::BitField;
+use crate::{trace, error, interrupts};
 use mikanos_usb as usb;
 
 
+// 割り込みハンドラからは lock しない (process_events は main loop で呼ぶ) ので SpinMutex でよい
 pub static XHC_CONTROLLER: SpinMutex<Option<&'static mut usb::xhci::Controller>> 
     = SpinMutex::new(None); // MaybeUninit, Option, 
 
@@ -14,8 +14,7 @@ pub fn init_xhc() {
     if let Some(device) = xhc_dev {
 
         // msi の設定
-        const LOCAL_APIC_ID_REGISTER: *const u32 = 0xfee00020 as *const u32;
-        let bsp_local_apic_id = unsafe { *LOCAL_APIC_ID_REGISTER }.get_bits(24..32) as u8;
+        let bsp_local_apic_id = crate::apic::local_apic_id();
         let is_err = device.configure_msi_fixed_destination(
             bsp_local_apic_id, 
             pci::MSITriggerMode::Level, 
@@ -42,7 +41,10 @@ pub fn init_xhc() {
         controller.run().unwrap();
 
         use crate::mouse::mouse_observer;
+        use crate::keyboard::keyboard_observer;
         usb::HidMouseDriver::set_default_observer(mouse_observer);
+        usb::HidKeyboardDriver::set_default_observer(keyboard_observer);
+        usb::HidGenericDriver::set_default_observers(crate::hid::descriptor_observer, crate::hid::report_observer);
         controller.configure_connected_ports();
 
 
@@ -51,6 +53,35 @@ pub fn init_xhc() {
 
 }
 
+// main loop から呼ぶ. 溜まっている event を処理する (mouse や keyboard の observer もここから呼ばれる)
+pub fn process_events() {
+    let mut controller = XHC_CONTROLLER.lock();
+    let controller = match controller.as_mut() {
+        Some(controller) => controller,
+        None => return,
+    };
+    while controller.has_event() {
+        if let Err(e) = controller.process_event() {
+            error!("xhc: {:?}", e);
+        }
+    }
+}
+
+// 接続されている USB device の情報を slot 順に f に渡す
+pub fn for_each_device<F: FnMut(&usb::xhci::DeviceInfo)>(mut f: F) {
+    let mut controller = XHC_CONTROLLER.lock();
+    let controller = match controller.as_mut() {
+        Some(controller) => controller,
+        None => return,
+    };
+    let max_slots = controller.max_slots().min(u8::MAX as usize) as u8;
+    for slot_id in 1..=max_slots {
+        if let Some(info) = controller.device_info(slot_id) {
+            f(&info);
+        }
+    }
+}
+
 fn find_xhc_device() -> Option<&'static Device> {
     let mut xhc_dev = None;
     for device in pci::devices() {
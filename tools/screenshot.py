@@ -0,0 +1,130 @@
+#!/usr/bin/env python3
+"""potatOS が serial に送った screenshot を組み立てて保存する.
+
+frame の形式は src/graphics/screenshot.rs を参照.
+
+使い方:
+  # 実行中の QEMU (makers run) に要求を送って受け取る
+  tools/screenshot.py localhost:4444 --request ppm -o screenshot.ppm
+  # serial のログ (makers run-headless など) から取り出す
+  tools/screenshot.py target/serial.log -o screenshot.ppm
+"""
+
+import argparse
+import socket
+import struct
+import sys
+import zlib
+
+MAGIC = b"PSHT"
+BEGIN, DATA, END = 0, 1, 2
+HEADER = struct.Struct("<4sBHH")
+REQUEST = {"ppm": b"\x10", "bmp": b"\x02"}
+EXTENSION = {ord("P"): "ppm", ord("B"): "bmp"}
+
+
+class ByteSource:
+    """file か TCP (host:port) から byte を読む"""
+
+    def __init__(self, path):
+        self.sock = None
+        self.file = None
+        host, sep, port = path.rpartition(":")
+        if sep and port.isdigit():
+            self.sock = socket.create_connection((host or "localhost", int(port)))
+        else:
+            self.file = open(path, "rb")
+        self.buf = b""
+
+    def request(self, data):
+        if self.sock is None:
+            sys.exit("--request needs a TCP serial (host:port)")
+        self.sock.sendall(data)
+
+    def read(self, n):
+        """n byte 読む. 終わりに達したら None"""
+        while len(self.buf) < n:
+            chunk = self.sock.recv(65536) if self.sock else self.file.read(65536)
+            if not chunk:
+                return None
+            self.buf += chunk
+        data, self.buf = self.buf[:n], self.buf[n:]
+        return data
+
+    def sync(self):
+        """magic まで読み飛ばす (間に入ったログなど)"""
+        window = b""
+        while True:
+            b = self.read(1)
+            if b is None:
+                return False
+            window = (window + b)[-len(MAGIC):]
+            if window == MAGIC:
+                return True
+
+
+def read_frame(src):
+    """(kind, seq, payload) を返す. checksum が合わない frame は読み飛ばす"""
+    while src.sync():
+        rest = src.read(HEADER.size - len(MAGIC))
+        if rest is None:
+            return None
+        kind, seq, length = struct.unpack("<BHH", rest)
+        payload = src.read(length)
+        crc = src.read(4)
+        if payload is None or crc is None:
+            return None
+        if zlib.crc32(rest + payload) != struct.unpack("<I", crc)[0]:
+            print(f"warning: bad frame checksum (seq {seq}), skipped", file=sys.stderr)
+            continue
+        return kind, seq, payload
+    return None
+
+
+def receive(src):
+    """1 枚分の (format, width, height, data) を返す"""
+    image = None
+    while True:
+        frame = read_frame(src)
+        if frame is None:
+            sys.exit("error: stream ended before the screenshot was complete")
+        kind, seq, payload = frame
+        if kind == BEGIN:
+            fmt, width, height, size = struct.unpack("<BIII", payload)
+            image = {"format": fmt, "width": width, "height": height, "size": size, "data": bytearray(), "seq": 1}
+        elif image is None:
+            continue
+        elif seq != image["seq"] & 0xFFFF:
+            print(f"error: missing frame (expected seq {image['seq'] & 0xFFFF}, got {seq}), waiting for next screenshot", file=sys.stderr)
+            image = None
+        elif kind == DATA:
+            image["data"] += payload
+            image["seq"] += 1
+        elif kind == END:
+            data = bytes(image["data"])
+            if len(data) != image["size"] or zlib.crc32(data) != struct.unpack("<I", payload)[0]:
+                print("error: image checksum mismatch, waiting for next screenshot", file=sys.stderr)
+                image = None
+                continue
+            return image["format"], image["width"], image["height"], data
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument("source", help="serial log file or host:port of a TCP serial")
+    parser.add_argument("-o", "--output", help="output file (default: screenshot.<format>)")
+    parser.add_argument("--request", choices=REQUEST, help="ask the kernel to send a screenshot in this format")
+    args = parser.parse_args()
+
+    src = ByteSource(args.source)
+    if args.request:
+        src.request(REQUEST[args.request])
+    fmt, width, height, data = receive(src)
+    output = args.output or f"screenshot.{EXTENSION.get(fmt, 'bin')}"
+    with open(output, "wb") as f:
+        f.write(data)
+    print(f"saved {width}x{height} screenshot to {output}")
+
+
+if __name__ == "__main__":
+    main()